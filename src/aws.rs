@@ -2,4 +2,6 @@ mod arn;
 mod glob;
 
 pub use arn::{ARN, ARNParseError};
-pub use glob::glob_matches;
+#[cfg(test)]
+pub(crate) use glob::compiled_count;
+pub use glob::{glob_covers, glob_matches, glob_matches_unanchored, glob_parts_match, globs_overlap, CompiledGlob, GlobPart};
@@ -1,9 +1,14 @@
+use super::glob_matches;
+
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ARNParseError {
     InvalidFormat,
     MissingPrefix,
+    // A wildcard where it can only be a mistake, such as in part of a
+    // partition or service name.
+    InvalidWildcard,
 }
 
 #[derive(Clone)]
@@ -32,6 +37,10 @@ impl ARN {
         ARN {value, separators}
     }
 
+    pub fn partition(&self) -> &str {
+        &self.value[self.separators[0] + 1 .. self.separators[1]]
+    }
+
     pub fn service(&self) -> &str {
         &self.value[self.separators[1] + 1 .. self.separators[2]]
     }
@@ -48,9 +57,81 @@ impl ARN {
         &self.value[self.separators[4] + 1 ..]
     }
 
+    // Resources take the form resource-type/resource-id,
+    // resource-type:resource-id, or resource-id. The type ends at the first
+    // '/' or ':', so for S3 objects this is the bucket name.
+    fn resource_separator(&self) -> Option<usize> {
+        self.resource().find(['/', ':'])
+    }
+
+    pub fn resource_type(&self) -> Option<&str> {
+        self.resource_separator().map(|i| &self.resource()[..i])
+    }
+
+    // Everything after the resource type delimiter, including any nested
+    // sub-resource path, or the entire resource if it has no type.
+    pub fn resource_id(&self) -> &str {
+        match self.resource_separator() {
+            Some(i) => &self.resource()[i + 1 ..],
+            None => self.resource(),
+        }
+    }
+
+    // Parsing only requires the "arn:" prefix and five separators, so this
+    // checks the ARN is not degenerate: the partition, service, and resource
+    // must be non-empty. The region and account are empty for some services,
+    // e.g. S3 and IAM.
+    pub fn is_well_formed(&self) -> bool {
+        !self.partition().is_empty() && !self.service().is_empty() && !self.resource().is_empty()
+    }
+
     pub fn raw(&self) -> &str {
         &self.value
     }
+
+    // Matches this ARN against an ARN pattern. As in AWS, each of the
+    // colon-delimited segments is matched separately, so a wildcard cannot
+    // match across a segment boundary. The resource segment is matched as a
+    // whole, including any colons it contains.
+    pub fn matches_pattern(&self, pattern: &ARN) -> bool {
+        glob_matches(pattern.partition(), self.partition())
+            && glob_matches(pattern.service(), self.service())
+            && glob_matches(pattern.region(), self.region())
+            && glob_matches(pattern.account(), self.account())
+            && glob_matches(pattern.resource(), self.resource())
+    }
+
+    // Returns an equivalent pattern with runs of '*' collapsed, after
+    // checking that its wildcards make sense. The partition and service must
+    // be literal or exactly "*", since partial wildcards there almost always
+    // indicate a typo. The remaining segments may use wildcards anywhere.
+    //
+    // Under matches_pattern, a '*' in the resource segment matches across
+    // '/' and ':' within the resource, but never into another segment. So
+    // "arn:aws:s3:::bucket*" matches the bucket itself, its objects, and
+    // other buckets whose names start with "bucket", whereas
+    // "arn:aws:s3:::bucket/*" only matches objects in that bucket.
+    pub fn normalize_pattern(&self) -> Result<ARN, ARNParseError> {
+        let is_valid = |segment: &str| segment == "*" || !segment.contains(['*', '?']);
+        if !is_valid(self.partition()) || !is_valid(self.service()) {
+            return Err(ARNParseError::InvalidWildcard);
+        }
+        let mut value = String::with_capacity(self.value.len());
+        for c in self.value.chars() {
+            if c != '*' || !value.ends_with('*') {
+                value.push(c);
+            }
+        }
+        value.parse()
+    }
+
+    // Whether the two ARNs could refer to the same account. An account of
+    // "*" or an empty account, as used by services like S3 whose ARNs omit
+    // it, is treated as matching any account.
+    pub fn same_account(&self, other: &ARN) -> bool {
+        let is_wildcard = |account: &str| account.is_empty() || account == "*";
+        is_wildcard(self.account()) || is_wildcard(other.account()) || self.account() == other.account()
+    }
 }
 
 impl PartialEq for ARN {
@@ -106,12 +187,13 @@ impl FromStr for ARN {
 
 #[cfg(test)]
 mod test {
-    use super::ARN;
+    use super::{ARN, ARNParseError};
 
     #[test]
     fn parse_fully_specified() {
         let result: ARN = "arn:aws:iam:us-east-1:123456789012:user/Username"
             .parse().expect("The input should have parsed successfully");
+        assert_eq!(result.partition(), "aws");
         assert_eq!(result.service(), "iam");
         assert_eq!(result.region(), "us-east-1");
         assert_eq!(result.account(), "123456789012");
@@ -128,6 +210,18 @@ mod test {
         assert_eq!(result.resource(), "BUCKET-NAME");
     }
 
+    #[test]
+    fn well_formed() {
+        for value in ["arn:aws:s3:::bucket/key", "arn:aws:iam::123456789012:user/Alice", "arn:aws-cn:ec2:cn-north-1:123456789012:instance/*", "arn:*:*:*:*:*"] {
+            let arn: ARN = value.parse().unwrap();
+            assert!(arn.is_well_formed(), "{}", value);
+        }
+        for value in ["arn:::::", "arn::s3:::bucket", "arn:aws::::bucket", "arn:aws:s3:::", "arn:aws:iam::123456789012:"] {
+            let arn: ARN = value.parse().unwrap();
+            assert!(!arn.is_well_formed(), "{}", value);
+        }
+    }
+
     #[test]
     fn parse_with_globs() {
         let result: ARN = "arn:aws:iam:*:123456789012:user/Username"
@@ -147,4 +241,98 @@ mod test {
         assert!(result.account().is_empty());
         assert_eq!(result.resource(), "BUCKET-NAME/home/${aws:username}");
     }
+
+    #[test]
+    fn resource_type_and_id() {
+        let result: ARN = "arn:aws:ec2:us-east-1:123456789012:subnet/subnet-abc".parse().unwrap();
+        assert_eq!(result.resource_type(), Some("subnet"));
+        assert_eq!(result.resource_id(), "subnet-abc");
+
+        let result: ARN = "arn:aws:s3:::bucket/path/to/object".parse().unwrap();
+        assert_eq!(result.resource_type(), Some("bucket"));
+        assert_eq!(result.resource_id(), "path/to/object");
+
+        let result: ARN = "arn:aws:lambda:us-east-1:123456789012:function:my-function:1".parse().unwrap();
+        assert_eq!(result.resource_type(), Some("function"));
+        assert_eq!(result.resource_id(), "my-function:1");
+
+        let result: ARN = "arn:aws:s3:::bucket".parse().unwrap();
+        assert_eq!(result.resource_type(), None);
+        assert_eq!(result.resource_id(), "bucket");
+    }
+
+    #[test]
+    fn matches_pattern_by_segment() {
+        let arn: ARN = "arn:aws:iam::123456789012:user/Alice".parse().unwrap();
+        let cases = [
+            ("arn:aws:iam::123456789012:user/Alice", true),
+            ("arn:*:iam::123456789012:user/Alice", true),
+            ("arn:aws*:iam::123456789012:user/Alice", true),
+            ("arn:aws:iam::*:user/*", true),
+            ("arn:aws-cn:iam::123456789012:user/Alice", false),
+            ("arn:aws:iam::123456789012:user/Bob", false),
+        ];
+        for (pattern, expected) in cases {
+            let pattern: ARN = pattern.parse().unwrap();
+            assert_eq!(expected, arn.matches_pattern(&pattern), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn matches_pattern_wildcards_stay_in_segment() {
+        // A flat glob would let the service wildcard consume "iam:x".
+        let arn: ARN = "arn:aws:iam:x:us-east-1:123456789012:thing".parse().unwrap();
+        let pattern: ARN = "arn:aws:*:us-east-1:123456789012:thing".parse().unwrap();
+        assert!(!arn.matches_pattern(&pattern));
+    }
+
+    #[test]
+    fn same_account() {
+        let alice: ARN = "arn:aws:iam::123456789012:user/Alice".parse().unwrap();
+        let role: ARN = "arn:aws:iam::123456789012:role/Admin".parse().unwrap();
+        let other: ARN = "arn:aws:iam::210987654321:user/Bob".parse().unwrap();
+        let any: ARN = "arn:aws:iam::*:role/Admin".parse().unwrap();
+        let bucket: ARN = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert!(alice.same_account(&role));
+        assert!(!alice.same_account(&other));
+        assert!(!other.same_account(&alice));
+        assert!(alice.same_account(&any));
+        assert!(any.same_account(&other));
+        assert!(bucket.same_account(&alice));
+        assert!(other.same_account(&bucket));
+    }
+
+    #[test]
+    fn normalize_pattern() {
+        let pattern: ARN = "arn:aws:s3:::bucket**/**".parse().unwrap();
+        assert_eq!(pattern.normalize_pattern().unwrap().raw(), "arn:aws:s3:::bucket*/*");
+        let pattern: ARN = "arn:*:*:*:*:*".parse().unwrap();
+        assert_eq!(pattern.normalize_pattern().unwrap(), pattern);
+        let pattern: ARN = "arn:aws:iam::*:role/*".parse().unwrap();
+        assert_eq!(pattern.normalize_pattern().unwrap(), pattern);
+
+        for pattern in ["arn:aw*:s3:::bucket", "arn:aws:s?:::bucket", "arn:aws:ec2*:us-east-1:123456789012:instance/*"] {
+            let pattern: ARN = pattern.parse().unwrap();
+            assert_eq!(pattern.normalize_pattern(), Err(ARNParseError::InvalidWildcard));
+        }
+    }
+
+    #[test]
+    fn bucket_wildcard_vs_object_wildcard() {
+        let bucket_prefix: ARN = "arn:aws:s3:::bucket*".parse().unwrap();
+        let objects: ARN = "arn:aws:s3:::bucket/*".parse().unwrap();
+        let cases = [
+            ("arn:aws:s3:::bucket", true, false),
+            ("arn:aws:s3:::bucket/key", true, true),
+            ("arn:aws:s3:::bucket/nested/key", true, true),
+            ("arn:aws:s3:::bucket-archive", true, false),
+            ("arn:aws:s3:::bucket-archive/key", true, false),
+            ("arn:aws:s3:::other/bucket", false, false),
+        ];
+        for (resource, matches_prefix, matches_objects) in cases {
+            let resource: ARN = resource.parse().unwrap();
+            assert_eq!(resource.matches_pattern(&bucket_prefix), matches_prefix, "{}", resource);
+            assert_eq!(resource.matches_pattern(&objects), matches_objects, "{}", resource);
+        }
+    }
 }
@@ -1,50 +1,219 @@
-use regex::{escape, Regex};
+use regex::{escape, Regex, RegexBuilder};
 
+// Each run of wildcards becomes one '.' per '?' followed by a single ".*" if
+// the run has any '*', e.g. "a*?*b" becomes "^a..*b$". Collapsing runs keeps
+// the regular expression free of adjacent ".*" terms, which backtrack badly
+// when a match fails.
 fn pattern_from_glob(glob: &str) -> String {
-    let mut prefix = String::new();
-    let mut pattern = glob.chars().fold(String::from('^'), |mut acc, c| {
+    format!("^{}$", unanchored_pattern_from_glob(glob))
+}
+
+fn unanchored_pattern_from_glob(glob: &str) -> String {
+    let mut pattern = String::new();
+    let mut literal = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '?' && c != '*' {
+            literal.push(c);
+            continue;
+        }
         // TODO: Check if escaping glob characters is supported
-        match c {
-            '?' => {
-                if !prefix.is_empty() {
-                    acc.extend(escape(&prefix).drain(..));
-                    prefix.clear();
-                }
-                acc.push('.');
-            }
-            '*' => {
-                if !prefix.is_empty() {
-                    acc.extend(escape(&prefix).drain(..));
-                    prefix.clear();
-                }
-                acc.push_str(".*");
+        pattern.push_str(&escape(&literal));
+        literal.clear();
+        let (mut singles, mut any) = (0, false);
+        let mut wildcard = Some(c);
+        while let Some(c) = wildcard {
+            match c {
+                '?' => singles += 1,
+                _ => any = true,
             }
-            _ => {
-                prefix.push(c);
-            }
-        };
-        acc
-    });
-    pattern.extend(escape(&prefix).drain(..));
-    pattern.push('$');
+            wildcard = chars.next_if(|c| *c == '?' || *c == '*');
+        }
+        pattern.push_str(&".".repeat(singles));
+        if any {
+            pattern.push_str(".*");
+        }
+    }
+    pattern.push_str(&escape(&literal));
     pattern
 }
 
 pub fn try_regex_from_glob(glob: &str) -> Result<Regex, regex::Error> {
-    Regex::new(&pattern_from_glob(glob))
+    build_regex(glob, false)
+}
+
+fn build_regex(glob: &str, ignore_case: bool) -> Result<Regex, regex::Error> {
+    #[cfg(test)]
+    COMPILED.with(|compiled| compiled.set(compiled.get() + 1));
+    RegexBuilder::new(&pattern_from_glob(glob)).case_insensitive(ignore_case).build()
+}
+
+// Counts the regular expressions built from globs on the current thread, so
+// tests can check that matching does not recompile patterns.
+#[cfg(test)]
+thread_local! {
+    static COMPILED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+pub(crate) fn compiled_count() -> usize {
+    COMPILED.with(|compiled| compiled.get())
+}
+
+fn is_literal_glob(glob: &str) -> bool {
+    !glob.contains(['?', '*'])
+}
+
+// A glob pattern compiled once for repeated matching. Literal patterns are
+// compared directly without building a regular expression.
+#[derive(Debug, Clone)]
+pub struct CompiledGlob {
+    glob: String,
+    regex: Option<Regex>,
+    ignore_case: bool,
+}
+
+impl CompiledGlob {
+    pub fn new(glob: &str) -> Result<Self, regex::Error> {
+        Self::build(glob, false)
+    }
+
+    // A matcher that ignores case, e.g., for actions.
+    pub fn new_ignore_case(glob: &str) -> Result<Self, regex::Error> {
+        Self::build(glob, true)
+    }
+
+    fn build(glob: &str, ignore_case: bool) -> Result<Self, regex::Error> {
+        let regex = if is_literal_glob(glob) {
+            None
+        } else {
+            Some(build_regex(glob, ignore_case)?)
+        };
+        Ok(CompiledGlob{ glob: glob.to_string(), regex, ignore_case })
+    }
+
+    pub fn glob(&self) -> &str {
+        &self.glob
+    }
+
+    // Whether the pattern contains no wildcards, so it only matches itself.
+    pub fn is_literal(&self) -> bool {
+        self.regex.is_none()
+    }
+
+    pub fn is_match(&self, target: &str) -> bool {
+        match &self.regex {
+            Some(re) => re.is_match(target),
+            None if self.ignore_case => target.eq_ignore_ascii_case(&self.glob),
+            None => target == self.glob,
+        }
+    }
 }
 
 pub fn glob_matches(glob: &str, target: &str) -> bool {
-    if !glob.contains(['?', '*']) {
+    if is_literal_glob(glob) {
         return target == glob;
     }
     // TODO: Errors should be impossible.
-    try_regex_from_glob(glob).map_or(false, |re| re.is_match(target))
+    try_regex_from_glob(glob).is_ok_and(|re| re.is_match(target))
+}
+
+// A piece of a glob pattern. Literal text only matches itself, even if it
+// contains '*' or '?', e.g., a value substituted for a policy variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GlobPart {
+    Glob(String),
+    Literal(String),
+}
+
+// Whether the pattern made of the parts, in order, matches the whole target.
+pub fn glob_parts_match(parts: &[GlobPart], target: &str) -> bool {
+    let is_literal = |part: &GlobPart| match part {
+        GlobPart::Glob(glob) => is_literal_glob(glob),
+        GlobPart::Literal(_) => true,
+    };
+    if parts.iter().all(is_literal) {
+        let text: String = parts.iter().map(|part| match part {
+            GlobPart::Glob(text) | GlobPart::Literal(text) => text.as_str(),
+        }).collect();
+        return target == text;
+    }
+    let mut pattern = String::from("^");
+    for part in parts {
+        match part {
+            GlobPart::Glob(glob) => pattern.push_str(&unanchored_pattern_from_glob(glob)),
+            GlobPart::Literal(text) => pattern.push_str(&escape(text)),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).is_ok_and(|re| re.is_match(target))
+}
+
+// Whether the glob matches some part of the target, rather than all of it
+// as in glob_matches, e.g. "b?" matches "abc". AWS always matches the whole
+// value, so this is only for custom uses outside of policy evaluation.
+pub fn glob_matches_unanchored(glob: &str, target: &str) -> bool {
+    if is_literal_glob(glob) {
+        return target.contains(glob);
+    }
+    Regex::new(&unanchored_pattern_from_glob(glob)).is_ok_and(|re| re.is_match(target))
+}
+
+// Returns whether some string can be matched by both glob patterns.
+pub fn globs_overlap(lhs: &str, rhs: &str) -> bool {
+    let lhs: Vec<char> = lhs.chars().collect();
+    let rhs: Vec<char> = rhs.chars().collect();
+    let mut seen = vec![vec![false; rhs.len() + 1]; lhs.len() + 1];
+    overlap_from(&lhs, &rhs, 0, 0, &mut seen)
+}
+
+fn overlap_from(lhs: &[char], rhs: &[char], i: usize, j: usize, seen: &mut Vec<Vec<bool>>) -> bool {
+    // A state that was already visited either succeeded, ending the search,
+    // or failed, so there is no need to revisit it.
+    if seen[i][j] {
+        return false;
+    }
+    seen[i][j] = true;
+    match (lhs.get(i), rhs.get(j)) {
+        (None, None) => true,
+        (Some('*'), _) => overlap_from(lhs, rhs, i + 1, j, seen)
+            || (j < rhs.len() && overlap_from(lhs, rhs, i, j + 1, seen)),
+        (_, Some('*')) => overlap_from(lhs, rhs, i, j + 1, seen)
+            || (i < lhs.len() && overlap_from(lhs, rhs, i + 1, j, seen)),
+        (Some(a), Some(b)) => (*a == '?' || *b == '?' || a == b) && overlap_from(lhs, rhs, i + 1, j + 1, seen),
+        _ => false,
+    }
+}
+
+// Returns whether every string matched by the specific glob pattern is also
+// matched by the general one. This is conservative: a '*' in the specific
+// pattern can only be covered by a '*' in the general pattern, and a '?' by
+// either a '?' or a '*'.
+pub fn glob_covers(general: &str, specific: &str) -> bool {
+    let general: Vec<char> = general.chars().collect();
+    let specific: Vec<char> = specific.chars().collect();
+    let mut seen = vec![vec![false; specific.len() + 1]; general.len() + 1];
+    covers_from(&general, &specific, 0, 0, &mut seen)
+}
+
+fn covers_from(general: &[char], specific: &[char], i: usize, j: usize, seen: &mut Vec<Vec<bool>>) -> bool {
+    if seen[i][j] {
+        return false;
+    }
+    seen[i][j] = true;
+    match (general.get(i), specific.get(j)) {
+        (None, None) => true,
+        (Some('*'), _) => covers_from(general, specific, i + 1, j, seen)
+            || (j < specific.len() && covers_from(general, specific, i, j + 1, seen)),
+        (Some('?'), Some(b)) => *b != '*' && covers_from(general, specific, i + 1, j + 1, seen),
+        (Some(a), Some(b)) => a == b && *b != '?' && *b != '*' && covers_from(general, specific, i + 1, j + 1, seen),
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{glob_matches, pattern_from_glob};
+    use super::{glob_covers, glob_matches, glob_matches_unanchored, glob_parts_match, globs_overlap, pattern_from_glob, CompiledGlob, GlobPart};
 
     #[test]
     fn test_literal_pattern() {
@@ -78,6 +247,51 @@ mod test {
         assert_eq!(pattern, "^a.*c$");
     }
 
+    #[test]
+    fn test_anchored() {
+        assert!(glob_matches("abc", "abc"));
+        assert!(!glob_matches("abc", "xabcx"));
+        assert!(!glob_matches("a?c", "xabcx"));
+        assert!(!glob_matches("a*c", "xabcx"));
+        assert!(!glob_matches("a*", "xabc"));
+        assert!(!glob_matches("*c", "abcx"));
+        assert!(glob_matches("*abc*", "xabcx"));
+    }
+
+    #[test]
+    fn test_unanchored() {
+        assert!(glob_matches_unanchored("abc", "xabcx"));
+        assert!(glob_matches_unanchored("a?c", "xabcx"));
+        assert!(glob_matches_unanchored("a*c", "xabbbcx"));
+        assert!(glob_matches_unanchored("", "anything"));
+        assert!(glob_matches_unanchored("b.c", "ab.cd"));
+        assert!(!glob_matches_unanchored("b.c", "abxcd"));
+        assert!(!glob_matches_unanchored("abc", "xacbx"));
+        assert!(!glob_matches_unanchored("a??c", "xabcx"));
+    }
+
+    #[test]
+    fn test_consecutive_wildcard_pattern() {
+        assert_eq!(pattern_from_glob("a**b"), pattern_from_glob("a*b"));
+        assert_eq!(pattern_from_glob("a***"), "^a.*$");
+        assert_eq!(pattern_from_glob("a*?b"), "^a..*b$");
+        assert_eq!(pattern_from_glob("a?*b"), "^a..*b$");
+        assert_eq!(pattern_from_glob("a*?*?*b"), "^a...*b$");
+        assert_eq!(pattern_from_glob("a??b"), "^a..b$");
+        assert_eq!(pattern_from_glob("a.*+b"), "^a\\..*\\+b$");
+
+        for target in ["ab", "axb", "axxb", "a", "b", "axbx"] {
+            assert_eq!(glob_matches("a**b", target), glob_matches("a*b", target), "{}", target);
+        }
+        assert!(glob_matches("a*?b", "axb"));
+        assert!(! glob_matches("a*?b", "ab"));
+
+        // Many wildcards against a long non-matching target finish quickly
+        let glob = "*a".repeat(2) + &"*".repeat(50) + "b";
+        let target = "a".repeat(5000);
+        assert!(! glob_matches(&glob, &target));
+    }
+
     #[test]
     fn test_literal() {
         assert!(glob_matches("", ""));
@@ -102,4 +316,72 @@ mod test {
         assert!(! glob_matches("a*c", "bc"));
         assert!(! glob_matches("a*c", "ab"));
     }
+
+    #[test]
+    fn test_overlap() {
+        assert!(globs_overlap("abc", "abc"));
+        assert!(globs_overlap("a*", "*c"));
+        assert!(globs_overlap("a?c", "*b*"));
+        assert!(globs_overlap("Get*", "*Object"));
+        assert!(globs_overlap("*", ""));
+        assert!(! globs_overlap("abc", "abd"));
+        assert!(! globs_overlap("a*", "b*"));
+        assert!(! globs_overlap("a?", "a"));
+        assert!(! globs_overlap("Get*", "Put*"));
+    }
+
+    #[test]
+    fn test_covers() {
+        assert!(glob_covers("*", "Get*"));
+        assert!(glob_covers("Get*", "GetObject"));
+        assert!(glob_covers("Get*", "Get?bject*"));
+        assert!(glob_covers("a?", "a?"));
+        assert!(glob_covers("abc", "abc"));
+        assert!(! glob_covers("a?", "a*"));
+        assert!(! glob_covers("GetObject", "Get*"));
+        assert!(! glob_covers("Get*", "*Object"));
+        assert!(! glob_covers("abc", "a?c"));
+    }
+
+    #[test]
+    fn test_matcher_is_literal() {
+        assert!(CompiledGlob::new("").unwrap().is_literal());
+        assert!(CompiledGlob::new("sometext").unwrap().is_literal());
+        assert!(! CompiledGlob::new("some*").unwrap().is_literal());
+        assert!(! CompiledGlob::new("some?ext").unwrap().is_literal());
+    }
+
+    #[test]
+    fn test_matcher() {
+        let matcher = CompiledGlob::new("sometext").unwrap();
+        assert!(matcher.is_match("sometext"));
+        assert!(! matcher.is_match("sometextandmore"));
+        let matcher = CompiledGlob::new("a*c").unwrap();
+        assert!(matcher.is_match("abbc"));
+        assert!(! matcher.is_match("ab"));
+        assert_eq!(matcher.glob(), "a*c");
+        assert!(! matcher.is_match("ABBC"));
+
+        let matcher = CompiledGlob::new_ignore_case("Get*").unwrap();
+        assert!(matcher.is_match("getobject"));
+        assert!(matcher.is_match("GETOBJECT"));
+        assert!(! matcher.is_match("PutObject"));
+        let matcher = CompiledGlob::new_ignore_case("GetObject").unwrap();
+        assert!(matcher.is_literal());
+        assert!(matcher.is_match("getobject"));
+        assert!(! matcher.is_match("getobjectacl"));
+    }
+
+    #[test]
+    fn test_glob_parts() {
+        let parts = [GlobPart::Glob("home/".to_string()), GlobPart::Literal("a*b?".to_string()), GlobPart::Glob("/*".to_string())];
+        assert!(glob_parts_match(&parts, "home/a*b?/file"));
+        // Wildcards in literal parts do not match other characters
+        assert!(!glob_parts_match(&parts, "home/axxbc/file"));
+        assert!(!glob_parts_match(&parts, "home/a*b?"));
+        let literal = [GlobPart::Glob("home/".to_string()), GlobPart::Literal("*".to_string())];
+        assert!(glob_parts_match(&literal, "home/*"));
+        assert!(!glob_parts_match(&literal, "home/alice"));
+        assert!(glob_parts_match(&[], ""));
+    }
 }
@@ -1,3 +1,11 @@
+mod catalog;
+mod scope;
+mod targets;
+
+pub use catalog::{ActionCatalog, PRIVILEGE_ESCALATION_ACTIONS};
+pub use scope::{ResourceScope, ResourceScopeCatalog, RESOURCE_SCOPES};
+pub use targets::{ServiceTargetCatalog, SERVICE_TARGETS};
+
 use crate::aws::ARN;
 
 use std::str::FromStr;
@@ -10,6 +18,130 @@ pub enum Principal {
     Federated(String),
     Service(String),
     CanonicalUser(String),
+    // A request that is not signed, which only matches a Principal of "*".
+    // See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_elements_principal.html#principal-anonymous
+    Anonymous,
+}
+
+// The kinds of identity provider a federated principal can name.
+// See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_elements_principal.html#principal-federated-web-identity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FederatedKind {
+    // A SAML provider ARN, e.g. arn:aws:iam::123456789012:saml-provider/Okta
+    Saml,
+    // An OIDC provider, either by ARN (oidc-provider/...) or Amazon Cognito
+    // identity pools (cognito-identity.amazonaws.com)
+    Oidc,
+    // A built-in web identity provider: www.amazon.com, graph.facebook.com,
+    // or accounts.google.com
+    WebIdentity,
+    Unknown,
+}
+
+impl Principal {
+    // Classifies a federated principal by its identity provider. Returns
+    // None for other kinds of principal.
+    pub fn federated_kind(&self) -> Option<FederatedKind> {
+        let provider = match self {
+            Principal::Federated(provider) => provider,
+            _ => return None,
+        };
+        let kind = if let Ok(arn) = provider.parse::<ARN>() {
+            match (arn.service(), arn.resource_type()) {
+                ("iam", Some("saml-provider")) => FederatedKind::Saml,
+                ("iam", Some("oidc-provider")) => FederatedKind::Oidc,
+                _ => FederatedKind::Unknown,
+            }
+        } else {
+            match provider.as_str() {
+                "cognito-identity.amazonaws.com" => FederatedKind::Oidc,
+                "www.amazon.com" | "graph.facebook.com" | "accounts.google.com" => FederatedKind::WebIdentity,
+                _ => FederatedKind::Unknown,
+            }
+        };
+        Some(kind)
+    }
+
+    // A bare account ID, e.g. "123456789012", stands for the account's root
+    // user, arn:aws:iam::123456789012:root, which is how AWS stores it in a
+    // policy. Parsing already maps account IDs to the root user, so this only
+    // matters for a Federated principal constructed directly from one. Returns
+    // the principal unchanged otherwise.
+    pub fn normalize_account(&self) -> Principal {
+        match self {
+            Principal::Federated(value) => account_root(value).map_or_else(|| self.clone(), Principal::AWS),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn service_name(&self) -> Option<&str> {
+        match self {
+            Principal::Service(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    // Whether this is a service principal with a well-formed name, e.g.,
+    // ec2.amazonaws.com. Some services use a regional name, e.g.,
+    // logs.us-east-1.amazonaws.com, and the China regions use the
+    // amazonaws.com.cn domain.
+    pub fn is_valid_service(&self) -> bool {
+        let name = match self.service_name() {
+            Some(name) => name,
+            None => return false,
+        };
+        let prefix = match name.strip_suffix(".amazonaws.com").or_else(|| name.strip_suffix(".amazonaws.com.cn")) {
+            Some(prefix) => prefix,
+            None => return false,
+        };
+        let labels: Vec<&str> = prefix.split('.').collect();
+        labels.len() <= 2 && labels.iter().all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrincipalParseError {
+    InvalidARN,
+    Empty,
+}
+
+// Infers the kind of principal from its form: "*" is anonymous, an ARN is an
+// AWS principal, a 12 digit account ID is the account's root user, a name
+// under amazonaws.com is a service, and a 64 digit hex string is a canonical
+// user. Anything else is taken to be a federated
+// identity provider, e.g., accounts.google.com. Note that Cognito's provider,
+// cognito-identity.amazonaws.com, parses as a service, so construct it
+// directly when that matters.
+impl FromStr for Principal {
+    type Err = PrincipalParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.is_empty() {
+            Err(PrincipalParseError::Empty)
+        } else if value == "*" {
+            Ok(Principal::Anonymous)
+        } else if value.starts_with("arn:") {
+            value.parse().map(Principal::AWS).map_err(|_| PrincipalParseError::InvalidARN)
+        } else if let Some(root) = account_root(value).filter(|_| value.len() == 12) {
+            Ok(Principal::AWS(root))
+        } else if value.ends_with(".amazonaws.com") || value.ends_with(".amazonaws.com.cn") {
+            Ok(Principal::Service(value.to_string()))
+        } else if value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Principal::CanonicalUser(value.to_string()))
+        } else {
+            Ok(Principal::Federated(value.to_string()))
+        }
+    }
+}
+
+// The ARN of an account's root user, if the value is an account ID.
+pub(crate) fn account_root(value: &str) -> Option<ARN> {
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    format!("arn:aws:iam::{}:root", value).parse().ok()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,6 +149,16 @@ pub enum ActionParseError {
     InvalidFormat,
 }
 
+impl std::fmt::Display for ActionParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFormat => f.write_str("expected an action of the form service:Action, e.g. s3:GetObject"),
+        }
+    }
+}
+
+impl std::error::Error for ActionParseError {}
+
 impl std::fmt::Display for Principal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -24,6 +166,7 @@ impl std::fmt::Display for Principal {
             Principal::Federated(id) => f.write_fmt(format_args!("Federated: {}", id)),
             Principal::Service(id) => f.write_fmt(format_args!("Service: {}", id)),
             Principal::CanonicalUser(id) => f.write_fmt(format_args!("CanonicalUser: {}", id)),
+            Principal::Anonymous => f.write_str("Anonymous"),
         }
     }
 }
@@ -52,6 +195,71 @@ impl Action {
     pub fn action(&self) -> &str {
         &self.value[self.separator + 1 ..]
     }
+
+    // Service prefixes are case-insensitive, like the actions themselves.
+    pub fn same_service(&self, other: &Action) -> bool {
+        self.service().eq_ignore_ascii_case(other.service())
+    }
+
+    // AWS treats actions case-insensitively, so matching is done on the
+    // lowercase form of both the pattern and the requested action.
+    pub fn to_lowercase(&self) -> Self {
+        Action{value: self.value.to_ascii_lowercase(), separator: self.separator}
+    }
+
+    // Suggests what was meant by a value that is not a valid action. A
+    // service joined by the wrong separator, e.g. "s3.GetObject", gets a
+    // colon instead. A bare action name, e.g. "GetObject", gets the service
+    // of a known action with that name, ignoring case. Returns None if the
+    // value is already valid or nothing suitable is found.
+    pub fn suggest_correction<'a>(value: &str, known: impl IntoIterator<Item = &'a Action>) -> Option<Action> {
+        let value = value.trim();
+        if value.is_empty() || value.contains(':') {
+            return None;
+        }
+        let is_service = |service: &str| !service.is_empty() && service.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if let Some((service, action)) = value.split_once(|c: char| c == '.' || c == '/' || c.is_whitespace()) {
+            let action = action.trim();
+            return (is_service(service) && !action.is_empty()).then(|| Action::new(service, action));
+        }
+        known.into_iter()
+            .find(|known| is_service(known.service()) && known.action().eq_ignore_ascii_case(value))
+            .map(|known| Action::new(known.service(), value))
+    }
+}
+
+// Equality, ordering and hashing ignore case, consistent with matching, so
+// s3:getobject and s3:GetObject are the same action. The action keeps the
+// case it was written in for display.
+impl PartialEq for Action {
+    fn eq(&self, other: &Self) -> bool {
+        self.value.eq_ignore_ascii_case(&other.value)
+    }
+}
+
+impl Eq for Action {}
+
+impl PartialOrd for Action {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Action {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.value.bytes().map(|b| b.to_ascii_lowercase())
+            .cmp(other.value.bytes().map(|b| b.to_ascii_lowercase()))
+    }
+}
+
+impl std::hash::Hash for Action {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        for b in self.value.bytes() {
+            state.write_u8(b.to_ascii_lowercase());
+        }
+        // Terminate the value, as str does
+        state.write_u8(0xff);
+    }
 }
 
 impl std::fmt::Debug for Action {
@@ -74,3 +282,138 @@ impl FromStr for Action {
         Ok(Action{value: value.into(), separator})
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Action, FederatedKind, Principal, PrincipalParseError};
+
+    #[test]
+    fn normalize_account() {
+        let root: Principal = "arn:aws:iam::123456789012:root".parse().unwrap();
+        let account: Principal = "123456789012".parse().unwrap();
+        assert_eq!(account, root);
+        assert_eq!(Principal::Federated("123456789012".to_string()).normalize_account(), root);
+        assert_eq!(root.normalize_account(), root);
+        // Only 12 digit IDs are accounts
+        assert_eq!("12345".parse::<Principal>().unwrap(), Principal::Federated("12345".to_string()));
+
+        let others = [
+            Principal::Federated("accounts.google.com".to_string()),
+            Principal::Service("ec2.amazonaws.com".to_string()),
+            Principal::AWS("arn:aws:iam::123456789012:user/Alice".parse().unwrap()),
+            Principal::Anonymous,
+        ];
+        for principal in others {
+            assert_eq!(principal.normalize_account(), principal);
+        }
+    }
+
+    #[test]
+    fn action_ignores_case() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let written: Action = "s3:GetObject".parse().unwrap();
+        let lowercase: Action = "s3:getobject".parse().unwrap();
+        assert_eq!(written, lowercase);
+        assert_eq!(written.cmp(&lowercase), std::cmp::Ordering::Equal);
+        assert_eq!(HashSet::from([written.clone(), lowercase.clone()]).len(), 1);
+        assert_eq!(BTreeSet::from([written.clone(), lowercase]).len(), 1);
+        // The written case is kept for display
+        assert_eq!(written.to_string(), "s3:GetObject");
+
+        let sorted: Vec<String> = BTreeSet::from(["s3:PutObject", "S3:GetObject", "s3:listBucket"].map(|action| action.parse::<Action>().unwrap()))
+            .iter().map(Action::to_string).collect();
+        assert_eq!(sorted, vec!["S3:GetObject", "s3:listBucket", "s3:PutObject"]);
+        assert_ne!(written, "s3:GetObjectAcl".parse().unwrap());
+    }
+
+    #[test]
+    fn suggest_correction() {
+        let known: Vec<Action> = ["*:GetObject", "s3:Get*", "s3:GetObject", "ec2:RunInstances"].iter().map(|action| action.parse().unwrap()).collect();
+        let suggest = |value: &str| Action::suggest_correction(value, &known).map(|action| action.to_string());
+        assert_eq!(suggest("GetObject"), Some("s3:GetObject".to_string()));
+        assert_eq!(suggest("runinstances"), Some("ec2:runinstances".to_string()));
+        assert_eq!(suggest("s3.PutObject"), Some("s3:PutObject".to_string()));
+        assert_eq!(suggest("s3/PutObject"), Some("s3:PutObject".to_string()));
+        assert_eq!(suggest("s3 PutObject"), Some("s3:PutObject".to_string()));
+        assert_eq!(suggest("DeleteBucket"), None);
+        assert_eq!(suggest("s3:GetObject"), None);
+        assert_eq!(suggest(""), None);
+        assert_eq!(suggest("s3."), None);
+
+        assert_eq!("GetObject".parse::<Action>().unwrap_err().to_string(), "expected an action of the form service:Action, e.g. s3:GetObject");
+    }
+
+    #[test]
+    fn same_service() {
+        let get: Action = "s3:GetObject".parse().unwrap();
+        let put: Action = "s3:PutObject".parse().unwrap();
+        let upper: Action = "S3:ListBucket".parse().unwrap();
+        let run: Action = "ec2:RunInstances".parse().unwrap();
+        assert!(get.same_service(&put));
+        assert!(get.same_service(&upper));
+        assert!(upper.same_service(&get));
+        assert!(!get.same_service(&run));
+        // The service must match exactly, not just by prefix
+        assert!(!Action::new("s3", "GetObject").same_service(&Action::new("s3-object-lambda", "GetObject")));
+    }
+
+    #[test]
+    fn service_name() {
+        let service = Principal::Service("ec2.amazonaws.com".to_string());
+        assert_eq!(service.service_name(), Some("ec2.amazonaws.com"));
+        assert_eq!(Principal::Federated("accounts.google.com".to_string()).service_name(), None);
+        assert_eq!(Principal::Anonymous.service_name(), None);
+    }
+
+    #[test]
+    fn valid_service_principals() {
+        for name in ["ec2.amazonaws.com", "logs.us-east-1.amazonaws.com", "ec2.amazonaws.com.cn", "elasticloadbalancing.amazonaws.com"] {
+            assert!(Principal::Service(name.to_string()).is_valid_service(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn malformed_service_principals() {
+        let names = [
+            "ec2",
+            "ec2.amazon.com",
+            "amazonaws.com",
+            ".amazonaws.com",
+            "EC2.amazonaws.com",
+            "ec2.amazonaws.com.evil.com",
+            "a.b.c.amazonaws.com",
+            "ec2..amazonaws.com",
+        ];
+        for name in names {
+            assert!(!Principal::Service(name.to_string()).is_valid_service(), "{}", name);
+        }
+        assert!(!Principal::Federated("ec2.amazonaws.com".to_string()).is_valid_service());
+    }
+
+    #[test]
+    fn federated_kind() {
+        let federated = |provider: &str| Principal::Federated(provider.to_string());
+        assert_eq!(federated("arn:aws:iam::123456789012:saml-provider/Okta").federated_kind(), Some(FederatedKind::Saml));
+        assert_eq!(federated("arn:aws:iam::123456789012:oidc-provider/token.actions.githubusercontent.com").federated_kind(), Some(FederatedKind::Oidc));
+        assert_eq!(federated("cognito-identity.amazonaws.com").federated_kind(), Some(FederatedKind::Oidc));
+        for provider in ["www.amazon.com", "graph.facebook.com", "accounts.google.com"] {
+            assert_eq!(federated(provider).federated_kind(), Some(FederatedKind::WebIdentity), "{}", provider);
+        }
+        assert_eq!(federated("example.com").federated_kind(), Some(FederatedKind::Unknown));
+        assert_eq!(federated("arn:aws:iam::123456789012:role/Admin").federated_kind(), Some(FederatedKind::Unknown));
+        assert_eq!(Principal::Service("ecs.amazonaws.com".to_string()).federated_kind(), None);
+    }
+
+    #[test]
+    fn parse_principal() {
+        assert_eq!("*".parse(), Ok(Principal::Anonymous));
+        assert_eq!("arn:aws:iam::123456789012:role/Admin".parse(), Ok(Principal::AWS("arn:aws:iam::123456789012:role/Admin".parse().unwrap())));
+        assert_eq!("ecs.amazonaws.com".parse(), Ok(Principal::Service("ecs.amazonaws.com".to_string())));
+        assert_eq!("accounts.google.com".parse(), Ok(Principal::Federated("accounts.google.com".to_string())));
+        let canonical = "79a59df900b949e55d96a1e698fbacedfd6e09d98eacf8f8d5218e7cd47ef2be";
+        assert_eq!(canonical.parse(), Ok(Principal::CanonicalUser(canonical.to_string())));
+        assert_eq!("arn:aws".parse::<Principal>(), Err(PrincipalParseError::InvalidARN));
+        assert_eq!("".parse::<Principal>(), Err(PrincipalParseError::Empty));
+    }
+}
@@ -0,0 +1,103 @@
+use super::Action;
+
+use std::collections::BTreeSet;
+
+use anyhow::anyhow;
+
+// Actions that let a principal grant itself more permissions, directly or
+// by acting as another principal.
+pub const PRIVILEGE_ESCALATION_ACTIONS: &[&str] = &[
+    "iam:AddUserToGroup",
+    "iam:AttachGroupPolicy",
+    "iam:AttachRolePolicy",
+    "iam:AttachUserPolicy",
+    "iam:CreateAccessKey",
+    "iam:CreateLoginProfile",
+    "iam:CreatePolicyVersion",
+    "iam:PassRole",
+    "iam:PutGroupPolicy",
+    "iam:PutRolePolicy",
+    "iam:PutUserPolicy",
+    "iam:SetDefaultPolicyVersion",
+    "iam:UpdateAssumeRolePolicy",
+    "iam:UpdateLoginProfile",
+    "lambda:UpdateFunctionCode",
+    "sts:AssumeRole",
+];
+
+// A list of concrete actions (i.e., without wildcards), used to expand the
+// action patterns in a policy into the actions they refer to.
+#[derive(Debug, Clone, Default)]
+pub struct ActionCatalog {
+    actions: BTreeSet<Action>,
+}
+
+impl ActionCatalog {
+    pub fn new() -> Self {
+        ActionCatalog{ actions: BTreeSet::new() }
+    }
+
+    // A catalog of the built-in PRIVILEGE_ESCALATION_ACTIONS.
+    pub fn privilege_escalation() -> Self {
+        PRIVILEGE_ESCALATION_ACTIONS.iter()
+            .map(|action| action.parse().expect("built-in actions are valid"))
+            .collect()
+    }
+
+    pub fn insert(&mut self, action: Action) -> bool {
+        self.actions.insert(action)
+    }
+
+    pub fn contains(&self, action: &Action) -> bool {
+        self.actions.contains(action)
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Action> {
+        self.actions.iter()
+    }
+}
+
+impl FromIterator<Action> for ActionCatalog {
+    fn from_iter<T: IntoIterator<Item = Action>>(iter: T) -> Self {
+        ActionCatalog{ actions: iter.into_iter().collect() }
+    }
+}
+
+impl TryFrom<&json::JsonValue> for ActionCatalog {
+    type Error = anyhow::Error;
+
+    // Expects an array of action names, e.g., ["s3:GetObject", "s3:PutObject"]
+    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+        if !value.is_array() {
+            return Err(anyhow!("expected action catalog to be an array"));
+        }
+        value.members().map(|value| {
+            let value = value.as_str().ok_or_else(|| anyhow!("expected action to be a string"))?;
+            value.parse().map_err(|_| anyhow!("expected an action, found {}", value))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ActionCatalog;
+
+    #[test]
+    fn parse_catalog() {
+        let value = json::parse(r#"["s3:GetObject", "s3:PutObject", "s3:GetObject"]"#).unwrap();
+        let catalog = ActionCatalog::try_from(&value).unwrap();
+        assert_eq!(catalog.len(), 2);
+        assert!(catalog.contains(&"s3:PutObject".parse().unwrap()));
+
+        let value = json::parse(r#"["GetObject"]"#).unwrap();
+        assert!(ActionCatalog::try_from(&value).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use super::Action;
+
+use std::collections::HashMap;
+
+// Whether an action can be scoped to specific resources.
+// See https://docs.aws.amazon.com/service-authorization/latest/reference/reference_policies_actions-resources-contextkeys.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceScope {
+    // The action has no resource types, so it only matches Resource "*".
+    WildcardOnly,
+    // The action acts on specific resources, which the policy can name.
+    Specific,
+}
+
+// A small sample of well-known actions. Callers can add their own to a
+// ResourceScopeCatalog, e.g., from the service authorization reference.
+pub const RESOURCE_SCOPES: &[(&str, ResourceScope)] = &[
+    ("ec2:DescribeInstances", ResourceScope::WildcardOnly),
+    ("iam:ListRoles", ResourceScope::WildcardOnly),
+    ("iam:ListUsers", ResourceScope::WildcardOnly),
+    ("s3:ListAllMyBuckets", ResourceScope::WildcardOnly),
+    ("sts:GetCallerIdentity", ResourceScope::WildcardOnly),
+    ("iam:PassRole", ResourceScope::Specific),
+    ("kms:Decrypt", ResourceScope::Specific),
+    ("s3:DeleteObject", ResourceScope::Specific),
+    ("s3:GetObject", ResourceScope::Specific),
+    ("s3:PutObject", ResourceScope::Specific),
+    ("sts:AssumeRole", ResourceScope::Specific),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct ResourceScopeCatalog {
+    scopes: HashMap<Action, ResourceScope>,
+}
+
+impl ResourceScopeCatalog {
+    pub fn new() -> Self {
+        ResourceScopeCatalog{ scopes: HashMap::new() }
+    }
+
+    // A catalog of the built-in RESOURCE_SCOPES.
+    pub fn with_builtins() -> Self {
+        let mut catalog = Self::new();
+        for (action, scope) in RESOURCE_SCOPES {
+            catalog.insert(action.parse().expect("built-in actions are valid"), *scope);
+        }
+        catalog
+    }
+
+    pub fn insert(&mut self, action: Action, scope: ResourceScope) -> Option<ResourceScope> {
+        self.scopes.insert(action.to_lowercase(), scope)
+    }
+
+    // Actions are looked up case-insensitively.
+    pub fn get(&self, action: &Action) -> Option<ResourceScope> {
+        self.scopes.get(&action.to_lowercase()).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ResourceScope, ResourceScopeCatalog};
+
+    #[test]
+    fn lookup() {
+        let mut catalog = ResourceScopeCatalog::with_builtins();
+        assert_eq!(catalog.get(&"S3:listallmybuckets".parse().unwrap()), Some(ResourceScope::WildcardOnly));
+        assert_eq!(catalog.get(&"s3:GetObject".parse().unwrap()), Some(ResourceScope::Specific));
+        assert_eq!(catalog.get(&"s3:GetObjectAcl".parse().unwrap()), None);
+        catalog.insert("s3:GetObjectAcl".parse().unwrap(), ResourceScope::Specific);
+        assert_eq!(catalog.get(&"s3:getobjectacl".parse().unwrap()), Some(ResourceScope::Specific));
+    }
+}
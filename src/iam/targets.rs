@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+// Services whose actions can apply to another service's resources, as pairs
+// of the action's service and the resource's service. A small sample of
+// well-known cases. Callers can add their own to a ServiceTargetCatalog.
+pub const SERVICE_TARGETS: &[(&str, &str)] = &[
+    // S3 actions apply to Object Lambda access points and S3 on Outposts
+    ("s3", "s3-object-lambda"),
+    ("s3", "s3-outposts"),
+    ("s3-object-lambda", "s3"),
+    ("s3-outposts", "s3"),
+    ("sts", "iam"),
+    ("ssm", "ec2"),
+    // ec2:RunInstances takes SSM parameters for AMIs and IAM instance profiles
+    ("ec2", "ssm"),
+    ("ec2", "iam"),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct ServiceTargetCatalog {
+    targets: HashSet<(String, String)>,
+}
+
+impl ServiceTargetCatalog {
+    pub fn new() -> Self {
+        ServiceTargetCatalog{ targets: HashSet::new() }
+    }
+
+    // A catalog of the built-in SERVICE_TARGETS.
+    pub fn with_builtins() -> Self {
+        let mut catalog = Self::new();
+        for (action, resource) in SERVICE_TARGETS {
+            catalog.insert(action, resource);
+        }
+        catalog
+    }
+
+    pub fn insert(&mut self, action_service: &str, resource_service: &str) -> bool {
+        self.targets.insert((action_service.to_ascii_lowercase(), resource_service.to_ascii_lowercase()))
+    }
+
+    // Whether actions of the first service can apply to resources of the
+    // second, which is always true of a service's own resources. Services
+    // are compared case-insensitively.
+    pub fn allows(&self, action_service: &str, resource_service: &str) -> bool {
+        action_service.eq_ignore_ascii_case(resource_service)
+            || self.targets.contains(&(action_service.to_ascii_lowercase(), resource_service.to_ascii_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServiceTargetCatalog;
+
+    #[test]
+    fn lookup() {
+        let mut catalog = ServiceTargetCatalog::with_builtins();
+        assert!(catalog.allows("S3", "s3"));
+        assert!(catalog.allows("s3", "s3-outposts"));
+        assert!(catalog.allows("EC2", "ssm"));
+        assert!(!catalog.allows("ssm", "kms"));
+        catalog.insert("SSM", "KMS");
+        assert!(catalog.allows("ssm", "kms"));
+        assert!(!ServiceTargetCatalog::new().allows("sts", "iam"));
+    }
+}
@@ -1,10 +1,15 @@
 use awspolicy::aws::ARN;
 use awspolicy::iam::{Action, Principal};
+use awspolicy::policy::constraint::ActionConstraint;
 use awspolicy::policy::context::Context;
+use awspolicy::policy::statement::ActionClause;
+use awspolicy::policy::request::{parse_action, parse_principal, parse_resource};
 use awspolicy::policy::{CheckResult, Policy};
 
+use std::process::ExitCode;
+
 use anyhow::anyhow;
-use clap::Parser;
+use clap::{ArgGroup, Parser, ValueEnum};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ArgsError {
@@ -17,33 +22,67 @@ enum ArgsError {
     InvalidContext,
 }
 
-enum RunConfig {
-    None,
-    Identity(Action, ARN, Context),
-    Resource(Principal, Action, ARN, Context),
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    // Print warnings and evaluation results as text
+    Text,
+    // Print the policy as indented JSON and exit
+    Pretty,
+    // Print evaluation results as a table of comma-separated values, with
+    // a row per principal and a column per action
+    Csv,
+}
+
+// Every principal, or none for an identity policy, with every action. A
+// single check is a matrix with one cell.
+struct RunConfig {
+    principals: Vec<Principal>,
+    actions: Vec<Action>,
+    resource: ARN,
+    context: Context,
 }
 
 impl RunConfig {
-    fn check(&self, policy: &Policy) -> anyhow::Result<CheckResult> {
-        match self {
-            Self::None => Ok(CheckResult::Unspecified),
-            Self::Identity(action, resource, context) => policy.check_action(action, resource, context),
-            Self::Resource(principal, action, resource, context) => policy.check(principal, action, resource, context),
+    // Returns the results with a row per principal and a column per action.
+    fn check(&self, policy: &Policy, only_sid: Option<&str>) -> anyhow::Result<Vec<Vec<CheckResult>>> {
+        let RunConfig{ principals, actions, resource, context } = self;
+        if principals.is_empty() {
+            let row = actions.iter().map(|action| match only_sid {
+                Some(sid) => policy.check_statement(sid, None, action, resource, context),
+                None => policy.check_action(action, resource, context),
+            }).collect::<anyhow::Result<Vec<_>>>()?;
+            return Ok(vec![row]);
         }
+        let columns = actions.iter().map(|action| match only_sid {
+            Some(sid) => principals.iter().map(|principal| policy.check_statement(sid, Some(principal), action, resource, context)).collect(),
+            None => policy.check_principals(principals, action, resource, context)
+                .map(|results| results.into_iter().map(|(_, result)| result).collect()),
+        }).collect::<anyhow::Result<Vec<Vec<CheckResult>>>>()?;
+        Ok((0..principals.len()).map(|row| columns.iter().map(|column| column[row]).collect()).collect())
     }
 }
 
 #[derive(Parser, Debug)]
 #[clap(about, version)]
+#[clap(group(ArgGroup::new("policy_source").required(true).args(&["policy", "policy-json"])))]
 struct Args {
     #[clap(long)]
-    policy: String,
+    policy: Option<String>,
+
+    #[clap(long)]
+    policy_json: Option<String>,
+
+    #[clap(long)]
+    context: Vec<String>,
+
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 
     #[clap(long)]
-    context: Option<String>,
+    only_sid: Option<String>,
 
     #[clap(long)]
-    principal_aws: Option<String>,
+    principal_aws: Vec<String>,
 
     #[clap(long)]
     principal_federated: Option<String>,
@@ -55,45 +94,129 @@ struct Args {
     principal_canonical_user: Option<String>,
 
     #[clap(long)]
-    action: Option<String>,
+    principal_anonymous: bool,
+
+    #[clap(long)]
+    action: Vec<String>,
 
     #[clap(long)]
     resource: Option<String>,
 }
 
-impl TryFrom<&Args> for RunConfig {
-    type Error = ArgsError;
-
-    fn try_from(args: &Args) -> Result<Self, Self::Error> {
-        if args.action.is_none() && args.resource.is_none() && args.principal_aws.is_none() && args.principal_federated.is_none() && args.principal_service.is_none() && args.principal_canonical_user.is_none() {
-            return Ok(RunConfig::None);
+impl RunConfig {
+    // Returns None if the arguments ask for no checks, only parsing.
+    fn from_args(args: &Args) -> Result<Option<Self>, ArgsError> {
+        if args.action.is_empty() && args.resource.is_none() && args.principal_aws.is_empty() && args.principal_federated.is_none() && args.principal_service.is_none() && args.principal_canonical_user.is_none() && !args.principal_anonymous {
+            return Ok(None);
         }
 
-        let action = args.action.as_ref().ok_or(ArgsError::NoActionSpecified).and_then(
-            |action| action.parse().map_err(|_| ArgsError::InvalidAction)
-        )?;
+        if args.action.is_empty() {
+            return Err(ArgsError::NoActionSpecified);
+        }
+        let actions = args.action.iter()
+            .map(|action| parse_action(action).map_err(|_| ArgsError::InvalidAction))
+            .collect::<Result<Vec<Action>, _>>()?;
         let resource = args.resource.as_ref().ok_or(ArgsError::NoResourceSpecified).and_then(
-            |resource| resource.parse().map_err(|_| ArgsError::InvalidResource)
+            |resource| parse_resource(resource).map_err(|_| ArgsError::InvalidResource)
         )?;
-        let context = args.context.as_ref()
-            .map(|path| load_context(path.as_str()))
-            .unwrap_or_else(|| Ok(Context::new()))
+        // Later contexts override keys from earlier ones
+        let context = args.context.iter()
+            .try_fold(Context::new(), |mut context, path| {
+                context.merge(load_context(path.as_str())?);
+                Ok::<_, anyhow::Error>(context)
+            })
             .map_err(|_| ArgsError::InvalidContext)?;
 
-        match (&args.principal_aws, &args.principal_service, &args.principal_federated, &args.principal_canonical_user) {
-            (Some(aws), None, None, None) => if let Ok(arn) = aws.parse() {
-                Ok(RunConfig::Resource(Principal::AWS(arn), action, resource, context))
-            } else {
-                Err(ArgsError::InvalidPrincipal)
-            }
-            (None, Some(service), None, None) => Ok(RunConfig::Resource(Principal::Service(service.clone()), action, resource, context)),
-            (None, None, Some(federated), None) => Ok(RunConfig::Resource(Principal::Federated(federated.clone()), action, resource, context)),
-            (None, None, None, Some(canonical)) => Ok(RunConfig::Resource(Principal::CanonicalUser(canonical.clone()), action, resource, context)),
-            (None, None, None, None) => Ok(RunConfig::Identity(action, resource, context)),
-            _ => Err(ArgsError::MultiplePrincipalsSpecified),
+        // A bare account ID stands for the account's root user
+        let mut principals = args.principal_aws.iter()
+            .map(|aws| match parse_principal(aws) {
+                Ok(principal @ Principal::AWS(_)) => Ok(principal),
+                _ => Err(ArgsError::InvalidPrincipal),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let others = [
+            args.principal_service.clone().map(Principal::Service),
+            args.principal_federated.clone().map(Principal::Federated),
+            args.principal_canonical_user.clone().map(Principal::CanonicalUser),
+            args.principal_anonymous.then_some(Principal::Anonymous),
+        ];
+        // Only AWS principals can be repeated
+        if others.iter().flatten().count() + usize::from(!principals.is_empty()) > 1 {
+            return Err(ArgsError::MultiplePrincipalsSpecified);
         }
+        principals.extend(others.into_iter().flatten());
+        Ok(Some(RunConfig{ principals, actions, resource, context }))
+    }
+
+}
+
+// Explains an action that failed to parse, suggesting a correction based on
+// the actions the policy names.
+fn explain_invalid_action(policy: &Policy, value: &str) {
+    eprintln!("Actions must be written as service:Action, e.g. s3:GetObject");
+    let known = policy.iter()
+        .flat_map(|stmt| match &stmt.actions {
+            ActionClause::Action(actions) | ActionClause::NotAction(actions) => actions.iter(),
+        })
+        .filter_map(|constraint| match constraint {
+            ActionConstraint::Pattern(action) => Some(&**action),
+            ActionConstraint::Any => None,
+        });
+    if let Some(suggestion) = Action::suggest_correction(value, known) {
+        eprintln!("Did you mean {}?", suggestion);
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Prints the result of a single check as one line of text, or the error to
+// stderr.
+fn print_result(principal: Option<&Principal>, action: &Action, resource: &ARN, result: anyhow::Result<CheckResult>) -> ExitCode {
+    match (principal, result) {
+        (None, Ok(result)) => println!("Checked {:?} on {:?}: {:?} ({})", action, resource, &result, result.description()),
+        (None, Err(err)) => {
+            eprintln!("Error checking {:?} on {:?}: {:?}", action, resource, &err);
+            return ExitCode::FAILURE;
+        },
+        (Some(principal), Ok(result)) => println!("Checked {:?} doing {:?} on {:?}: {:?} ({})", principal, action, resource, &result, result.description()),
+        (Some(principal), Err(err)) => {
+            eprintln!("Error checking {:?} doing {:?} on {:?}: {:?}", principal, action, resource, &err);
+            return ExitCode::FAILURE;
+        },
     }
+    ExitCode::SUCCESS
+}
 
+// Prints the results of a config as an aligned table, or as CSV.
+fn print_matrix(principals: &[Principal], actions: &[Action], results: &[Vec<CheckResult>], format: Format) {
+    let labels: Vec<String> = if principals.is_empty() {
+        vec!["(no principal)".to_string()]
+    } else {
+        principals.iter().map(Principal::to_string).collect()
+    };
+    let mut rows = vec![std::iter::once("Principal".to_string()).chain(actions.iter().map(Action::to_string)).collect::<Vec<_>>()];
+    for (label, row) in labels.into_iter().zip(results) {
+        rows.push(std::iter::once(label).chain(row.iter().map(|result| format!("{:?}", result))).collect());
+    }
+    if format == Format::Csv {
+        for row in rows {
+            println!("{}", row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+        }
+        return;
+    }
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    for row in rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
 }
 
 fn load_policy(path: &str) -> anyhow::Result<Policy> {
@@ -106,36 +229,85 @@ fn load_context(path: &str) -> anyhow::Result<Context> {
     data.parse()
 }
 
-fn main() {
+// Errors are printed to stderr and exit with a failure status.
+fn main() -> ExitCode {
     let args = Args::parse();
-    let policy = match load_policy(args.policy.as_str()) {
+    let policy = match (&args.policy, &args.policy_json) {
+        (Some(path), _) => load_policy(path.as_str()),
+        (None, Some(json)) => json.parse(),
+        (None, None) => Err(anyhow!("no policy provided")),
+    };
+    let policy = match policy {
         Ok(policy) => policy,
         Err(err) => {
-            println!("Policy parse error: {:?}", err);
-            return;
+            eprintln!("Policy parse error: {:?}", err);
+            return ExitCode::FAILURE;
         }
     };
-    let config = match RunConfig::try_from(&args) {
+    if args.format == Format::Pretty {
+        println!("{}", policy.to_pretty_json(4));
+        return ExitCode::SUCCESS;
+    }
+    // Keep CSV output parseable by sending warnings to stderr
+    let warn = |message: &str| if args.format == Format::Csv {
+        eprintln!("Warning: {}", message);
+    } else {
+        println!("Warning: {}", message);
+    };
+    for warning in policy.lint() {
+        warn(&warning.to_string());
+    }
+    let config = match RunConfig::from_args(&args) {
         Ok(config) => config,
         Err(err) => {
-            println!("Argument error: {:?}", &err);
-            return;
+            eprintln!("Argument error: {:?}", &err);
+            if err == ArgsError::InvalidAction {
+                if let Some(action) = args.action.iter().find(|action| parse_action(action).is_err()) {
+                    explain_invalid_action(&policy, action);
+                }
+            }
+            return ExitCode::FAILURE;
         }
     };
 
-    match &config {
-        RunConfig::None => println!("Policy successfully parsed"),
-        RunConfig::Identity(action, resource, _context) => {
-            match config.check(&policy) {
-                Ok(result) => println!("Checked {:?} on {:?}: {:?}", action, resource, &result),
-                Err(err) => println!("Error checking {:?} on {:?}: {:?}", action, resource, &err),
-            };
+    if let Some(sid) = &args.only_sid {
+        if policy.statement_by_sid(sid).is_none() {
+            eprintln!("Argument error: no statement with Sid {}", sid);
+            return ExitCode::FAILURE;
         }
-        RunConfig::Resource(principal, action, resource, _context) => {
-            match config.check(&policy) {
-                Ok(result) => println!("Checked {:?} doing {:?} on {:?}: {:?}", principal, action, resource, &result),
-                Err(err) => println!("Error checking {:?} doing {:?} on {:?}: {:?}", principal, action, resource, &err),
-            };
+    }
+
+    let no_principal = config.as_ref().is_some_and(|config| config.principals.is_empty());
+    if no_principal && policy.has_principals() {
+        warn("the policy has Principal clauses but no principal was provided, so they will be ignored");
+    }
+    if args.context.is_empty() && config.is_some() && policy.has_conditions() {
+        warn("the policy has conditions but no context was provided, so conditional statements will not match");
+    }
+
+    match &config {
+        None => {
+            println!("Policy successfully parsed");
+            ExitCode::SUCCESS
+        },
+        Some(config @ RunConfig{ principals, actions, resource, .. }) => {
+            let results = config.check(&policy, args.only_sid.as_deref());
+            match actions.as_slice() {
+                // A single check as text is printed as one line
+                [action] if principals.len() <= 1 && args.format == Format::Text => {
+                    print_result(principals.first(), action, resource, results.map(|results| results[0][0]))
+                }
+                _ => match results {
+                    Ok(results) => {
+                        print_matrix(principals, actions, &results, args.format);
+                        ExitCode::SUCCESS
+                    },
+                    Err(err) => {
+                        eprintln!("Error checking {:?} on {:?}: {:?}", actions, resource, &err);
+                        ExitCode::FAILURE
+                    },
+                },
+            }
         }
-    };
+    }
 }
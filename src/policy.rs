@@ -1,18 +1,40 @@
+pub mod authorizer;
 pub mod condition;
 pub mod constraint;
 pub mod context;
+pub mod explain;
+pub mod limits;
+pub mod lint;
+pub mod options;
+pub mod principals;
+pub mod request;
 pub mod statement;
+pub mod stream;
+pub mod variables;
 
+pub use authorizer::Authorizer;
+pub use limits::{PolicyKind, PolicyTooLarge};
+pub use options::{EvaluationOptions, OnConditionError, ParseOptions};
+pub use principals::EffectivePrincipals;
 pub use statement::CheckResult;
 
 use crate::aws::ARN;
-use crate::iam::{Action, Principal};
+use crate::iam::{Action, ActionCatalog, Principal, ResourceScope, ResourceScopeCatalog, ServiceTargetCatalog};
+use condition::global::Type;
+use condition::operator::Operator;
+use condition::registry::KeyRegistry;
 use context::Context;
-use statement::{Effect, Statement};
+use explain::{CheckExplanation, Decision, StatementTrace};
+use lint::Warning;
+use constraint::{ActionConstraint, ResourceConstraint};
+use statement::{ActionClause, Effect, PrincipalClause, ResourceClause, Statement};
 
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
 use std::str::FromStr;
 
 use anyhow::anyhow;
+use ipnetwork::IpNetwork;
 use json;
 
 // This was an earlier version of the policy language. You might see this
@@ -35,7 +57,28 @@ for the JSON policy grammar and https://docs.aws.amazon.com/IAM/latest/UserGuide
 for a description of each element.
  */
 
-#[derive(Debug, Clone)]
+// Policy documents keyed by managed policy ARN, e.g.,
+// arn:aws:iam::aws:policy/ReadOnlyAccess, as shipped by tools that bundle
+// AWS managed policies for offline use.
+pub type ManagedPolicyBundle = HashMap<String, String>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvaluationError {
+    // More statements needed evaluating than check_with_budget allowed
+    BudgetExceeded,
+}
+
+impl std::fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BudgetExceeded => f.write_str("evaluation needed more statements than its budget allows"),
+        }
+    }
+}
+
+impl std::error::Error for EvaluationError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Policy {
     pub version: Option<String>,
     pub id: Option<String>,
@@ -43,62 +86,452 @@ pub struct Policy {
 }
 
 impl Policy {
+    // Checks only that the input is well-formed JSON, without interpreting it
+    // as a policy.
+    pub fn validate_json(value: &str) -> Result<(), json::Error> {
+        json::parse(trim_source(value)).map(|_| ())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Statement> {
+        self.statements.iter()
+    }
+
     /*
     See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html#policy-eval-denyallow
+
+    This evaluates the policy as an identity policy, so any Principal or
+    NotPrincipal clauses are ignored. Use has_principals to detect when a
+    resource policy is being evaluated this way.
     */
     pub fn check_action(&self, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
-        self.statements.iter().fold(Ok(CheckResult::Unspecified), |result, stmt| {
-            match result {
+        self.check_with_options(None, action, resource, context, &EvaluationOptions::default())
+    }
+
+    pub fn check(&self, principal: &Principal, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
+        self.check_with_options(Some(principal), action, resource, context, &EvaluationOptions::default())
+    }
+
+    // Parses the request from strings and dispatches to check, or to
+    // check_action if no principal is given. The strings are parsed as the
+    // CLI parses its arguments, see the request module. A missing context is
+    // treated as empty.
+    pub fn check_strings(&self, principal: Option<&str>, action: &str, resource: &str, context: Option<&Context>) -> anyhow::Result<CheckResult> {
+        let principal = principal.map(request::parse_principal).transpose()?;
+        let action = request::parse_action(action)?;
+        let resource = request::parse_resource(resource)?;
+        let empty = Context::new();
+        let context = context.unwrap_or(&empty);
+        match &principal {
+            Some(principal) => self.check(principal, &action, &resource, context),
+            None => self.check_action(&action, &resource, context),
+        }
+    }
+
+    // Like check, or check_action if no principal is given, with control
+    // over how errors evaluating a statement's conditions are handled.
+    pub fn check_with_options(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, options: &EvaluationOptions) -> anyhow::Result<CheckResult> {
+        self.check_recording(principal, action, resource, context, options, None)
+    }
+
+    // As check_with_options, also returning the context keys consulted by
+    // the conditions of the statements evaluated, e.g., to find entries in
+    // the context that the request does not depend on. Statements skipped
+    // because they cannot change the result consult no keys. See
+    // Context::unused_keys.
+    pub fn check_with_usage(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, options: &EvaluationOptions) -> anyhow::Result<(CheckResult, HashSet<String>)> {
+        let mut used = HashSet::new();
+        let result = self.check_recording(principal, action, resource, context, options, Some(&mut used))?;
+        Ok((result, used))
+    }
+
+    fn check_recording(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, options: &EvaluationOptions, mut used: Option<&mut HashSet<String>>) -> anyhow::Result<CheckResult> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("check", action = %action, resource = %resource).entered();
+        if self.has_blanket_deny() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("blanket deny");
+            return Ok(CheckResult::Deny);
+        }
+        let mut evaluated = 0;
+        self.statements.iter().enumerate().try_fold(CheckResult::Unspecified, |result, (_index, stmt)| {
+            let can_change = match result {
                 // An explicit deny in any policy overrides any allows
-                Ok(CheckResult::Deny) => result,
-                Ok(CheckResult::Unspecified) => stmt.check_action(action, resource, context),
+                CheckResult::Deny => false,
                 // If there is an explict allow, we only need to evaluate policies that would
                 // override this with an explicit deny
-                Ok(CheckResult::Allow) => if stmt.effect == Effect::Deny {
-                    match stmt.check_action(action, resource, context) {
-                        // An explicit deny overrides any other result
-                        Ok(CheckResult::Deny) => Ok(CheckResult::Deny),
-                        // The previous explicit allow takes precedence
-                        Ok(_) => Ok(CheckResult::Allow),
-                        Err(err) => Err(err),
-                    }
-                } else {
-                    result
-                }
-                Err(_) => result,
+                CheckResult::Allow => stmt.effect == Effect::Deny,
+                CheckResult::Unspecified => true,
+            };
+            if !can_change {
+                return Ok(result);
             }
+            if options.budget == Some(evaluated) {
+                return Err(EvaluationError::BudgetExceeded.into());
+            }
+            evaluated += 1;
+            let stmt_result = stmt.evaluate_with(principal, action, resource, context, self.supports_variables(), used.as_deref_mut());
+            let stmt_result = match (stmt_result, options.on_condition_error) {
+                (Ok(stmt_result), _) => stmt_result,
+                (Err(_), OnConditionError::Skip) => CheckResult::Unspecified,
+                (Err(err), OnConditionError::Fail) => return Err(err),
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(index = _index, sid = ?stmt.sid, result = ?stmt_result, "evaluated statement");
+            Ok(result.combine(stmt_result))
         })
     }
 
-    pub fn check(&self, principal: &Principal, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
-        self.statements.iter().fold(Ok(CheckResult::Unspecified), |result, stmt| {
-            match result {
-                // An explicit deny in any policy overrides any allows
-                Ok(CheckResult::Deny) => result,
-                Ok(CheckResult::Unspecified) => stmt.check(principal, action, resource, context),
-                // If there is an explict allow, we only need to evaluate policies that would
-                // override this with an explicit deny
-                Ok(CheckResult::Allow) => if stmt.effect == Effect::Deny {
-                    match stmt.check(principal, action, resource, context) {
-                        // An explicit deny overrides any other result
-                        Ok(CheckResult::Deny) => Ok(CheckResult::Deny),
-                        // The previous explicit allow takes precedence
-                        Ok(_) => Ok(CheckResult::Allow),
-                        Err(err) => Err(err),
-                    }
-                } else {
-                    result
+    pub fn statement_by_sid(&self, sid: &str) -> Option<&Statement> {
+        self.statements.iter().find(|stmt| stmt.sid.as_deref() == Some(sid))
+    }
+
+    // Evaluates only the statement with the given Sid, in isolation from the
+    // rest of the policy. If no principal is given, Principal clauses are
+    // ignored as in check_action.
+    pub fn check_statement(&self, sid: &str, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
+        let stmt = self.statement_by_sid(sid)
+            .ok_or_else(|| anyhow!("no statement with Sid {}", sid))?;
+        stmt.evaluate(principal, action, resource, context, self.supports_variables())
+    }
+
+    // Checks the same request for each principal, e.g., to build a table of
+    // who can perform an action on a resource.
+    pub fn check_principals(&self, principals: &[Principal], action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<Vec<(Principal, CheckResult)>> {
+        principals.iter().map(|principal| {
+            let result = self.check(principal, action, resource, context)?;
+            Ok((principal.clone(), result))
+        }).collect()
+    }
+
+    // Like check, or check_action if no principal is given, but fails with
+    // EvaluationError::BudgetExceeded rather than evaluate more than budget
+    // statements. Statements that cannot change the result are skipped and
+    // do not count against the budget.
+    pub fn check_with_budget(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, budget: usize) -> anyhow::Result<CheckResult> {
+        let options = EvaluationOptions{ budget: Some(budget), ..EvaluationOptions::default() };
+        self.check_with_options(principal, action, resource, context, &options)
+    }
+
+    // Evaluates every statement independently, in source order, without the
+    // short-circuiting done by check and check_action. If no principal is
+    // given, Principal clauses are ignored as in check_action.
+    pub fn trace(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<Vec<StatementTrace>> {
+        self.statements.iter().enumerate().map(|(index, stmt)| {
+            let result = stmt.evaluate(principal, action, resource, context, self.supports_variables())?;
+            Ok(StatementTrace{ index, sid: stmt.sid.clone(), result })
+        }).collect()
+    }
+
+    // Evaluates the request like check, reporting which statements decided
+    // it, including an Allow that a Deny overrode. See Decision.
+    pub fn decide(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<Decision> {
+        self.trace(principal, action, resource, context).map(|traces| Decision::from_traces(&traces))
+    }
+
+    // Evaluates the request like check, or like check_action without a
+    // principal, and also reports every statement that matched it and which
+    // one decided the result. See CheckExplanation.
+    pub fn check_explained(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckExplanation> {
+        let mut matched = Vec::new();
+        for (index, stmt) in self.statements.iter().enumerate() {
+            if stmt.matches_with(principal, action, resource, context, self.supports_variables())? {
+                matched.push(StatementTrace{ index, sid: stmt.sid.clone(), result: stmt.effect.as_check_result() });
+            }
+        }
+        Ok(CheckExplanation::from_matched(matched))
+    }
+
+    // Returns a copy of the policy with every statement's Effect flipped,
+    // e.g., to build an explicit block-list from an allow-list. This is not a
+    // logical negation of the policy: a request no statement matched is still
+    // not matched, and a request matched by both an Allow and a Deny statement
+    // is denied both before and after.
+    pub fn invert(&self) -> Policy {
+        let statements = self.statements.iter().map(|stmt| Statement{
+            effect: stmt.effect.inverted(),
+            ..stmt.clone()
+        }).collect();
+        Policy{
+            version: self.version.clone(),
+            id: self.id.clone(),
+            statements,
+        }
+    }
+
+    // Appends the other policy's statements after this policy's statements,
+    // so statement indices from this policy are unchanged and those from the
+    // other policy are offset by this policy's statement count.
+    pub fn merge(&mut self, other: Policy) {
+        self.statements.extend(other.statements);
+    }
+
+    // Evaluates the request as if it came from the given IP address, so that
+    // IpAddress and NotIpAddress conditions on aws:SourceIp across all
+    // statements are considered together.
+    pub fn check_source_ip(&self, ip: IpAddr, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
+        let mut context = context.clone();
+        context.insert_global("aws:SourceIp", vec![ip.to_string()]);
+        self.check_action(action, resource, &context)
+    }
+
+    // Returns the privilege escalation actions in the catalog that an Allow
+    // statement without conditions grants, whatever its resources, and that
+    // no unconditional Deny blocks. Pass ActionCatalog::privilege_escalation()
+    // for the built-in list, or a catalog of your own.
+    pub fn grants_privilege_escalation(&self, catalog: &ActionCatalog) -> Vec<Action> {
+        self.allowed_actions(catalog, |stmt| stmt.conditions.is_none()).cloned().collect()
+    }
+
+    // Answers whether the policy allows at least one of the actions matched by
+    // the action pattern (e.g., "s3:*"), as opposed to check_action, which
+    // treats the action as a single exact action. This ignores principals and
+    // is conservative about denies: an action pattern is only considered
+    // blocked if a single Deny statement covers all of it.
+    pub fn allows_any_action(&self, actions: &Action, resource: &ARN, context: &Context) -> anyhow::Result<bool> {
+        let mut allowed = false;
+        for stmt in &self.statements {
+            let applies = match stmt.effect {
+                Effect::Allow => !allowed && stmt.overlaps_actions(actions),
+                Effect::Deny => stmt.covers_actions(actions),
+            };
+            if !applies || !stmt.applies_to(resource, context)? {
+                continue;
+            }
+            match stmt.effect {
+                Effect::Allow => allowed = true,
+                Effect::Deny => return Ok(false),
+            }
+        }
+        Ok(allowed)
+    }
+
+    // Whether the policy has an unconditional Deny of "*" on Resource "*",
+    // which means every request is denied without evaluating anything else.
+    pub fn has_blanket_deny(&self) -> bool {
+        self.statements.iter().any(Statement::is_blanket_deny)
+    }
+
+    // Whether any statement has a Principal or NotPrincipal clause, which
+    // indicates this is a resource policy rather than an identity policy.
+    pub fn has_principals(&self) -> bool {
+        self.statements.iter().any(|stmt| !matches!(stmt.principals, PrincipalClause::None))
+    }
+
+    pub fn has_conditions(&self) -> bool {
+        self.statements.iter().any(|stmt| stmt.conditions.is_some())
+    }
+
+    // Whether the policy uses the 2008-10-17 language, which AWS assumes when
+    // the Version element is missing.
+    pub fn is_legacy(&self) -> bool {
+        matches!(self.version.as_deref(), None | Some(VERSION_2008_10_17))
+    }
+
+    // Whether policy variables such as ${aws:username} are substituted,
+    // rather than treated as literal strings.
+    pub fn supports_variables(&self) -> bool {
+        self.version.as_deref() == Some(VERSION_2012_10_17)
+    }
+
+    // The CIDRs in IpAddress and NotIpAddress conditions on aws:SourceIp or
+    // aws:VpcSourceIp in statements with the given effect, without
+    // duplicates, in the order they appear. Targets that are not valid
+    // CIDRs are left out.
+    pub fn source_ip_constraints_for(&self, effect: Effect) -> Vec<IpNetwork> {
+        let mut networks: Vec<IpNetwork> = Vec::new();
+        let conditions = self.statements.iter()
+            .filter(|stmt| stmt.effect == effect)
+            .filter_map(|stmt| stmt.conditions.as_ref());
+        for (quant, values) in conditions.flat_map(|conditions| conditions.iter()) {
+            let is_ip_op = matches!(quant.operator(), Some(Operator::IpAddress | Operator::NotIpAddress));
+            if !is_ip_op {
+                continue;
+            }
+            let targets = values.iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case("aws:SourceIp") || key.eq_ignore_ascii_case("aws:VpcSourceIp"))
+                .flat_map(|(_, targets)| targets);
+            for network in targets.filter_map(|target| target.parse::<IpNetwork>().ok()) {
+                if !networks.contains(&network) {
+                    networks.push(network);
                 }
-                Err(_) => result,
             }
-        })
+        }
+        networks
+    }
+
+    // The CIDRs used in source IP conditions by Allow and Deny statements,
+    // see source_ip_constraints_for.
+    pub fn source_ip_constraints(&self) -> Vec<IpNetwork> {
+        let mut networks = self.source_ip_constraints_for(Effect::Allow);
+        for network in self.source_ip_constraints_for(Effect::Deny) {
+            if !networks.contains(&network) {
+                networks.push(network);
+            }
+        }
+        networks
+    }
+
+    // The distinct condition keys used by any statement, as written.
+    pub fn condition_keys(&self) -> BTreeSet<String> {
+        self.statements.iter()
+            .filter_map(|stmt| stmt.conditions.as_ref())
+            .flat_map(|conditions| conditions.keys().cloned())
+            .collect()
+    }
+
+    // The condition keys used by the policy, sorted, with their types for
+    // keys in the built-in tables. Unknown keys have no type.
+    pub fn typed_condition_keys(&self) -> Vec<(String, Option<Type>)> {
+        let registry = KeyRegistry::with_builtins();
+        self.condition_keys().into_iter()
+            .map(|key| {
+                let ty = registry.lookup(&key).map(|(ty, _)| ty);
+                (key, ty)
+            })
+            .collect()
+    }
+
+    // Expands the policy's actions against the catalog, returning the actions
+    // allowed by any Allow statement minus those blocked by an unconditional
+    // Deny statement on all resources. Resources, principals, and conditions
+    // on Allow statements are ignored, so this is the worst-case set of
+    // actions the policy can permit.
+    pub fn effective_actions(&self, catalog: &ActionCatalog) -> BTreeSet<Action> {
+        self.allowed_actions(catalog, |_| true).cloned().collect()
+    }
+
+    // The actions in the catalog, in its order, that an Allow statement
+    // accepted by allow_counts matches and that no unconditional Deny
+    // statement on all resources blocks.
+    fn allowed_actions<'a>(&'a self, catalog: &'a ActionCatalog, allow_counts: impl Fn(&Statement) -> bool) -> impl Iterator<Item = &'a Action> {
+        let (allows, denies): (Vec<&Statement>, Vec<&Statement>) = self.statements.iter()
+            .partition(|stmt| stmt.effect == Effect::Allow);
+        let allows: Vec<&Statement> = allows.into_iter().filter(|stmt| allow_counts(stmt)).collect();
+        catalog.iter()
+            .filter(move |action| allows.iter().any(|stmt| stmt.matches_action(action)))
+            .filter(move |action| !denies.iter().any(|stmt| stmt.is_unconditional() && stmt.matches_action(action)))
+    }
+
+    // Returns who this resource policy grants access to. See
+    // EffectivePrincipals.
+    pub fn effective_principals(&self) -> EffectivePrincipals {
+        EffectivePrincipals::from_statements(&self.statements)
+    }
+
+    // A coarse measure of how much the policy permits: the number of actions
+    // in the catalog it can allow, per effective_actions.
+    pub fn permission_score(&self, catalog: &ActionCatalog) -> usize {
+        self.effective_actions(catalog).len()
+    }
+
+    // Returns the actions in the catalog that this policy can allow but the
+    // baseline cannot, per effective_actions, in sorted order. Use this to
+    // review what a change to a policy grants.
+    pub fn access_added(&self, baseline: &Policy, catalog: &ActionCatalog) -> Vec<Action> {
+        let before = baseline.effective_actions(catalog);
+        self.effective_actions(catalog).into_iter()
+            .filter(|action| !before.contains(action))
+            .collect()
+    }
+
+    // Returns the indices of statements that can never match a request. This
+    // is best-effort: a statement not reported here may still be dead, e.g.,
+    // if its conditions contradict each other.
+    pub fn find_dead_statements(&self) -> Vec<usize> {
+        self.statements.iter().enumerate()
+            .filter(|(_, stmt)| stmt.is_dead())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // Finds statements whose services differ, using the built-in
+    // ServiceTargetCatalog. See find_service_mismatches_with.
+    pub fn find_service_mismatches(&self) -> Vec<usize> {
+        self.find_service_mismatches_with(&ServiceTargetCatalog::with_builtins())
+    }
+
+    // Returns the indices of statements whose actions can never apply to
+    // their resources because the services differ, and the catalog does not
+    // list the resource's service as a target of the action's. See
+    // Statement::has_service_mismatch.
+    pub fn find_service_mismatches_with(&self, targets: &ServiceTargetCatalog) -> Vec<usize> {
+        self.statements.iter().enumerate()
+            .filter(|(_, stmt)| stmt.has_service_mismatch(targets))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // Finds actions whose Resource does not suit them, using the built-in
+    // ResourceScopeCatalog. See find_invalid_resource_scoping_with.
+    pub fn find_invalid_resource_scoping(&self) -> Vec<(usize, Action, ResourceScope)> {
+        self.find_invalid_resource_scoping_with(&ResourceScopeCatalog::with_builtins())
+    }
+
+    // Returns the statement index, action, and the action's scope for each
+    // action that is either WildcardOnly but scoped to specific ARNs, so it
+    // never matches, or Specific but granted on "*", which is broader than
+    // necessary. Only actions without wildcards are checked, and actions the
+    // catalog does not know are skipped.
+    pub fn find_invalid_resource_scoping_with(&self, catalog: &ResourceScopeCatalog) -> Vec<(usize, Action, ResourceScope)> {
+        let mut result = Vec::new();
+        for (index, stmt) in self.statements.iter().enumerate() {
+            let (actions, resources) = match (&stmt.actions, &stmt.resources) {
+                (ActionClause::Action(actions), ResourceClause::Resource(resources)) if !resources.is_empty() => (actions, resources),
+                _ => continue,
+            };
+            let any_resource = resources.iter().any(|constraint| matches!(constraint, ResourceConstraint::Any));
+            for action in actions {
+                let action = match action {
+                    ActionConstraint::Pattern(action) if !action.action().contains(['*', '?']) => &**action,
+                    _ => continue,
+                };
+                match catalog.get(action) {
+                    Some(scope @ ResourceScope::WildcardOnly) if !any_resource => result.push((index, action.clone(), scope)),
+                    Some(scope @ ResourceScope::Specific) if any_resource => result.push((index, action.clone(), scope)),
+                    _ => {}
+                }
+            }
+        }
+        result
+    }
+
+    // Reports statements that parse correctly but probably do not behave the
+    // way their author intended.
+    pub fn lint(&self) -> Vec<Warning> {
+        self.statements.iter().enumerate()
+            .flat_map(|(index, stmt)| lint::lint_statement(index, stmt))
+            .collect()
     }
 }
 
-impl TryFrom<&json::JsonValue> for Policy {
-    type Error = anyhow::Error;
+impl<'a> IntoIterator for &'a Policy {
+    type Item = &'a Statement;
+    type IntoIter = std::slice::Iter<'a, Statement>;
 
-    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Policy {
+    // Lazily parses a JSON array of policies, yielding each policy as it is
+    // read. See PolicyStream.
+    pub fn parse_many<R: std::io::Read>(reader: R) -> impl Iterator<Item = anyhow::Result<Policy>> {
+        stream::PolicyStream::new(reader)
+    }
+
+    // Parses a policy written as JSON with comments, as some authoring tools
+    // emit. Both // and /* */ comments are removed, except inside strings.
+    pub fn from_jsonc(value: &str) -> anyhow::Result<Self> {
+        strip_comments(value)?.parse()
+    }
+
+    pub fn parse_with_options(value: &str, options: &ParseOptions) -> anyhow::Result<Self> {
+        let value = json::parse(trim_source(value))?;
+        Self::from_json_with_options(&value, options)
+    }
+
+    pub fn from_json_with_options(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Self> {
         let version = &value["Version"];
         let version = if let Some(v) = version.as_str() {
             // TODO: Introduce proper error type (or use a crate like anyhow)
@@ -114,9 +547,11 @@ impl TryFrom<&json::JsonValue> for Policy {
         let id = value["Id"].as_str().map(|s| s.to_string());
         let statements = &value["Statement"];
         let statements = if statements.is_object() {
-            Statement::try_from(statements).map(|statement| vec![statement])?
+            Statement::from_json_with_options(statements, options).map(|statement| vec![statement])?
         } else if statements.is_array() {
-            statements.members().map(Statement::try_from).collect::<anyhow::Result<Vec<_>>>()?
+            statements.members()
+                .map(|statement| Statement::from_json_with_options(statement, options))
+                .collect::<anyhow::Result<Vec<_>>>()?
         } else {
             return Err(anyhow!("expected Statements to be an object or array"));
         };
@@ -124,11 +559,1672 @@ impl TryFrom<&json::JsonValue> for Policy {
     }
 }
 
+impl Policy {
+    // Serializes the policy as indented JSON, e.g. to reformat a minified
+    // policy. Conditions are written in the normalized form described on
+    // the ConditionList serializer.
+    pub fn to_pretty_json(&self, indent: u16) -> String {
+        json::JsonValue::from(self).pretty(indent)
+    }
+
+    // Returns a copy of the policy with only the statements matching the
+    // predicate, in their original order, e.g. to share just the parts of a
+    // policy relevant to a question.
+    pub fn filter(&self, pred: impl Fn(&Statement) -> bool) -> Policy {
+        Policy{
+            version: self.version.clone(),
+            id: self.id.clone(),
+            statements: self.statements.iter().filter(|stmt| pred(stmt)).cloned().collect(),
+        }
+    }
+
+    // Returns the size of the policy as AWS counts it, i.e., the serialized
+    // policy without whitespace, or an error if it exceeds the limit for the
+    // kind of policy. The size is of the normalized serialization, which can
+    // differ slightly from the original document.
+    pub fn size_check(&self, kind: PolicyKind) -> Result<usize, PolicyTooLarge> {
+        let size = json::JsonValue::from(self).dump().chars().filter(|c| !c.is_whitespace()).count();
+        let limit = kind.size_limit();
+        if size > limit {
+            Err(PolicyTooLarge{ kind, size, limit })
+        } else {
+            Ok(size)
+        }
+    }
+}
+
+impl From<&Policy> for json::JsonValue {
+    fn from(policy: &Policy) -> Self {
+        let mut result = json::JsonValue::new_object();
+        if let Some(version) = &policy.version {
+            result["Version"] = version.as_str().into();
+        }
+        if let Some(id) = &policy.id {
+            result["Id"] = id.as_str().into();
+        }
+        result["Statement"] = json::JsonValue::Array(policy.statements.iter().map(json::JsonValue::from).collect());
+        result
+    }
+}
+
+impl Policy {
+    pub fn from_managed_bundle(bundle: &ManagedPolicyBundle, arn: &str) -> anyhow::Result<Self> {
+        let document = bundle.get(arn)
+            .ok_or_else(|| anyhow!("managed policy not found in bundle: {}", arn))?;
+        document.parse()
+            .map_err(|err| anyhow!("unable to parse managed policy {}: {}", arn, err))
+    }
+}
+
+impl TryFrom<&json::JsonValue> for Policy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+        Self::from_json_with_options(value, &ParseOptions::default())
+    }
+}
+
+// Strips a leading UTF-8 byte order mark and surrounding whitespace, which
+// some tools add when exporting policy files.
+fn trim_source(value: &str) -> &str {
+    value.strip_prefix('\u{feff}').unwrap_or(value).trim()
+}
+
+// Replaces each comment with whitespace, keeping line breaks so that parse
+// errors still report the original line numbers.
+fn strip_comments(value: &str) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    let (mut in_string, mut escaped) = (false, false);
+    while let Some(c) = chars.next() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            result.push(c);
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            },
+            ('/', Some('*')) => {
+                chars.next();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.next_if_eq(&'/').is_some() {
+                        closed = true;
+                        break;
+                    }
+                    result.push(if c == '\n' { '\n' } else { ' ' });
+                }
+                if !closed {
+                    return Err(anyhow!("unterminated comment"));
+                }
+                result.push(' ');
+            },
+            ('"', _) => {
+                in_string = true;
+                result.push(c);
+            },
+            _ => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
 impl FromStr for Policy {
     type Err = anyhow::Error;
 
     fn from_str(value: &str) -> anyhow::Result<Self> {
-        let value = json::parse(value)?;
+        let value = json::parse(trim_source(value))?;
         Self::try_from(&value)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::statement::{Effect, Statement};
+    use super::condition::global::Type;
+    use super::{CheckResult, EvaluationError, EvaluationOptions, ManagedPolicyBundle, OnConditionError, ParseOptions, Policy, PolicyKind, PolicyTooLarge};
+    use super::{VERSION_2008_10_17, VERSION_2012_10_17};
+    use super::explain::MatchOutcome;
+    use crate::iam::{Action, ActionCatalog, Principal, ResourceScope, ResourceScopeCatalog, ServiceTargetCatalog};
+    use crate::policy::context::Context;
+
+    use chrono::{TimeZone, Utc};
+    use ipnetwork::IpNetwork;
+
+    #[test]
+    fn precompiled_patterns() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:Get*", "s3:List?ucket"], "Resource": "arn:aws:s3:::bucket-*/*"},
+                {"Effect": "Deny", "Action": "s3:*", "Resource": "arn:*:s3:::bucket-*/secret/*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let check = |action: &str, resource: &str| {
+            policy.check_action(&action.parse().unwrap(), &resource.parse().unwrap(), &context).unwrap()
+        };
+        let compiled = crate::aws::compiled_count();
+        for _ in 0..10 {
+            assert_eq!(CheckResult::Allow, check("s3:GetObject", "arn:aws:s3:::bucket-1/key"));
+            assert_eq!(CheckResult::Deny, check("s3:GetObject", "arn:aws:s3:::bucket-1/secret/key"));
+            assert_eq!(CheckResult::Unspecified, check("s3:PutObject", "arn:aws:s3:::bucket-1/key"));
+        }
+        // Matching uses the patterns compiled when the policy was parsed
+        assert_eq!(compiled, crate::aws::compiled_count());
+
+        // A pattern that cannot be compiled fails to parse
+        let pattern = format!("arn:aws:s3:::{}", "*a".repeat(100_000));
+        let source = format!(r#"{{"Version": "2012-10-17", "Statement": {{"Effect": "Allow", "Action": "s3:*", "Resource": "{}"}}}}"#, pattern);
+        let err = source.parse::<Policy>().unwrap_err();
+        assert!(format!("{:#}", err).contains("size limit"), "{:#}", err);
+    }
+
+    #[test]
+    fn validate_json() {
+        assert!(Policy::validate_json(r#"{"Version": "2012-10-17", "Statement": []}"#).is_ok());
+        // Well-formed JSON is accepted even if it is not a valid policy
+        assert!(Policy::validate_json(r#"{"Version": "1999-01-01"}"#).is_ok());
+        assert!(Policy::validate_json(r#"{"Version": "2012-10-17", "Statement": [}"#).is_err());
+        assert!(Policy::validate_json(r#"{"Version": "2012-10-17""#).is_err());
+        assert!(Policy::validate_json("").is_err());
+    }
+
+    fn s3_catalog() -> ActionCatalog {
+        ["s3:GetObject", "s3:PutObject", "s3:DeleteObject", "s3:DeleteBucket", "s3:ListBucket", "ec2:RunInstances"]
+            .iter().map(|action| action.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn effective_actions() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:*", "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:Delete*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let actions: Vec<String> = policy.effective_actions(&s3_catalog()).iter().map(Action::to_string).collect();
+        assert_eq!(actions, vec!["s3:GetObject", "s3:ListBucket", "s3:PutObject"]);
+    }
+
+    #[test]
+    fn permission_score() {
+        let admin: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Action": "*", "Resource": "*"}
+        }"#.parse().unwrap();
+        let read_only: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Action": ["s3:Get*", "s3:List*"], "Resource": "arn:aws:s3:::bucket/*"}
+        }"#.parse().unwrap();
+        let catalog = s3_catalog();
+        assert_eq!(admin.permission_score(&catalog), catalog.len());
+        assert_eq!(read_only.permission_score(&catalog), 2);
+        assert!(read_only.permission_score(&catalog) < admin.permission_score(&catalog));
+        // Actions that differ only in case are counted once
+        let mixed: ActionCatalog = ["s3:GetObject", "s3:getobject"].iter().map(|action| action.parse().unwrap()).collect();
+        assert_eq!(admin.permission_score(&mixed), 1);
+    }
+
+    #[test]
+    fn access_added() {
+        let baseline: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Action": ["s3:GetObject", "s3:PutObject"], "Resource": "arn:aws:s3:::bucket/*"}
+        }"#.parse().unwrap();
+        let updated: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:GetObject", "s3:PutObject"], "Resource": "arn:aws:s3:::bucket/*"},
+                {"Sid": "Cleanup", "Effect": "Allow", "Action": "s3:DeleteObject", "Resource": "arn:aws:s3:::bucket/tmp/*"}
+            ]
+        }"#.parse().unwrap();
+        let catalog = s3_catalog();
+        let added: Vec<String> = updated.access_added(&baseline, &catalog).iter().map(Action::to_string).collect();
+        assert_eq!(added, vec!["s3:DeleteObject"]);
+        // Removing access is not reported
+        assert!(baseline.access_added(&updated, &catalog).is_empty());
+        assert!(baseline.access_added(&baseline, &catalog).is_empty());
+    }
+
+    #[test]
+    fn effective_actions_ignores_scoped_denies() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:GetObject", "s3:DeleteObject"], "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:Delete*", "Resource": "arn:aws:s3:::bucket/*"},
+                {"Effect": "Deny", "Action": "s3:GetObject", "Resource": "*", "Condition": {"Bool": {"aws:SecureTransport": "false"}}}
+            ]
+        }"#.parse().unwrap();
+        let actions: Vec<String> = policy.effective_actions(&s3_catalog()).iter().map(Action::to_string).collect();
+        assert_eq!(actions, vec!["s3:DeleteObject", "s3:GetObject"]);
+    }
+
+    #[test]
+    fn find_dead_statements() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Effect": "Allow", "NotAction": "*", "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        assert_eq!(policy.find_dead_statements(), vec![1]);
+    }
+
+    #[test]
+    fn find_service_mismatches() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:ec2:us-east-1:123456789012:instance/*"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": ["arn:aws:ec2:us-east-1:123456789012:instance/*", "arn:aws:s3:::bucket/*"]},
+                {"Effect": "Allow", "Action": ["S3:GetObject", "ec2:StartInstances"], "Resource": "arn:aws:ec2:us-east-1:123456789012:instance/*"},
+                {"Effect": "Deny", "Action": ["iam:PassRole", "s3:PutObject"], "Resource": "arn:aws:kms:us-east-1:123456789012:key/*"},
+                {"Effect": "Allow", "Action": "*", "Resource": "arn:aws:ec2:us-east-1:123456789012:instance/*"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:*:us-east-1:123456789012:*"},
+                {"Effect": "Allow", "NotAction": "s3:GetObject", "Resource": "arn:aws:ec2:us-east-1:123456789012:instance/*"},
+                {"Effect": "Allow", "Action": "sts:AssumeRole", "Resource": "arn:aws:iam::123456789012:role/Admin"},
+                {"Effect": "Allow", "Action": "ssm:SendCommand", "Resource": "arn:aws:ec2:us-east-1:123456789012:instance/*"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": ["arn:aws:s3-object-lambda:us-east-1:123456789012:accesspoint/*", "arn:aws:s3-outposts:us-east-1:123456789012:outpost/*"]},
+                {"Effect": "Allow", "Action": "ec2:RunInstances", "Resource": ["arn:aws:ssm:us-east-1::parameter/aws/service/*", "arn:aws:iam::123456789012:instance-profile/*"]}
+            ]
+        }"#.parse().unwrap();
+        assert_eq!(policy.find_service_mismatches(), vec![0, 3]);
+
+        // Without the built-in targets, cross-service statements are reported
+        let mut targets = ServiceTargetCatalog::new();
+        targets.insert("sts", "iam");
+        assert_eq!(policy.find_service_mismatches_with(&targets), vec![0, 3, 9, 10, 11]);
+    }
+
+    #[test]
+    fn find_invalid_resource_scoping() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:ListAllMyBuckets", "s3:GetObject"], "Resource": "arn:aws:s3:::bucket/*"},
+                {"Effect": "Allow", "Action": ["s3:ListAllMyBuckets", "s3:PutObject", "s3:Get*"], "Resource": "*"},
+                {"Effect": "Allow", "Action": "sts:GetCallerIdentity", "Resource": ["*", "arn:aws:sts::123456789012:*"]},
+                {"Effect": "Allow", "NotAction": "s3:ListAllMyBuckets", "Resource": "arn:aws:s3:::bucket/*"},
+                {"Effect": "Allow", "Action": "s3:CreateJob", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        assert_eq!(policy.find_invalid_resource_scoping(), vec![
+            (0, "s3:ListAllMyBuckets".parse().unwrap(), ResourceScope::WildcardOnly),
+            (1, "s3:PutObject".parse().unwrap(), ResourceScope::Specific),
+        ]);
+
+        let mut catalog = ResourceScopeCatalog::new();
+        catalog.insert("s3:CreateJob".parse().unwrap(), ResourceScope::Specific);
+        assert_eq!(policy.find_invalid_resource_scoping_with(&catalog), vec![
+            (4, "s3:CreateJob".parse().unwrap(), ResourceScope::Specific),
+        ]);
+    }
+
+    #[test]
+    fn trace_order_after_merge() {
+        let mut policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "AllowRead", "Effect": "Allow", "Action": "s3:Get*", "Resource": "*"},
+                {"Sid": "AllowList", "Effect": "Allow", "Action": "s3:List*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let other: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "DenyObject", "Effect": "Deny", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::secret/*"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        policy.merge(other);
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::secret/key".parse().unwrap();
+        let trace = policy.trace(None, &action, &resource, &context).unwrap();
+        let summary: Vec<_> = trace.iter().map(|entry| (entry.index, entry.sid.as_deref(), entry.result)).collect();
+        assert_eq!(summary, vec![
+            (0, Some("AllowRead"), CheckResult::Allow),
+            (1, Some("AllowList"), CheckResult::Unspecified),
+            (2, Some("DenyObject"), CheckResult::Deny),
+            (3, None, CheckResult::Allow),
+        ]);
+    }
+
+    #[test]
+    fn allow_overridden_by_deny() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "AllowList", "Effect": "Allow", "Action": "s3:List*", "Resource": "*"},
+                {"Sid": "AllowRead", "Effect": "Allow", "Action": "s3:Get*", "Resource": "*"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Sid": "DenySecret", "Effect": "Deny", "Action": "s3:*", "Resource": "arn:aws:s3:::secret/*"},
+                {"Sid": "DenyDelete", "Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let decide = |action: &str, resource: &str| policy.decide(None, &action.parse().unwrap(), &resource.parse().unwrap(), &context).unwrap();
+
+        let decision = decide("s3:GetObject", "arn:aws:s3:::secret/key");
+        assert_eq!(decision.result, CheckResult::Deny);
+        assert_eq!(decision.override_sids(), Some((Some("AllowRead"), Some("DenySecret"))));
+        assert_eq!(decision.overridden_allow.map(|trace| trace.index), Some(1));
+        assert_eq!(decision.deciding.map(|trace| trace.index), Some(3));
+
+        // Denied without any Allow to override
+        let decision = decide("s3:DeleteObject", "arn:aws:s3:::bucket/key");
+        assert_eq!(decision.result, CheckResult::Deny);
+        assert_eq!(decision.deciding.as_ref().and_then(|trace| trace.sid.as_deref()), Some("DenyDelete"));
+        assert_eq!(decision.override_sids(), None);
+
+        let decision = decide("s3:GetObject", "arn:aws:s3:::bucket/key");
+        assert_eq!(decision.result, CheckResult::Allow);
+        assert_eq!(decision.deciding.as_ref().and_then(|trace| trace.sid.as_deref()), Some("AllowRead"));
+        assert_eq!(decision.override_sids(), None);
+
+        let decision = decide("ec2:RunInstances", "arn:aws:ec2:us-east-1:123456789012:instance/*");
+        assert_eq!(decision.result, CheckResult::Unspecified);
+        assert_eq!(decision.deciding, None);
+    }
+
+    #[test]
+    fn check_explained() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "AllowRead", "Effect": "Allow", "Action": "s3:Get*", "Resource": "*"},
+                {"Effect": "Allow", "Action": "s3:*", "Resource": "arn:aws:s3:::bucket/*"},
+                {"Sid": "DenySecret", "Effect": "Deny", "Action": "s3:*", "Resource": "arn:aws:s3:::bucket/secret/*"},
+                {"Sid": "AllowAlice", "Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:user/Alice"}, "Action": "s3:PutObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let explain = |principal: Option<&Principal>, action: &str, resource: &str| {
+            let (action, resource) = (action.parse().unwrap(), resource.parse().unwrap());
+            let explanation = policy.check_explained(principal, &action, &resource, &context).unwrap();
+            let expected = match principal {
+                Some(principal) => policy.check(principal, &action, &resource, &context).unwrap(),
+                None => policy.check_action(&action, &resource, &context).unwrap(),
+            };
+            assert_eq!(explanation.result, expected);
+            explanation
+        };
+        let matched = |explanation: &super::explain::CheckExplanation| -> Vec<(usize, CheckResult)> {
+            explanation.matched.iter().map(|trace| (trace.index, trace.result)).collect()
+        };
+
+        // The Deny decides even though earlier Allows matched
+        let explanation = explain(None, "s3:GetObject", "arn:aws:s3:::bucket/secret/key");
+        assert_eq!(explanation.result, CheckResult::Deny);
+        assert_eq!(explanation.decided_by(), Some((2, Some("DenySecret"))));
+        assert_eq!(matched(&explanation), vec![(0, CheckResult::Allow), (1, CheckResult::Allow), (2, CheckResult::Deny)]);
+
+        // The first matching Allow decides, and may have no Sid
+        let explanation = explain(None, "s3:GetObject", "arn:aws:s3:::bucket/key");
+        assert_eq!(explanation.decided_by(), Some((0, Some("AllowRead"))));
+        assert_eq!(matched(&explanation).len(), 2);
+        let explanation = explain(None, "s3:ListBucket", "arn:aws:s3:::bucket/key");
+        assert_eq!(explanation.decided_by(), Some((1, None)));
+
+        // The Principal clause is only checked when a principal is given
+        let alice: Principal = "arn:aws:iam::123456789012:user/Alice".parse().unwrap();
+        let bob: Principal = "arn:aws:iam::123456789012:user/Bob".parse().unwrap();
+        let explanation = explain(Some(&alice), "s3:PutObject", "arn:aws:s3:::other/key");
+        assert_eq!(explanation.decided_by(), Some((3, Some("AllowAlice"))));
+        let explanation = explain(Some(&bob), "s3:PutObject", "arn:aws:s3:::other/key");
+        assert_eq!(explanation.result, CheckResult::Unspecified);
+
+        // An implicit deny has no deciding statement
+        let explanation = explain(None, "ec2:RunInstances", "arn:aws:ec2:us-east-1:123456789012:instance/*");
+        assert_eq!(explanation.result, CheckResult::Unspecified);
+        assert_eq!(explanation.deciding, None);
+        assert!(explanation.matched.is_empty());
+
+        let stmt = &policy.statements[2];
+        assert!(stmt.matches_request(None, &"s3:PutObject".parse().unwrap(), &"arn:aws:s3:::bucket/secret/key".parse().unwrap(), &context).unwrap());
+        assert!(!stmt.matches_request(None, &"s3:PutObject".parse().unwrap(), &"arn:aws:s3:::bucket/key".parse().unwrap(), &context).unwrap());
+    }
+
+    #[test]
+    fn iterate_statements() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "First", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Sid": "Second", "Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let sids: Vec<_> = policy.iter().map(|stmt| stmt.sid.as_deref()).collect();
+        assert_eq!(sids, vec![Some("First"), Some("Second")]);
+
+        let mut count = 0;
+        for stmt in &policy {
+            assert!(stmt.sid.is_some());
+            count += 1;
+        }
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn grants_privilege_escalation() {
+        let escalation = ActionCatalog::privilege_escalation();
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "iam:*", "Resource": "*"},
+                {"Effect": "Deny", "Action": "iam:PassRole", "Resource": "*"},
+                {"Effect": "Allow", "Action": "sts:AssumeRole", "Resource": "*", "Condition": {"Bool": {"aws:MultiFactorAuthPresent": "true"}}}
+            ]
+        }"#.parse().unwrap();
+        let granted: Vec<String> = policy.grants_privilege_escalation(&escalation).iter().map(|action| action.to_string()).collect();
+        assert!(granted.contains(&"iam:CreatePolicyVersion".to_string()));
+        assert!(granted.contains(&"iam:AttachUserPolicy".to_string()));
+        assert!(!granted.contains(&"iam:PassRole".to_string()));
+        assert!(!granted.contains(&"sts:AssumeRole".to_string()));
+        assert!(granted.iter().all(|action| action.starts_with("iam:")));
+
+        // The list can be replaced
+        let custom: ActionCatalog = ["iam:PassRole", "iam:CreateUser", "s3:GetObject"].iter().map(|action| action.parse().unwrap()).collect();
+        let granted: Vec<String> = policy.grants_privilege_escalation(&custom).iter().map(|action| action.to_string()).collect();
+        assert_eq!(granted, vec!["iam:CreateUser"]);
+
+        let read_only: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Action": ["s3:Get*", "iam:Get*", "iam:List*"], "Resource": "*"}
+        }"#.parse().unwrap();
+        assert!(read_only.grants_privilege_escalation(&escalation).is_empty());
+    }
+
+    #[test]
+    fn allows_any_action() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:Get*", "Resource": "*"},
+                {"Effect": "Deny", "Action": "ec2:*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let cases = [
+            ("s3:*", true),
+            ("s3:*Object", true),
+            ("s3:GetObject", true),
+            ("s3:Put*", false),
+            ("ec2:*", false),
+            ("ec2:Describe*", false),
+            ("*:*", true),
+        ];
+        for (actions, expected) in cases {
+            let result = policy.allows_any_action(&actions.parse().unwrap(), &resource, &context).unwrap();
+            assert_eq!(expected, result, "{}", actions);
+        }
+    }
+
+    #[test]
+    fn allows_any_action_blocked_by_covering_deny() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:*", "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:Delete*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert!(policy.allows_any_action(&"s3:*".parse().unwrap(), &resource, &context).unwrap());
+        assert!(!policy.allows_any_action(&"s3:Delete*".parse().unwrap(), &resource, &context).unwrap());
+        assert!(!policy.allows_any_action(&"s3:DeleteObject*".parse().unwrap(), &resource, &context).unwrap());
+    }
+
+    #[test]
+    fn identity_check_ignores_principals() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Principal": {"AWS": "arn:aws:iam::123456789012:user/Alice"},
+                "Action": "s3:GetObject",
+                "Resource": "*"
+            }
+        }"#.parse().unwrap();
+        assert!(policy.has_principals());
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(CheckResult::Allow, policy.check_action(&action, &resource, &context).unwrap());
+        let bob = Principal::AWS("arn:aws:iam::123456789012:user/Bob".parse().unwrap());
+        assert_eq!(CheckResult::Unspecified, policy.check(&bob, &action, &resource, &context).unwrap());
+    }
+
+    #[test]
+    fn identity_policy_has_no_principals() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}
+        }"#.parse().unwrap();
+        assert!(!policy.has_principals());
+    }
+
+    #[test]
+    fn anonymous_principal() {
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let alice = Principal::AWS("arn:aws:iam::123456789012:user/Alice".parse().unwrap());
+
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "*"}
+        }"#.parse().unwrap();
+        assert_eq!(CheckResult::Allow, policy.check(&Principal::Anonymous, &action, &resource, &context).unwrap());
+        assert_eq!(CheckResult::Allow, policy.check(&alice, &action, &resource, &context).unwrap());
+
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Principal": {"AWS": "*"}, "Action": "s3:GetObject", "Resource": "*"}
+        }"#.parse().unwrap();
+        assert_eq!(CheckResult::Unspecified, policy.check(&Principal::Anonymous, &action, &resource, &context).unwrap());
+        assert_eq!(CheckResult::Allow, policy.check(&alice, &action, &resource, &context).unwrap());
+    }
+
+    #[test]
+    fn wildcard_principal_forms() {
+        let policy = |principal: &str| -> Policy {
+            format!(r#"{{
+                "Version": "2012-10-17",
+                "Statement": {{"Effect": "Allow", "Principal": {}, "Action": "sts:AssumeRoleWithWebIdentity", "Resource": "*"}}
+            }}"#, principal).parse().unwrap()
+        };
+        let federated = Principal::Federated("accounts.google.com".to_string());
+        let action = "sts:AssumeRoleWithWebIdentity".parse().unwrap();
+        let resource = "arn:aws:iam::123456789012:role/WebIdentity".parse().unwrap();
+        let context = Context::new();
+        assert_eq!(CheckResult::Allow, policy(r#""*""#).check(&federated, &action, &resource, &context).unwrap());
+        assert_eq!(CheckResult::Unspecified, policy(r#"{"AWS": "*"}"#).check(&federated, &action, &resource, &context).unwrap());
+        assert_eq!(CheckResult::Allow, policy(r#"{"Federated": "accounts.google.com"}"#).check(&federated, &action, &resource, &context).unwrap());
+    }
+
+    #[test]
+    fn account_principal_forms() {
+        let forms = [r#""123456789012""#, r#""arn:aws:iam::123456789012:root""#, r#""arn:aws:iam::123456789012:*""#];
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let context = Context::new();
+        for form in forms {
+            let policy: Policy = format!(r#"{{
+                "Version": "2012-10-17",
+                "Statement": {{"Effect": "Allow", "Principal": {{"AWS": {}}}, "Action": "s3:GetObject", "Resource": "*"}}
+            }}"#, form).parse().unwrap();
+            for principal in ["arn:aws:iam::123456789012:user/Alice", "arn:aws:iam::123456789012:role/R"] {
+                let principal = Principal::AWS(principal.parse().unwrap());
+                assert_eq!(CheckResult::Allow, policy.check(&principal, &action, &resource, &context).unwrap(), "{} {}", form, principal);
+            }
+            let other = Principal::AWS("arn:aws:iam::210987654321:user/Alice".parse().unwrap());
+            assert_eq!(CheckResult::Unspecified, policy.check(&other, &action, &resource, &context).unwrap(), "{}", form);
+        }
+    }
+
+    #[test]
+    fn deny_not_principal() {
+        let policy: Policy = include_str!("../testdata/resource_s3_deny_not_principal.json").parse().unwrap();
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::BUCKETNAME/key".parse().unwrap();
+        let cases = [
+            // The excepted principals fall through to whatever other policies allow
+            ("arn:aws:iam::444455556666:user/Bob", CheckResult::Unspecified),
+            ("arn:aws:iam::444455556666:root", CheckResult::Unspecified),
+            // Everyone else, including other principals in the same account, is denied
+            ("arn:aws:iam::444455556666:user/Alice", CheckResult::Deny),
+            ("arn:aws:iam::111122223333:user/Bob", CheckResult::Deny),
+        ];
+        for (principal, expected) in cases {
+            let principal = Principal::AWS(principal.parse().unwrap());
+            assert_eq!(expected, policy.check(&principal, &action, &resource, &context).unwrap(), "{}", principal);
+        }
+        let service = Principal::Service("ecs.amazonaws.com".to_string());
+        assert_eq!(CheckResult::Deny, policy.check(&service, &action, &resource, &context).unwrap());
+
+        // The deny does not extend to resources outside the bucket
+        let other = "arn:aws:s3:::OTHERBUCKET/key".parse().unwrap();
+        let alice = Principal::AWS("arn:aws:iam::444455556666:user/Alice".parse().unwrap());
+        assert_eq!(CheckResult::Unspecified, policy.check(&alice, &action, &other, &context).unwrap());
+    }
+
+    #[test]
+    fn bool_false_target() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {
+                    "Effect": "Deny",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": {"Bool": {"aws:SecureTransport": "false"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let cases = [
+            (r#"{"global": {"aws:SecureTransport": "false"}}"#, CheckResult::Deny),
+            (r#"{"global": {"aws:SecureTransport": "true"}}"#, CheckResult::Allow),
+            // Without the key, the Bool condition does not match, so the deny does not apply
+            (r#"{"global": {}}"#, CheckResult::Allow),
+        ];
+        for (source, expected) in cases {
+            let context: Context = source.parse().unwrap();
+            assert_eq!(expected, policy.check_action(&action, &resource, &context).unwrap(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn overlapping_ip_conditions() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": {"IpAddress": {"aws:SourceIp": "10.0.0.0/8"}}
+                },
+                {
+                    "Effect": "Deny",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": {"IpAddress": {"aws:SourceIp": ["10.1.0.0/16", "192.0.2.0/24"]}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let cases = [
+            ("10.2.3.4", CheckResult::Allow),
+            ("10.1.2.3", CheckResult::Deny),
+            ("192.0.2.1", CheckResult::Deny),
+            ("198.51.100.1", CheckResult::Unspecified),
+        ];
+        for (ip, expected) in cases {
+            assert_eq!(expected, policy.check_source_ip(ip.parse().unwrap(), &action, &resource, &context).unwrap(), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn condition_keys_and_blocks_are_anded() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {
+                    "StringEquals": {"aws:PrincipalTag/team": "storage", "aws:PrincipalTag/env": "prod"},
+                    "IpAddress": {"aws:SourceIp": "203.0.113.0/24"}
+                }
+            }
+        }"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let check = |context: &str| policy.check_action(&action, &resource, &context.parse().unwrap()).unwrap();
+        assert_eq!(CheckResult::Allow, check(r#"{"global": {
+            "aws:PrincipalTag/team": "storage", "aws:PrincipalTag/env": "prod", "aws:SourceIp": "203.0.113.5"
+        }}"#));
+        // One key in the StringEquals block fails, so the whole block fails
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {
+            "aws:PrincipalTag/team": "storage", "aws:PrincipalTag/env": "dev", "aws:SourceIp": "203.0.113.5"
+        }}"#));
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {
+            "aws:PrincipalTag/team": "storage", "aws:SourceIp": "203.0.113.5"
+        }}"#));
+        // The StringEquals block matches, but the IpAddress block does not
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {
+            "aws:PrincipalTag/team": "storage", "aws:PrincipalTag/env": "prod", "aws:SourceIp": "198.51.100.5"
+        }}"#));
+    }
+
+    #[test]
+    fn source_ip_constraints() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": {"IpAddress": {"aws:SourceIp": ["203.0.113.0/24", "198.51.100.0/24"]}}
+                },
+                {
+                    "Effect": "Deny",
+                    "Action": "s3:DeleteObject",
+                    "Resource": "*",
+                    "Condition": {
+                        "NotIpAddress": {"aws:VpcSourceIp": "10.0.0.0/8"},
+                        "StringEquals": {"aws:SourceIp": "192.0.2.0/24"}
+                    }
+                },
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:GetObject",
+                    "Resource": "*",
+                    "Condition": {"IpAddress": {"aws:SourceIp": "203.0.113.0/24"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let networks = |values: &[&str]| -> Vec<IpNetwork> { values.iter().map(|value| value.parse().unwrap()).collect() };
+        assert_eq!(policy.source_ip_constraints_for(Effect::Allow), networks(&["203.0.113.0/24", "198.51.100.0/24"]));
+        assert_eq!(policy.source_ip_constraints_for(Effect::Deny), networks(&["10.0.0.0/8"]));
+        assert_eq!(policy.source_ip_constraints(), networks(&["203.0.113.0/24", "198.51.100.0/24", "10.0.0.0/8"]));
+    }
+
+    #[test]
+    fn typed_condition_keys() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:GetObject",
+                    "Resource": "*",
+                    "Condition": {"IpAddress": {"aws:SourceIp": "203.0.113.0/24"}}
+                },
+                {
+                    "Effect": "Deny",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": {
+                        "NotIpAddress": {"aws:SourceIp": "203.0.113.0/24"},
+                        "StringEquals": {"example:Custom": "value", "aws:PrincipalTag/team": "storage"}
+                    }
+                },
+                {"Effect": "Allow", "Action": "s3:ListBucket", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        assert_eq!(policy.typed_condition_keys(), vec![
+            ("aws:PrincipalTag/team".to_string(), Some(Type::String)),
+            ("aws:SourceIp".to_string(), Some(Type::IpAddress)),
+            ("example:Custom".to_string(), None),
+        ]);
+    }
+
+    #[test]
+    fn source_vpc() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:*", "Resource": "*"},
+                {
+                    "Sid": "DenyOutsideVpc",
+                    "Effect": "Deny",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": {"StringNotEquals": {"aws:SourceVpc": "vpc-12345"}}
+                },
+                {
+                    "Sid": "DenyFromEndpoint",
+                    "Effect": "Deny",
+                    "Action": "s3:DeleteObject",
+                    "Resource": "*",
+                    "Condition": {"StringEquals": {"aws:SourceVpce": "vpce-1a2b3c4d"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let check = |action: &str, context: &str| policy.check_action(&action.parse().unwrap(), &resource, &context.parse().unwrap()).unwrap();
+
+        // Present and matching
+        assert_eq!(CheckResult::Allow, check("s3:GetObject", r#"{"global": {"aws:SourceVpc": "vpc-12345"}}"#));
+        // Present and mismatching
+        assert_eq!(CheckResult::Deny, check("s3:GetObject", r#"{"global": {"aws:SourceVpc": "vpc-67890"}}"#));
+        // A request that does not come through a VPC lacks the key, so the
+        // negated condition matches and the request is denied
+        assert_eq!(CheckResult::Deny, check("s3:GetObject", r#"{"global": {}}"#));
+
+        let vpce = r#"{"global": {"aws:SourceVpc": "vpc-12345", "aws:SourceVpce": "vpce-1a2b3c4d"}}"#;
+        assert_eq!(CheckResult::Deny, check("s3:DeleteObject", vpce));
+        let other_vpce = r#"{"global": {"aws:SourceVpc": "vpc-12345", "aws:SourceVpce": "vpce-99999999"}}"#;
+        assert_eq!(CheckResult::Allow, check("s3:DeleteObject", other_vpce));
+        // Without the key, a positive condition never matches
+        assert_eq!(CheckResult::Allow, check("s3:DeleteObject", r#"{"global": {"aws:SourceVpc": "vpc-12345"}}"#));
+    }
+
+    #[test]
+    fn mfa_guardrail() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "ec2:*", "Resource": "*"},
+                {
+                    "Sid": "DenyWithoutMFA",
+                    "Effect": "Deny",
+                    "Action": "*",
+                    "Resource": "*",
+                    "Condition": {"BoolIfExists": {"aws:MultiFactorAuthPresent": "false"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        // The Deny is conditional, so it is not a blanket deny
+        assert!(!policy.has_blanket_deny());
+        let action = "ec2:RunInstances".parse().unwrap();
+        let resource = "arn:aws:ec2:us-east-1:123456789012:instance/*".parse().unwrap();
+        let check = |context: &str| policy.check_action(&action, &resource, &context.parse().unwrap()).unwrap();
+        assert_eq!(CheckResult::Allow, check(r#"{"global": {"aws:MultiFactorAuthPresent": "true"}}"#));
+        assert_eq!(CheckResult::Deny, check(r#"{"global": {"aws:MultiFactorAuthPresent": "false"}}"#));
+        // Long-term credentials omit the key, which BoolIfExists also denies
+        assert_eq!(CheckResult::Deny, check(r#"{"global": {}}"#));
+    }
+
+    #[test]
+    fn epoch_time() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:GetObject",
+                    "Resource": "*",
+                    "Condition": {"NumericGreaterThan": {"aws:EpochTime": "1700000000"}}
+                },
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:PutObject",
+                    "Resource": "*",
+                    "Condition": {"DateGreaterThan": {"aws:EpochTime": "2023-11-14T22:13:20Z"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        for action in ["s3:GetObject", "s3:PutObject"] {
+            let action = action.parse().unwrap();
+            let check = |seconds: i64| {
+                let mut context = Context::new();
+                context.set_current_time(Utc.timestamp_opt(seconds, 0).unwrap());
+                policy.check_action(&action, &resource, &context).unwrap()
+            };
+            assert_eq!(CheckResult::Allow, check(1_700_000_001), "{}", action);
+            assert_eq!(CheckResult::Unspecified, check(1_700_000_000), "{}", action);
+            assert_eq!(CheckResult::Unspecified, check(1_600_000_000), "{}", action);
+        }
+    }
+
+    #[test]
+    fn recent_mfa() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "iam:*",
+                    "Resource": "*",
+                    "Condition": {"NumericLessThanEquals": {"aws:MultiFactorAuthAge": "3600"}}
+                },
+                {
+                    "Effect": "Allow",
+                    "Action": "ec2:*",
+                    "Resource": "*",
+                    "Condition": {"ForAnyValue:NumericLessThanEquals": {"aws:MultiFactorAuthAge": "3600"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let resource = "arn:aws:iam::123456789012:user/Alice".parse().unwrap();
+        for action in ["iam:CreateUser", "ec2:RunInstances"] {
+            let action = action.parse().unwrap();
+            let check = |context: &str| policy.check_action(&action, &resource, &context.parse().unwrap()).unwrap();
+            assert_eq!(CheckResult::Allow, check(r#"{"global": {"aws:MultiFactorAuthAge": "300"}}"#));
+            assert_eq!(CheckResult::Allow, check(r#"{"global": {"aws:MultiFactorAuthAge": "3600"}}"#));
+            assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"aws:MultiFactorAuthAge": "7200"}}"#));
+            // Without MFA the key is absent, which never matches
+            assert_eq!(CheckResult::Unspecified, check(r#"{"global": {}}"#));
+        }
+    }
+
+    #[test]
+    fn same_key_under_different_operators() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:ListBucket",
+                "Resource": "arn:aws:s3:::bucket",
+                "Condition": {
+                    "StringLike": {"s3:prefix": "home/*"},
+                    "StringNotEquals": {"s3:prefix": "home/admin"}
+                }
+            }
+        }"#.parse().unwrap();
+        let action = "s3:ListBucket".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket".parse().unwrap();
+        let check = |context: &str| policy.check_action(&action, &resource, &context.parse().unwrap()).unwrap();
+        assert_eq!(CheckResult::Allow, check(r#"{"global": {"s3:prefix": "home/alice"}}"#));
+        // Satisfies StringLike but not StringNotEquals
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"s3:prefix": "home/admin"}}"#));
+        // Satisfies StringNotEquals but not StringLike
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"s3:prefix": "tmp/"}}"#));
+    }
+
+    #[test]
+    fn current_time_window() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {
+                    "DateGreaterThan": {"aws:CurrentTime": "2020-04-01T00:00:00Z"},
+                    "DateLessThan": {"aws:CurrentTime": "2020-06-30T23:59:59Z"}
+                }
+            }
+        }"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let check_at = |now| {
+            let mut context = Context::new();
+            context.set_current_time(now);
+            policy.check_action(&action, &resource, &context).unwrap()
+        };
+        assert_eq!(CheckResult::Allow, check_at(Utc.ymd(2020, 5, 15).and_hms(12, 0, 0)));
+        assert_eq!(CheckResult::Allow, check_at(Utc.ymd(2020, 4, 1).and_hms(0, 0, 1)));
+        // Both bounds are exclusive
+        assert_eq!(CheckResult::Unspecified, check_at(Utc.ymd(2020, 4, 1).and_hms(0, 0, 0)));
+        assert_eq!(CheckResult::Unspecified, check_at(Utc.ymd(2020, 6, 30).and_hms(23, 59, 59)));
+        assert_eq!(CheckResult::Unspecified, check_at(Utc.ymd(2020, 3, 31).and_hms(12, 0, 0)));
+        assert_eq!(CheckResult::Unspecified, check_at(Utc.ymd(2020, 7, 1).and_hms(12, 0, 0)));
+    }
+
+    #[test]
+    fn allowed_tag_keys() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "ec2:CreateTags",
+                "Resource": "*",
+                "Condition": {"ForAllValues:StringEquals": {"aws:TagKeys": ["Env", "Owner"]}}
+            }
+        }"#.parse().unwrap();
+        let action = "ec2:CreateTags".parse().unwrap();
+        let resource = "arn:aws:ec2:us-east-1:123456789012:instance/i-1234567890abcdef0".parse().unwrap();
+        let check = |context: &str| policy.check_action(&action, &resource, &context.parse().unwrap()).unwrap();
+        assert_eq!(CheckResult::Allow, check(r#"{"global": {"aws:TagKeys": ["Env"]}}"#));
+        assert_eq!(CheckResult::Allow, check(r#"{"global": {"aws:TagKeys": ["Owner", "Env"]}}"#));
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"aws:TagKeys": ["Env", "Secret"]}}"#));
+        // Tag keys are compared case-sensitively by StringEquals
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"aws:TagKeys": ["env"]}}"#));
+    }
+
+    #[test]
+    fn policy_variables() {
+        let source = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "Home", "Effect": "Allow", "Action": "s3:*", "Resource": "arn:aws:s3:::bucket/home/${aws:username}/*"},
+                {"Sid": "Shared", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/shared/${aws:PrincipalTag/team, 'everyone'}/*"},
+                {"Sid": "Star", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/literal/${*}"},
+                {"Sid": "OwnRole", "Effect": "Allow", "Action": "iam:GetRole", "Resource": "arn:aws:iam::${aws:PrincipalAccount}:role/${aws:username}"},
+                {"Sid": "Tagged", "Effect": "Allow", "Action": "ec2:StartInstances", "Resource": "*", "Condition": {"StringEquals": {"aws:ResourceTag/owner": "${aws:username}"}}},
+                {"Sid": "Prefix", "Effect": "Allow", "Action": "s3:ListBucket", "Resource": "arn:aws:s3:::bucket", "Condition": {"StringLike": {"s3:prefix": "home/${aws:username}/${*}"}}}
+            ]
+        }"#;
+        let policy: Policy = source.parse().unwrap();
+        let alice: Context = r#"{"global": {"aws:username": "alice", "aws:PrincipalAccount": "123456789012", "aws:PrincipalTag/team": "storage"}}"#.parse().unwrap();
+        let check = |context: &Context, action: &str, resource: &str| {
+            policy.check_action(&action.parse().unwrap(), &resource.parse().unwrap(), context).unwrap()
+        };
+
+        assert_eq!(CheckResult::Allow, check(&alice, "s3:PutObject", "arn:aws:s3:::bucket/home/alice/notes.txt"));
+        assert_eq!(CheckResult::Unspecified, check(&alice, "s3:PutObject", "arn:aws:s3:::bucket/home/bob/notes.txt"));
+        assert_eq!(CheckResult::Allow, check(&alice, "s3:GetObject", "arn:aws:s3:::bucket/shared/storage/file"));
+        assert_eq!(CheckResult::Unspecified, check(&alice, "s3:GetObject", "arn:aws:s3:::bucket/shared/everyone/file"));
+        // ${*} is a literal asterisk
+        assert_eq!(CheckResult::Allow, check(&alice, "s3:GetObject", "arn:aws:s3:::bucket/literal/*"));
+        assert_eq!(CheckResult::Unspecified, check(&alice, "s3:GetObject", "arn:aws:s3:::bucket/literal/file"));
+        // A variable can supply a segment other than the resource
+        assert_eq!(CheckResult::Allow, check(&alice, "iam:GetRole", "arn:aws:iam::123456789012:role/alice"));
+        assert_eq!(CheckResult::Unspecified, check(&alice, "iam:GetRole", "arn:aws:iam::210987654321:role/alice"));
+
+        let mut resource_context = alice.clone();
+        resource_context.merge(r#"{"resources": {"arn:aws:ec2:us-east-1:123456789012:instance/i-1": {"aws:ResourceTag/owner": "alice"}}}"#.parse().unwrap());
+        assert_eq!(CheckResult::Allow, check(&resource_context, "ec2:StartInstances", "arn:aws:ec2:us-east-1:123456789012:instance/i-1"));
+        assert_eq!(CheckResult::Unspecified, check(&resource_context, "ec2:StartInstances", "arn:aws:ec2:us-east-1:123456789012:instance/i-2"));
+
+        let mut prefix_context = alice.clone();
+        prefix_context.insert_global("s3:prefix", vec!["home/alice/*".to_string()]);
+        assert_eq!(CheckResult::Allow, check(&prefix_context, "s3:ListBucket", "arn:aws:s3:::bucket"));
+        prefix_context.insert_global("s3:prefix", vec!["home/alice/docs".to_string()]);
+        assert_eq!(CheckResult::Unspecified, check(&prefix_context, "s3:ListBucket", "arn:aws:s3:::bucket"));
+
+        // Without aws:username, statements that use it do not match, while
+        // the default covers a missing tag
+        let anonymous = Context::new();
+        assert_eq!(CheckResult::Unspecified, check(&anonymous, "s3:PutObject", "arn:aws:s3:::bucket/home/${aws:username}/file"));
+        assert_eq!(CheckResult::Allow, check(&anonymous, "s3:GetObject", "arn:aws:s3:::bucket/shared/everyone/file"));
+        let stmt = policy.statement_by_sid("Home").unwrap();
+        let outcome = stmt.explain(None, &"s3:PutObject".parse().unwrap(), &"arn:aws:s3:::bucket/home/alice/file".parse().unwrap(), &anonymous).unwrap();
+        assert_eq!(outcome, MatchOutcome::MissingVariable("aws:username".to_string()));
+
+        // A multi-valued key cannot be substituted
+        let multi: Context = r#"{"global": {"aws:username": ["alice", "bob"]}}"#.parse().unwrap();
+        assert!(policy.check_action(&"s3:PutObject".parse().unwrap(), &"arn:aws:s3:::bucket/home/alice/file".parse().unwrap(), &multi).is_err());
+
+        // Earlier versions of the policy language treat variables as text
+        let legacy: Policy = source.replace("2012-10-17", "2008-10-17").parse().unwrap();
+        let action = "s3:PutObject".parse().unwrap();
+        assert_eq!(CheckResult::Unspecified, legacy.check_action(&action, &"arn:aws:s3:::bucket/home/alice/file".parse().unwrap(), &alice).unwrap());
+        assert_eq!(CheckResult::Allow, legacy.check_action(&action, &"arn:aws:s3:::bucket/home/${aws:username}/file".parse().unwrap(), &alice).unwrap());
+    }
+
+    #[test]
+    fn request_tag() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "ec2:RunInstances",
+                "Resource": "*",
+                "Condition": {"StringEquals": {"aws:RequestTag/Department": "Engineering"}}
+            }
+        }"#.parse().unwrap();
+        let action = "ec2:RunInstances".parse().unwrap();
+        let resource = "arn:aws:ec2:us-east-1:123456789012:instance/*".parse().unwrap();
+        let check = |context: &str| policy.check_action(&action, &resource, &context.parse().unwrap()).unwrap();
+        assert_eq!(CheckResult::Allow, check(r#"{"global": {"aws:RequestTag/Department": ["Engineering"]}}"#));
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"aws:RequestTag/Department": ["Finance"]}}"#));
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"aws:RequestTag/Owner": ["Engineering"]}}"#));
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {}}"#));
+        // The tag key after the slash is case-sensitive
+        assert_eq!(CheckResult::Unspecified, check(r#"{"global": {"aws:RequestTag/department": ["Engineering"]}}"#));
+    }
+
+    #[test]
+    fn from_managed_bundle() {
+        let bundle = ManagedPolicyBundle::from([
+            (
+                "arn:aws:iam::aws:policy/AmazonS3ReadOnlyAccess".to_string(),
+                r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": ["s3:Get*", "s3:List*"], "Resource": "*"}}"#.to_string(),
+            ),
+            ("arn:aws:iam::aws:policy/Broken".to_string(), r#"{"Version": "2012-10-17"}"#.to_string()),
+        ]);
+        let policy = Policy::from_managed_bundle(&bundle, "arn:aws:iam::aws:policy/AmazonS3ReadOnlyAccess").unwrap();
+        let context = Context::new();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(CheckResult::Allow, policy.check_action(&"s3:GetObject".parse().unwrap(), &resource, &context).unwrap());
+        assert_eq!(CheckResult::Unspecified, policy.check_action(&"s3:PutObject".parse().unwrap(), &resource, &context).unwrap());
+
+        assert!(Policy::from_managed_bundle(&bundle, "arn:aws:iam::aws:policy/Missing").is_err());
+        assert!(Policy::from_managed_bundle(&bundle, "arn:aws:iam::aws:policy/Broken").is_err());
+    }
+
+    #[test]
+    fn single_statement_equals_one_element_array() {
+        let single: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Sid": "Read", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}
+        }"#.parse().unwrap();
+        let array: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [{"Sid": "Read", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}]
+        }"#.parse().unwrap();
+        assert_eq!(single, array);
+        assert_eq!(json::JsonValue::from(&single), json::JsonValue::from(&array));
+
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(single.check_action(&action, &resource, &context).unwrap(), array.check_action(&action, &resource, &context).unwrap());
+
+        let other: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [{"Sid": "Read", "Effect": "Allow", "Action": "s3:PutObject", "Resource": "arn:aws:s3:::bucket/*"}]
+        }"#.parse().unwrap();
+        assert_ne!(single, other);
+    }
+
+    #[test]
+    fn size_check() {
+        // Pads a policy to an exact size using the Id element
+        let sized = |size: usize| -> Policy {
+            let template = r#"{"Version":"2012-10-17","Id":"ID","Statement":[{"Effect":"Allow","Action":"s3:GetObject","Resource":"*"}]}"#;
+            let id = "x".repeat(size + 2 - template.len());
+            template.replace("ID", &id).parse().unwrap()
+        };
+        assert_eq!(sized(6144).size_check(PolicyKind::Managed), Ok(6144));
+        assert_eq!(sized(6145).size_check(PolicyKind::Managed), Err(PolicyTooLarge{ kind: PolicyKind::Managed, size: 6145, limit: 6144 }));
+        assert!(sized(6145).size_check(PolicyKind::InlineRole).is_ok());
+        assert!(sized(2049).size_check(PolicyKind::InlineUser).is_err());
+
+        // Whitespace does not count
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}
+        }"#.parse().unwrap();
+        let compact = r#"{"Version":"2012-10-17","Statement":[{"Effect":"Allow","Action":"s3:GetObject","Resource":"*"}]}"#;
+        assert_eq!(policy.size_check(PolicyKind::Managed), Ok(compact.len()));
+    }
+
+    #[test]
+    fn to_pretty_json() {
+        let source = r#"{"Version":"2012-10-17","Id":"Example","Statement":[{"Sid":"Read","Effect":"Allow","Principal":{"AWS":["arn:aws:iam::123456789012:root","arn:aws:iam::210987654321:role/Reader"],"Service":"ec2.amazonaws.com"},"Action":["s3:GetObject","s3:ListBucket"],"Resource":"arn:aws:s3:::bucket/*","Condition":{"StringEquals":{"aws:username":"alice"}}},{"Effect":"Deny","NotAction":"s3:*","NotResource":["arn:aws:s3:::bucket","arn:aws:s3:::bucket/*"]}]}"#;
+        let policy: Policy = source.parse().unwrap();
+        let pretty = policy.to_pretty_json(2);
+        assert!(pretty.contains("\n  \"Statement\": [\n"));
+        let reparsed: Policy = pretty.parse().unwrap();
+        assert_eq!(policy, reparsed);
+        assert_eq!(json::parse(source).unwrap(), json::parse(&pretty).unwrap());
+    }
+
+    #[test]
+    fn version_capabilities() {
+        let policy = |version: Option<&str>| Policy{ version: version.map(String::from), id: None, statements: Vec::new() };
+        let current = policy(Some(VERSION_2012_10_17));
+        assert!(!current.is_legacy());
+        assert!(current.supports_variables());
+        let legacy = policy(Some(VERSION_2008_10_17));
+        assert!(legacy.is_legacy());
+        assert!(!legacy.supports_variables());
+        let missing = policy(None);
+        assert!(missing.is_legacy());
+        assert!(!missing.supports_variables());
+
+        let parsed: Policy = r#"{"Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}}"#.parse().unwrap();
+        assert!(parsed.is_legacy());
+    }
+
+    #[test]
+    fn filter() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Id": "Example",
+            "Statement": [
+                {"Sid": "Read", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Sid": "NoDelete", "Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*"},
+                {"Sid": "Write", "Effect": "Allow", "Action": "s3:PutObject", "Resource": "*"},
+                {"Sid": "NoIam", "Effect": "Deny", "Action": "iam:*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let denies = policy.filter(|stmt| stmt.effect == Effect::Deny);
+        assert_eq!(denies.version.as_deref(), Some("2012-10-17"));
+        assert_eq!(denies.id.as_deref(), Some("Example"));
+        let sids: Vec<&str> = denies.iter().filter_map(|stmt| stmt.sid.as_deref()).collect();
+        assert_eq!(sids, vec!["NoDelete", "NoIam"]);
+
+        let serialized = json::JsonValue::from(&denies);
+        assert_eq!(serialized, json::parse(r#"{
+            "Version": "2012-10-17",
+            "Id": "Example",
+            "Statement": [
+                {"Sid": "NoDelete", "Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*"},
+                {"Sid": "NoIam", "Effect": "Deny", "Action": "iam:*", "Resource": "*"}
+            ]
+        }"#).unwrap());
+
+        assert!(policy.filter(|_| false).statements.is_empty());
+        assert_eq!(policy.filter(|_| true), policy);
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let source = "\u{feff}\n  {\"Version\": \"2012-10-17\", \"Statement\": {\"Effect\": \"Allow\", \"Action\": \"s3:GetObject\", \"Resource\": \"*\"}}\r\n\n";
+        let policy: Policy = source.parse().unwrap();
+        assert_eq!(policy.statements.len(), 1);
+        assert_eq!(Policy::parse_with_options(source, &ParseOptions::default()).unwrap(), policy);
+        assert!(Policy::validate_json(source).is_ok());
+        // Only a leading BOM is stripped
+        assert!(Policy::validate_json("{}\u{feff}").is_err());
+    }
+
+    #[test]
+    fn from_jsonc() {
+        let source = r#"// Read-only access to the bucket
+        {
+            "Version": "2012-10-17", /* the current version */
+            "Statement": [
+                {
+                    "Sid": "Read // not a comment",
+                    "Effect": "Allow",
+                    /* Listing is granted
+                       separately */
+                    "Action": "s3:GetObject", // objects only
+                    "Resource": "arn:aws:s3:::bucket/*/* \" /* still a string"
+                }
+            ]
+        }
+        "#;
+        let policy = Policy::from_jsonc(source).unwrap();
+        assert_eq!(policy.statements.len(), 1);
+        assert_eq!(policy.statements[0].sid.as_deref(), Some("Read // not a comment"));
+        assert_eq!(CheckResult::Allow, policy.check_strings(None, "s3:GetObject", "arn:aws:s3:::bucket/a/b \" /* still a string", None).unwrap());
+        assert!(source.parse::<Policy>().is_err());
+
+        assert!(Policy::from_jsonc(r#"{"Version": "2012-10-17", "Statement": []} /* unterminated"#).is_err());
+        // A comment directly between tokens
+        assert!(Policy::from_jsonc(r#"{"Version": "2012-10-17", "Statement": []/**/}"#).is_ok());
+    }
+
+    #[test]
+    fn lenient_unknown_operator() {
+        let source = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {
+                    "Effect": "Deny",
+                    "Action": "s3:GetObject",
+                    "Resource": "*",
+                    "Condition": {"StringMatches": {"aws:username": "alice"}}
+                }
+            ]
+        }"#;
+        assert!(source.parse::<Policy>().is_err());
+
+        let policy = Policy::parse_with_options(source, &ParseOptions::lenient()).unwrap();
+        let context: Context = r#"{"global": {"aws:username": "alice"}}"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(CheckResult::Allow, policy.check_action(&action, &resource, &context).unwrap());
+
+        // An unknown operator does not match a missing key, so it cannot
+        // grant access in an Allow either
+        for condition in [r#"{"StringMatchesIfExists": {"aws:username": "alice"}}"#, r#"{"ForAllValues:StringMatches": {"aws:TagKeys": "Env"}}"#] {
+            let source = format!(r#"{{"Version": "2012-10-17", "Statement": {{"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*", "Condition": {}}}}}"#, condition);
+            let policy = Policy::parse_with_options(&source, &ParseOptions::lenient()).unwrap();
+            assert_eq!(CheckResult::Unspecified, policy.check_action(&action, &resource, &Context::new()).unwrap());
+        }
+    }
+
+    #[test]
+    fn invert() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Id": "Grants",
+            "Statement": [
+                {"Sid": "Read", "Effect": "Allow", "Action": ["s3:GetObject", "s3:ListBucket"], "Resource": "arn:aws:s3:::bucket/*"},
+                {"Sid": "NoDelete", "Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*", "Condition": {"Bool": {"aws:MultiFactorAuthPresent": "false"}}}
+            ]
+        }"#.parse().unwrap();
+        let inverted = policy.invert();
+        assert_eq!(inverted.statements[0].effect, Effect::Deny);
+        assert_eq!(inverted.statements[1].effect, Effect::Allow);
+        // Everything apart from the effect is unchanged
+        assert_eq!(inverted.id, policy.id);
+        assert_eq!(inverted.version, policy.version);
+        for (inverted, original) in inverted.statements.iter().zip(&policy.statements) {
+            assert_eq!(Statement{ effect: original.effect, ..inverted.clone() }, *original);
+        }
+        assert_eq!(inverted.invert(), policy);
+
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(CheckResult::Deny, inverted.check_action(&action, &resource, &context).unwrap());
+    }
+
+    #[test]
+    fn check_statement() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "AllowRead", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Sid": "DenySecret", "Effect": "Deny", "Action": "s3:*", "Resource": "arn:aws:s3:::secret/*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::secret/key".parse().unwrap();
+        assert_eq!(CheckResult::Deny, policy.check_action(&action, &resource, &context).unwrap());
+        assert_eq!(CheckResult::Allow, policy.check_statement("AllowRead", None, &action, &resource, &context).unwrap());
+        assert_eq!(CheckResult::Deny, policy.check_statement("DenySecret", None, &action, &resource, &context).unwrap());
+        assert!(policy.check_statement("Missing", None, &action, &resource, &context).is_err());
+    }
+
+    #[test]
+    fn check_principals() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:role/*"}, "Action": "s3:GetObject", "Resource": "*"},
+                {"Effect": "Deny", "Principal": {"AWS": "arn:aws:iam::123456789012:role/Untrusted"}, "Action": "s3:*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let reader = Principal::AWS("arn:aws:iam::123456789012:role/Reader".parse().unwrap());
+        let untrusted = Principal::AWS("arn:aws:iam::123456789012:role/Untrusted".parse().unwrap());
+        let service = Principal::Service("ec2.amazonaws.com".to_string());
+        let principals = [reader.clone(), untrusted.clone(), service.clone()];
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let results = policy.check_principals(&principals, &action, &resource, &Context::new()).unwrap();
+        assert_eq!(results, vec![
+            (reader, CheckResult::Allow),
+            (untrusted, CheckResult::Deny),
+            (service, CheckResult::Unspecified),
+        ]);
+    }
+
+    #[test]
+    fn on_condition_error() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Sid": "BadDate",
+                    "Effect": "Allow",
+                    "Action": "s3:GetObject",
+                    "Resource": "*",
+                    "Condition": {"DateLessThan": {"aws:CurrentTime": "tomorrow"}}
+                },
+                {"Effect": "Allow", "Action": "s3:ListBucket", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let context: Context = r#"{"global": {"aws:CurrentTime": "2020-04-01T00:00:00Z"}}"#.parse().unwrap();
+        let get = "s3:GetObject".parse().unwrap();
+        let list = "s3:ListBucket".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket".parse().unwrap();
+        let fail = EvaluationOptions::default();
+        let skip = EvaluationOptions{ on_condition_error: OnConditionError::Skip, ..EvaluationOptions::default() };
+
+        assert!(policy.check_action(&get, &resource, &context).is_err());
+        assert!(policy.check_with_options(None, &get, &resource, &context, &fail).is_err());
+        assert_eq!(CheckResult::Unspecified, policy.check_with_options(None, &get, &resource, &context, &skip).unwrap());
+        // The erroring statement is skipped, but later statements still apply
+        assert!(policy.check_with_options(None, &list, &resource, &context, &fail).is_ok());
+        assert_eq!(CheckResult::Allow, policy.check_with_options(None, &list, &resource, &context, &skip).unwrap());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_events() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        #[derive(Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        struct Fields(String);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.push_str(&format!(" {}={:?}", field.name(), value));
+            }
+        }
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool { true }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut fields = Fields(format!("span {}", span.metadata().name()));
+                span.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut fields = Fields("event".to_string());
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "AllowRead", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:PutObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let recorder = Recorder::default();
+        let records = recorder.0.clone();
+        tracing::subscriber::with_default(recorder, || {
+            let result = policy.check_action(&"s3:GetObject".parse().unwrap(), &"arn:aws:s3:::bucket/key".parse().unwrap(), &Context::new());
+            assert_eq!(CheckResult::Allow, result.unwrap());
+        });
+        let records = records.lock().unwrap();
+        assert_eq!(records[0], "span check action=s3:GetObject resource=arn:aws:s3:::bucket/key");
+        assert!(records[1].contains("index=0 sid=Some(\"AllowRead\") result=Allow"), "{}", records[1]);
+        assert!(records[2].contains("index=1 sid=None result=Unspecified"), "{}", records[2]);
+    }
+
+    #[test]
+    fn condition_error_in_later_deny() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:*", "Resource": "*"},
+                {
+                    "Sid": "BadDeny",
+                    "Effect": "Deny",
+                    "Action": "s3:DeleteObject",
+                    "Resource": "*",
+                    "Condition": {"DateGreaterThan": {"aws:CurrentTime": "not-a-date"}}
+                },
+                {"Effect": "Deny", "Action": "s3:PutObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let context: Context = r#"{"global": {"aws:CurrentTime": "2020-04-01T00:00:00Z"}}"#.parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let delete = "s3:DeleteObject".parse().unwrap();
+        let put = "s3:PutObject".parse().unwrap();
+        let fail = EvaluationOptions::default();
+        let skip = EvaluationOptions{ on_condition_error: OnConditionError::Skip, ..EvaluationOptions::default() };
+
+        // Failing discards the earlier Allow
+        assert!(policy.check_with_options(None, &delete, &resource, &context, &fail).is_err());
+        // Skipping treats the Deny as not matching, preserving the Allow
+        assert_eq!(CheckResult::Allow, policy.check_with_options(None, &delete, &resource, &context, &skip).unwrap());
+        // A Deny after the erroring statement still overrides the Allow
+        assert_eq!(CheckResult::Deny, policy.check_with_options(None, &put, &resource, &context, &skip).unwrap());
+        // The erroring statement is never evaluated for other actions
+        assert_eq!(CheckResult::Deny, policy.check_with_options(None, &put, &resource, &context, &fail).unwrap());
+    }
+
+    #[test]
+    fn check_with_budget() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "ec2:*", "Resource": "*"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+                {"Effect": "Allow", "Action": "s3:PutObject", "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let get = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        // After the allow, only the deny still needs evaluating
+        assert_eq!(CheckResult::Allow, policy.check_with_budget(None, &get, &resource, &context, 3).unwrap());
+
+        let put = "s3:PutObject".parse().unwrap();
+        let err = policy.check_with_budget(None, &put, &resource, &context, 2).unwrap_err();
+        assert_eq!(err.downcast_ref::<EvaluationError>(), Some(&EvaluationError::BudgetExceeded));
+        assert_eq!(err.to_string(), "evaluation needed more statements than its budget allows");
+        assert_eq!(CheckResult::Allow, policy.check_with_budget(None, &put, &resource, &context, 4).unwrap());
+
+        let alice = Principal::AWS("arn:aws:iam::123456789012:user/Alice".parse().unwrap());
+        assert!(policy.check_with_budget(Some(&alice), &put, &resource, &context, 0).is_err());
+    }
+
+    #[test]
+    fn blanket_deny() {
+        // The condition on the allow would fail to evaluate, so a result
+        // shows the deny short-circuited evaluation.
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": {"DateGreaterThan": {"aws:CurrentTime": "not-a-date"}}
+                },
+                {"Effect": "Deny", "Action": "*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        assert!(policy.has_blanket_deny());
+        let context: Context = r#"{"global": {"aws:CurrentTime": "2020-04-01T00:00:00Z"}}"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let alice = Principal::AWS("arn:aws:iam::123456789012:user/Alice".parse().unwrap());
+        assert_eq!(CheckResult::Deny, policy.check_action(&action, &resource, &context).unwrap());
+        assert_eq!(CheckResult::Deny, policy.check(&alice, &action, &resource, &context).unwrap());
+    }
+
+    #[test]
+    fn conditional_deny_is_not_blanket() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:*", "Resource": "*"},
+                {
+                    "Effect": "Deny",
+                    "Action": "*",
+                    "Resource": "*",
+                    "Condition": {"Bool": {"aws:MultiFactorAuthPresent": "false"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        assert!(!policy.has_blanket_deny());
+        let context: Context = r#"{"global": {"aws:MultiFactorAuthPresent": "true"}}"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(CheckResult::Allow, policy.check_action(&action, &resource, &context).unwrap());
+
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Deny", "Action": "*", "NotResource": "arn:aws:s3:::bucket/*"}
+        }"#.parse().unwrap();
+        assert!(!policy.has_blanket_deny());
+    }
+
+    #[test]
+    fn service_wildcard_on_any_resource() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:*",
+                "Resource": "*"
+            }
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let cases = [
+            ("s3:GetObject", "arn:aws:s3:::bucket/key", CheckResult::Allow),
+            ("s3:ListBucket", "arn:aws:s3:::bucket", CheckResult::Allow),
+            ("s3:GetObject", "arn:aws:iam::123456789012:user/Alice", CheckResult::Allow),
+            ("ec2:RunInstances", "arn:aws:ec2:us-east-1:123456789012:instance/*", CheckResult::Unspecified),
+            ("ec2:RunInstances", "arn:aws:s3:::bucket", CheckResult::Unspecified),
+        ];
+        for (action, resource, expected) in cases {
+            let result = policy.check_action(&action.parse().unwrap(), &resource.parse().unwrap(), &context).unwrap();
+            assert_eq!(expected, result, "{} on {}", action, resource);
+        }
+    }
+
+    #[test]
+    fn action_with_not_resource() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "NotResource": "arn:aws:s3:::secret/*"}
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let cases = [
+            ("s3:GetObject", "arn:aws:s3:::bucket/key", CheckResult::Allow),
+            ("s3:GetObject", "arn:aws:s3:::secret-archive/key", CheckResult::Allow),
+            ("s3:GetObject", "arn:aws:s3:::secret/key", CheckResult::Unspecified),
+            ("s3:GetObject", "arn:aws:s3:::secret/nested/key", CheckResult::Unspecified),
+            // NotResource does not widen the Action clause
+            ("s3:PutObject", "arn:aws:s3:::bucket/key", CheckResult::Unspecified),
+        ];
+        for (action, resource, expected) in cases {
+            let result = policy.check_action(&action.parse().unwrap(), &resource.parse().unwrap(), &context).unwrap();
+            assert_eq!(expected, result, "{} on {}", action, resource);
+        }
+    }
+
+    // S3 bucket operations such as ListBucket apply to the bucket ARN, while
+    // object operations apply to "bucket/key" ARNs. A statement scoped to
+    // "bucket/*" must not match the bucket itself, and vice versa.
+    // See https://docs.aws.amazon.com/AmazonS3/latest/userguide/s3-arn-format.html
+    #[test]
+    fn s3_bucket_vs_object_resources() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:ListBucket", "Resource": "arn:aws:s3:::bucket"},
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}
+            ]
+        }"#.parse().unwrap();
+        let context = Context::new();
+        let cases = [
+            ("s3:ListBucket", "arn:aws:s3:::bucket", CheckResult::Allow),
+            ("s3:GetObject", "arn:aws:s3:::bucket/key", CheckResult::Allow),
+            ("s3:GetObject", "arn:aws:s3:::bucket/path/to/key", CheckResult::Allow),
+            ("s3:GetObject", "arn:aws:s3:::bucket", CheckResult::Unspecified),
+            ("s3:ListBucket", "arn:aws:s3:::bucket/key", CheckResult::Unspecified),
+            ("s3:GetObject", "arn:aws:s3:::bucket-other/key", CheckResult::Unspecified),
+            ("s3:ListBucket", "arn:aws:s3:::bucket-other", CheckResult::Unspecified),
+        ];
+        for (action, resource, expected) in cases {
+            let result = policy.check_action(&action.parse().unwrap(), &resource.parse().unwrap(), &context).unwrap();
+            assert_eq!(expected, result, "{} on {}", action, resource);
+        }
+    }
+
+    #[test]
+    fn check_strings() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Principal": {"Service": "ecs.amazonaws.com"}, "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"},
+                {"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:root"}, "Action": "s3:PutObject", "Resource": "arn:aws:s3:::bucket/*"},
+                {
+                    "Effect": "Deny",
+                    "Principal": "*",
+                    "Action": "s3:*",
+                    "Resource": "arn:aws:s3:::bucket/*",
+                    "Condition": {"Bool": {"aws:SecureTransport": "false"}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let object = "arn:aws:s3:::bucket/key";
+
+        // Identity-based form ignores the Principal clauses
+        assert_eq!(CheckResult::Allow, policy.check_strings(None, "s3:GetObject", object, None).unwrap());
+
+        // Resource-based form
+        assert_eq!(CheckResult::Allow, policy.check_strings(Some("ecs.amazonaws.com"), "s3:GetObject", object, None).unwrap());
+        assert_eq!(CheckResult::Unspecified, policy.check_strings(Some("ecs.amazonaws.com"), "s3:PutObject", object, None).unwrap());
+        assert_eq!(CheckResult::Allow, policy.check_strings(Some("arn:aws:iam::123456789012:user/Alice"), "s3:PutObject", object, None).unwrap());
+        assert_eq!(CheckResult::Unspecified, policy.check_strings(Some("*"), "s3:GetObject", object, None).unwrap());
+        // A bare account ID is the account's root user
+        assert_eq!(CheckResult::Allow, policy.check_strings(Some("123456789012"), "s3:PutObject", object, None).unwrap());
+        assert_eq!(CheckResult::Allow, policy.check_strings(Some("arn:aws:iam::123456789012:root"), "s3:PutObject", object, None).unwrap());
+
+        let mut context = Context::new();
+        context.insert_global("aws:SecureTransport", vec!["false".to_string()]);
+        assert_eq!(CheckResult::Deny, policy.check_strings(Some("ecs.amazonaws.com"), "s3:GetObject", object, Some(&context)).unwrap());
+
+        assert!(policy.check_strings(Some("arn:aws"), "s3:GetObject", object, None).is_err());
+        assert!(policy.check_strings(None, "GetObject", object, None).is_err());
+        assert!(policy.check_strings(None, "s3:GetObject", "bucket/key", None).is_err());
+    }
+}
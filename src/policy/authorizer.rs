@@ -0,0 +1,143 @@
+use crate::aws::ARN;
+use crate::iam::{Action, Principal};
+use super::context::Context;
+use super::statement::CheckResult;
+use super::Policy;
+
+/*
+Combines the policies that apply to a request the way IAM does: an explicit
+Deny in any policy wins, and otherwise each kind of policy that is present must
+allow the request.
+
+A session policy, passed when assuming a role or federating, limits the
+session to the intersection of what it and the identity policy allow. It never
+grants anything on its own.
+See https://docs.aws.amazon.com/IAM/latest/UserGuide/access_policies.html#policies_session
+
+A request from a principal in one account to a resource in another needs both
+accounts to agree: the identity's policies and the resource's policy must each
+allow it. Within a single account, either one allowing is enough.
+See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic-cross-account.html
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Authorizer {
+    pub identity: Policy,
+    pub session: Option<Policy>,
+}
+
+impl Authorizer {
+    pub fn new(identity: Policy) -> Self {
+        Authorizer{ identity, session: None }
+    }
+
+    pub fn authorize(&self, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
+        let identity = self.identity.check_action(action, resource, context)?;
+        let session = match &self.session {
+            Some(session) => session.check_action(action, resource, context)?,
+            None => return Ok(identity),
+        };
+        Ok(match (identity, session) {
+            (CheckResult::Deny, _) | (_, CheckResult::Deny) => CheckResult::Deny,
+            (CheckResult::Allow, CheckResult::Allow) => CheckResult::Allow,
+            _ => CheckResult::Unspecified,
+        })
+    }
+
+    // Authorizes a request from the principal to a resource in another
+    // account, which the resource policy must allow in addition to the
+    // identity and session policies. It is up to the caller to establish
+    // that the accounts differ.
+    pub fn authorize_cross_account(&self, resource_policy: &Policy, principal: &Principal, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
+        let identity = self.authorize(action, resource, context)?;
+        let resource = resource_policy.check(principal, action, resource, context)?;
+        Ok(match (identity, resource) {
+            (CheckResult::Deny, _) | (_, CheckResult::Deny) => CheckResult::Deny,
+            (CheckResult::Allow, CheckResult::Allow) => CheckResult::Allow,
+            _ => CheckResult::Unspecified,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Authorizer;
+    use crate::iam::Principal;
+    use crate::policy::{CheckResult, Policy};
+    use crate::policy::context::Context;
+
+    #[test]
+    fn session_policy() {
+        let identity: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:*", "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:DeleteBucket", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let session: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:GetObject", "s3:DeleteBucket", "ec2:*"], "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::secret/*"}
+            ]
+        }"#.parse().unwrap();
+        let mut authorizer = Authorizer::new(identity);
+        let context = Context::new();
+        let check = |authorizer: &Authorizer, action: &str, resource: &str| {
+            authorizer.authorize(&action.parse().unwrap(), &resource.parse().unwrap(), &context).unwrap()
+        };
+
+        // Without a session policy, the identity policy decides
+        assert_eq!(CheckResult::Allow, check(&authorizer, "s3:PutObject", "arn:aws:s3:::bucket/key"));
+
+        authorizer.session = Some(session);
+        assert_eq!(CheckResult::Allow, check(&authorizer, "s3:GetObject", "arn:aws:s3:::bucket/key"));
+        // Allowed by the identity policy, but not by the session policy
+        assert_eq!(CheckResult::Unspecified, check(&authorizer, "s3:PutObject", "arn:aws:s3:::bucket/key"));
+        // Allowed by the session policy, but not by the identity policy
+        assert_eq!(CheckResult::Unspecified, check(&authorizer, "ec2:RunInstances", "arn:aws:ec2:us-east-1:123456789012:instance/*"));
+        // Denies in either policy apply
+        assert_eq!(CheckResult::Deny, check(&authorizer, "s3:DeleteBucket", "arn:aws:s3:::bucket"));
+        assert_eq!(CheckResult::Deny, check(&authorizer, "s3:GetObject", "arn:aws:s3:::secret/key"));
+    }
+
+    #[test]
+    fn cross_account() {
+        let identity: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:GetObject", "s3:PutObject"], "Resource": "arn:aws:s3:::shared/*"},
+                {"Effect": "Deny", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::shared/private/*"}
+            ]
+        }"#.parse().unwrap();
+        let bucket_policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Principal": {"AWS": "arn:aws:iam::111122223333:root"},
+                "Action": ["s3:GetObject", "s3:DeleteObject"],
+                "Resource": "arn:aws:s3:::shared/*"
+            }
+        }"#.parse().unwrap();
+        let authorizer = Authorizer::new(identity);
+        let principal: Principal = "arn:aws:iam::111122223333:user/Alice".parse().unwrap();
+        let context = Context::new();
+        let check = |action: &str, resource: &str| {
+            authorizer.authorize_cross_account(&bucket_policy, &principal, &action.parse().unwrap(), &resource.parse().unwrap(), &context).unwrap()
+        };
+
+        assert_eq!(CheckResult::Allow, check("s3:GetObject", "arn:aws:s3:::shared/key"));
+        // The identity policy allows it, but the bucket policy does not
+        assert_eq!(CheckResult::Unspecified, check("s3:PutObject", "arn:aws:s3:::shared/key"));
+        // The bucket policy allows it, but the identity policy does not
+        assert_eq!(CheckResult::Unspecified, check("s3:DeleteObject", "arn:aws:s3:::shared/key"));
+        // A deny on either side wins
+        assert_eq!(CheckResult::Deny, check("s3:GetObject", "arn:aws:s3:::shared/private/key"));
+
+        // The resource policy must name the principal's account
+        let other: Principal = "arn:aws:iam::444455556666:user/Bob".parse().unwrap();
+        let result = authorizer.authorize_cross_account(&bucket_policy, &other, &"s3:GetObject".parse().unwrap(), &"arn:aws:s3:::shared/key".parse().unwrap(), &context).unwrap();
+        assert_eq!(CheckResult::Unspecified, result);
+    }
+}
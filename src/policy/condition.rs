@@ -2,11 +2,18 @@ pub mod global;
 pub mod nullable;
 pub mod operator;
 pub mod quantifier;
+pub mod registry;
+pub mod s3;
+pub mod set;
 
 use crate::aws::ARN;
+use operator::Operator;
 use quantifier::Quantifier;
+use registry::KeyRegistry;
 
 use super::constraint::ResourceConstraint;
+use super::options::ParseOptions;
+use super::variables::{has_variables, substitute, VariableError};
 
 use std::cmp::Ordering;
 use std::collections::HashMap;
@@ -14,7 +21,7 @@ use std::net::IpAddr;
 use std::str::FromStr;
 
 use anyhow::anyhow;
-use chrono::DateTime;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use ipnetwork::IpNetwork;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,16 +39,35 @@ impl std::fmt::Display for ConditionError {
 
 impl std::error::Error for ConditionError {}
 
+// f64::from_str accepts "inf" and "NaN", which AWS does not treat as numbers.
+fn parse_number(value: &str) -> Result<f64, ConditionError> {
+    f64::from_str(value).ok()
+        .filter(|number| number.is_finite())
+        .ok_or(ConditionError::TypeMismatch)
+}
+
 fn cmp_numbers(lhs: &str, rhs: &str) -> anyhow::Result<Ordering> {
-    let lhs = f64::from_str(lhs).map_err(|_| ConditionError::TypeMismatch)?;
-    let rhs = f64::from_str(rhs).map_err(|_| ConditionError::TypeMismatch)?;
+    let lhs = parse_number(lhs)?;
+    let rhs = parse_number(rhs)?;
     let result = lhs.partial_cmp(&rhs).ok_or(ConditionError::TypeMismatch)?;
     Ok(result)
 }
 
+// Dates are either ISO 8601 timestamps with a time zone or seconds since the
+// Unix epoch, which is how aws:EpochTime is given.
+fn parse_date(value: &str) -> Result<DateTime<FixedOffset>, ConditionError> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date);
+    }
+    let seconds = i64::from_str(value).map_err(|_| ConditionError::TypeMismatch)?;
+    Utc.timestamp_opt(seconds, 0).single()
+        .map(DateTime::from)
+        .ok_or(ConditionError::TypeMismatch)
+}
+
 fn cmp_dates(lhs: &str, rhs: &str) -> anyhow::Result<Ordering> {
-    let lhs = DateTime::parse_from_rfc3339(lhs).map_err(|_| ConditionError::TypeMismatch)?;
-    let rhs = DateTime::parse_from_rfc3339(rhs).map_err(|_| ConditionError::TypeMismatch)?;
+    let lhs = parse_date(lhs)?;
+    let rhs = parse_date(rhs)?;
     Ok(lhs.cmp(&rhs))
 }
 
@@ -57,8 +83,19 @@ fn base64s_eq(lhs: &str, rhs: &str) -> anyhow::Result<bool> {
     Ok(lhs == rhs)
 }
 
+// An IPv6 address may carry a zone id naming the interface, e.g.
+// fe80::1%eth0, which IpAddr does not accept and which does not affect
+// matching.
+fn parse_ip(value: &str) -> Result<IpAddr, ConditionError> {
+    let address = match value.split_once('%') {
+        Some((address, zone)) if address.contains(':') && !zone.is_empty() => address,
+        _ => value,
+    };
+    IpAddr::from_str(address).map_err(|_| ConditionError::TypeMismatch)
+}
+
 fn ip_in_cidr(lhs: &str, rhs: &str) -> anyhow::Result<bool> {
-    let lhs = IpAddr::from_str(lhs).map_err(|_| ConditionError::TypeMismatch)?;
+    let lhs = parse_ip(lhs)?;
     let rhs = IpNetwork::from_str(rhs).map_err(|_| ConditionError::TypeMismatch)?;
     Ok(rhs.contains(lhs))
 }
@@ -81,7 +118,7 @@ fn arn_like(value: &str, pattern: &str) -> anyhow::Result<bool> {
 
 pub type ConditionValues = HashMap<String, Vec<String>>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ConditionList {
     conditions: HashMap<Quantifier, ConditionValues>,
 }
@@ -96,6 +133,46 @@ impl ConditionList {
         self.conditions.insert(op, values)
     }
 
+    // Adds the values to any already present for the quantifier, replacing
+    // the targets of keys present in both.
+    pub fn extend(&mut self, quant: &Quantifier, values: &ConditionValues) {
+        self.conditions.entry(quant.clone()).or_default()
+            .extend(values.iter().map(|(key, targets)| (key.clone(), targets.clone())));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Quantifier, &ConditionValues)> {
+        self.conditions.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.conditions.values().flat_map(|values| values.keys())
+    }
+
+    // As matches, after substituting policy variables in the targets with
+    // values from the same map. A variable whose key is missing fails to
+    // match. See policy::variables.
+    pub fn matches_with_variables(&self, value_map: &HashMap<String, Vec<String>>) -> anyhow::Result<bool> {
+        for (quant, target_map) in &self.conditions {
+            for (key, targets) in target_map {
+                let values = value_map.get(key);
+                let result = if targets.iter().any(|target| has_variables(target)) {
+                    let targets = match targets.iter().map(|target| substitute(target, value_map)).collect::<Result<Vec<_>, _>>() {
+                        Ok(targets) => targets,
+                        Err(VariableError::Missing(_)) => return Ok(false),
+                        Err(err) => return Err(err.into()),
+                    };
+                    quant.matches_parts(values, &targets)?
+                } else {
+                    quant.matches(values, targets)?
+                };
+                if !result {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
     pub fn matches(&self, value_map: &HashMap<String, Vec<String>>) -> anyhow::Result<bool> {
         self.conditions.iter().try_fold(true, |result, (op, target_map)| {
             // Short-circuit on the first failure to match
@@ -114,6 +191,61 @@ impl ConditionList {
             })
         })
     }
+
+    // Builds condition key values that satisfy every condition in the list,
+    // using Operator::sample_value for each operator. Keys that only appear
+    // under ForAllValues or IfExists, or under Null with "true", are left
+    // out, since a missing key satisfies those conditions. Returns None if the values
+    // derived this way do not satisfy the conditions, which can happen when
+    // several operators constrain the same key.
+    pub fn sample_values(&self) -> Option<ConditionValues> {
+        let mut result = ConditionValues::new();
+        let mut required = Vec::new();
+        for (quant, values) in &self.conditions {
+            for (key, targets) in values {
+                match quant {
+                    Quantifier::Plain(op) | Quantifier::ForAnyValue(op) => result.entry(key.clone()).or_default().push(op.sample_value(targets)?),
+                    Quantifier::Null if targets.iter().any(|target| target == "false") => required.push(key),
+                    Quantifier::IfExists(_) | Quantifier::ForAllValues(_) | Quantifier::ForAllValuesIfExists(_) | Quantifier::Null => {},
+                }
+            }
+        }
+        // Keys that Null requires to be present but no other operator gave a
+        // value for, using the ForAllValues or IfExists operator on the key if
+        // any.
+        for key in required {
+            if result.contains_key(key) {
+                continue;
+            }
+            let value = self.conditions.iter()
+                .find_map(|(quant, values)| match (quant, values.get(key)) {
+                    (Quantifier::IfExists(op) | Quantifier::ForAllValues(op) | Quantifier::ForAllValuesIfExists(op), Some(targets)) => Some(op.sample_value(targets)),
+                    _ => None,
+                })
+                .unwrap_or_else(|| Some("example".to_string()))?;
+            result.insert(key.clone(), vec![value]);
+        }
+        match self.matches(&result) {
+            Ok(true) => Some(result),
+            _ => None,
+        }
+    }
+
+    // Checks that each operator is applicable to the type of the keys it is
+    // applied to. Keys missing from the registry are not checked.
+    pub fn validate(&self, registry: &KeyRegistry) -> anyhow::Result<()> {
+        self.conditions.iter().try_for_each(|(quant, values)| {
+            let op = match quant.operator() {
+                Some(op) => op,
+                None => return Ok(()),
+            };
+            values.keys().try_for_each(|key| {
+                registry.check_operator(key, op)
+                    .map_err(|err| anyhow!("{} cannot be used with condition key {}: {}", op, key, err))
+            })
+        })
+    }
+
     fn try_from_values(values: &json::JsonValue) -> anyhow::Result<ConditionValues> {
         values.entries().map(|(key, values)| {
             if let Some(s) = values.as_str() {
@@ -133,38 +265,79 @@ impl Default for ConditionList {
     fn default() -> Self { ConditionList::new() }
 }
 
+impl ConditionList {
+    pub fn from_json_with_options(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Self> {
+        let parse_op = |op_str: &str| -> anyhow::Result<Operator> {
+            if options.allow_unknown_operators {
+                Ok(Operator::parse_lenient(op_str))
+            } else {
+                op_str.parse()
+            }
+        };
+        // Spellings that parse to the same quantifier, e.g., Null and
+        // ForAllValues:Null, are merged rather than replacing each other.
+        let mut conditions = ConditionList::new();
+        for (key, value) in value.entries() {
+            let (for_any, op_str) = if let Some(op) = key.strip_prefix("ForAnyValue:") {
+                (Some(true), op)
+            } else if let Some(op) = key.strip_prefix("ForAllValues:") {
+                (Some(false), op)
+            } else {
+                (None, key)
+            };
+            let (op_str, if_exists) = match op_str.strip_suffix("IfExists") {
+                Some(op) => (op, true),
+                None => (op_str, false),
+            };
+
+            let values = Self::try_from_values(value)?;
+            let quant = match (for_any, if_exists, op_str) {
+                (_, _, "Null") => Quantifier::Null,
+                (Some(true), true, _) => return Err(anyhow!("ForAnyValue with IfExists is not supported: {}", key)),
+                (Some(true), false, _) => Quantifier::ForAnyValue(parse_op(op_str)?),
+                (Some(false), false, _) => Quantifier::ForAllValues(parse_op(op_str)?),
+                (Some(false), true, _) => Quantifier::ForAllValuesIfExists(parse_op(op_str)?),
+                (None, false, _) => Quantifier::Plain(parse_op(op_str)?),
+                (None, true, _) => Quantifier::IfExists(parse_op(op_str)?),
+            };
+            conditions.extend(&quant, &values);
+        }
+        Ok(conditions)
+    }
+}
+
 impl TryFrom<&json::JsonValue> for ConditionList {
     type Error = anyhow::Error;
 
     fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
-        value.entries().map(|(key, value)| {
-            let mut op_str = key;
-            // The default for single-valued is to assume ForAny
-            let mut for_any = true;
-            if let Some(op) = key.strip_suffix("IfExists") {
-                op_str = op;
-                for_any = false;
-            }
+        Self::from_json_with_options(value, &ParseOptions::default())
+    }
+}
 
-            if let Some(op) = key.strip_prefix("ForAny:") {
-                op_str = op;
-                for_any = true;
-            } else if let Some(op) = key.strip_prefix("ForAll:") {
-                op_str = op;
-                for_any = false;
+// Each operator key is written as it was parsed. Operator keys and the
+// condition keys within them are sorted, so the output does not depend on
+// hash order.
+impl From<&ConditionList> for json::JsonValue {
+    fn from(conditions: &ConditionList) -> Self {
+        let mut blocks: Vec<(String, &ConditionValues)> = conditions.conditions.iter()
+            .map(|(quant, values)| (quant.to_string(), values))
+            .collect();
+        blocks.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        let mut result = json::JsonValue::new_object();
+        for (key, values) in blocks {
+            let mut names: Vec<(&String, &Vec<String>)> = values.iter().collect();
+            names.sort();
+            let mut entries = json::JsonValue::new_object();
+            for (name, targets) in names {
+                let targets = match targets.as_slice() {
+                    [target] => json::JsonValue::from(target.as_str()),
+                    _ => json::JsonValue::from(targets.clone()),
+                };
+                entries[name.as_str()] = targets;
             }
-
-            let operator = op_str.parse()?;
-            let is_null = op_str == "Null";
-            let values = Self::try_from_values(value)?;
-            let quant = match (for_any, is_null) {
-                (_, true) => Quantifier::Null,
-                (true, _) => Quantifier::ForAnyValue(operator),
-                (false, _) => Quantifier::ForAllValues(operator),
-            };
-            Ok((quant, values))
-        }).collect::<Result<HashMap<_, _>, _>>()
-            .map(|conditions| ConditionList { conditions })
+            result[key.as_str()] = entries;
+        }
+        result
     }
 }
 
@@ -174,7 +347,9 @@ mod test {
 
     use super::{ConditionList, ConditionValues};
     use super::operator::Operator;
+    use crate::policy::options::ParseOptions;
     use super::quantifier::Quantifier;
+    use super::registry::KeyRegistry;
 
     fn single_value(key: &str, value: &str) -> ConditionValues {
         ConditionValues::from([(key.to_string(), vec![value.to_string()])])
@@ -197,6 +372,24 @@ mod test {
         }
     }
 
+    #[test]
+    fn string_equals_empty_target() {
+        assert!(Operator::StringEquals.matches("", "").unwrap());
+        assert!(!Operator::StringEquals.matches("a", "").unwrap());
+        assert!(!Operator::StringEquals.matches("", "a").unwrap());
+
+        let list = ConditionList::try_from(&json::parse(r#"{"StringEquals": {"aws:PrincipalTag/team": ""}}"#).unwrap()).unwrap();
+        assert!(list.matches(&single_value("aws:PrincipalTag/team", "")).unwrap());
+        assert!(!list.matches(&single_value("aws:PrincipalTag/team", "storage")).unwrap());
+        // A missing key or one without values is not an empty string
+        assert!(!list.matches(&ConditionValues::new()).unwrap());
+        assert!(!list.matches(&ConditionValues::from([("aws:PrincipalTag/team".to_string(), vec![])])).unwrap());
+
+        let list = ConditionList::try_from(&json::parse(r#"{"StringNotEquals": {"aws:PrincipalTag/team": ""}}"#).unwrap()).unwrap();
+        assert!(!list.matches(&single_value("aws:PrincipalTag/team", "")).unwrap());
+        assert!(list.matches(&single_value("aws:PrincipalTag/team", "storage")).unwrap());
+    }
+
     #[test]
     fn op_string_equals_ignore_case() {
         let cases = [
@@ -223,6 +416,11 @@ mod test {
 
             assert_ne!(expected, op.matches("tst", "t?st").unwrap());
             assert_ne!(expected, op.matches("teest", "t?st").unwrap());
+
+            // Patterns must match the whole value
+            assert_ne!(expected, op.matches("xabcx", "abc").unwrap());
+            assert_ne!(expected, op.matches("xabcx", "a*c").unwrap());
+            assert_eq!(expected, op.matches("xabcx", "*abc*").unwrap());
         }
     }
 
@@ -250,6 +448,8 @@ mod test {
             ("1.0", "2.0", true, false),
             ("2.0", "2.0", false, true),
             ("3.0", "2.0", false, false),
+            ("-0", "0", false, true),
+            ("-0", "1", true, false),
         ];
         for (lhs, rhs, less_than, equals) in cases {
             assert_eq!(equals, NumericEquals.matches(lhs, rhs).unwrap());
@@ -275,6 +475,12 @@ mod test {
             ("1", "1.1.1"),
             ("1.1.1", "1"),
             ("1.1.1", "1.1.1"),
+            ("inf", "1"),
+            ("1", "inf"),
+            ("-inf", "1"),
+            ("infinity", "1"),
+            ("NaN", "1"),
+            ("1", "NaN"),
         ];
         for (lhs, rhs) in cases {
             assert!(NumericEquals.matches(lhs, rhs).is_err());
@@ -303,6 +509,11 @@ mod test {
             ("2020-04-01T00:00:02+01:00", "2020-04-01T00:00:02Z", true, false),
             ("2020-04-01T00:00:02+00:00", "2020-04-01T00:00:02Z", false, true),
             ("2020-04-01T00:00:02-01:00", "2020-04-01T00:00:02Z", false, false),
+            // Epoch seconds compare with each other and with timestamps
+            ("1585699201", "2020-04-01T00:00:02Z", true, false),
+            ("1585699202", "2020-04-01T00:00:02Z", false, true),
+            ("2020-04-01T00:00:03Z", "1585699202", false, false),
+            ("1585699202", "1585699203", true, false),
         ];
         for (lhs, rhs, less_than, equals) in cases {
             assert_eq!(equals, DateEquals.matches(lhs, rhs).unwrap());
@@ -329,6 +540,9 @@ mod test {
             ("2020-04-01T00:00:02", "2020-04-01T00:00:02Z"),
             ("2020-04-01T00:00:02Z", "2020-04-01T00:00:02"),
             ("2020-04-01T00:00:02", "2020-04-01T00:00:02"),
+            // Epoch time is a whole number of seconds
+            ("1585699202.5", "2020-04-01T00:00:02Z"),
+            ("1e9", "2020-04-01T00:00:02Z"),
         ];
         for (lhs, rhs) in cases {
             assert!(DateEquals.matches(lhs, rhs).is_err());
@@ -413,6 +627,10 @@ mod test {
             ("2001:DB8:1234:5678:FFFF:FFFF:FFFF:1", "2001:DB8:1234:5678::/64", true),
             ("2001:DB8:1234:5677::1", "2001:DB8:1234:5678::/64", false),
             ("2001:DB8:1234:5679::1", "2001:DB8:1234:5678::/64", false),
+            ("fe80::1", "fe80::/10", true),
+            ("fe80::1%eth0", "fe80::/10", true),
+            ("fe80::1%1", "fe80::/10", true),
+            ("fe80::1%eth0", "2001:DB8::/32", false),
         ];
         for (lhs, rhs, contains) in cases {
             assert_eq!(contains, IpAddress.matches(lhs, rhs).unwrap());
@@ -438,6 +656,10 @@ mod test {
             ("2001:DB8:1234:5678::1", "2001:DB8:1234:5678::/129"),
             // Value can't be a CIDR
             ("2001:DB8:1234:5678::1/126", "2001:DB8:1234:5678::/64"),
+            // Zone ids only apply to IPv6 addresses, and must not be empty
+            ("203.0.113.64%eth0", "203.0.113.0/24"),
+            ("fe80::1%", "fe80::/10"),
+            ("fe80::1", "fe80::/10%eth0"),
         ];
         for (lhs, rhs) in cases {
             assert!(IpAddress.matches(lhs, rhs).is_err());
@@ -456,6 +678,10 @@ mod test {
             ("arn:aws:iam::123456789012:user/Alice", "arn:aws:iam::*:user/Alice", false, true),
             // Not sure this counts as valid. It should never happen in practice.
             ("arn:aws:iam::*:user/Alice", "arn:aws:iam::*:user/Alice", true, true),
+            ("arn:aws:iam::123456789012:user/Alice", "arn:*:iam::123456789012:user/Alice", false, true),
+            ("arn:aws:iam::123456789012:user/Alice", "arn:aws*:iam::123456789012:user/*", false, true),
+            ("arn:aws-cn:iam::123456789012:user/Alice", "arn:aws:iam::123456789012:user/Alice", false, false),
+            ("arn:aws-us-gov:iam::123456789012:user/Alice", "arn:*:iam::123456789012:user/Alice", false, true),
         ];
         for (lhs, rhs, equals, like) in cases {
             assert_eq!(equals, ArnEquals.matches(lhs, rhs).unwrap());
@@ -468,7 +694,7 @@ mod test {
     #[test]
     fn condition_list_string_equals() {
         let mut set = ConditionList::new();
-        let quant = Quantifier::ForAnyValue(Operator::StringEquals);
+        let quant = Quantifier::Plain(Operator::StringEquals);
         set.insert((quant, single_value("test:Property", "foo")));
         let values = single_value("test:Property", "foo");
         assert!(set.matches(&values).unwrap());
@@ -479,4 +705,250 @@ mod test {
         let values = HashMap::new();
         assert!(!set.matches(&values).unwrap());
     }
+
+    #[test]
+    fn condition_list_bool_false() {
+        let value = json::parse(r#"{"Bool": {"aws:SecureTransport": "false"}}"#).unwrap();
+        let set = ConditionList::try_from(&value).unwrap();
+        assert!(set.matches(&single_value("aws:SecureTransport", "false")).unwrap());
+        assert!(!set.matches(&single_value("aws:SecureTransport", "true")).unwrap());
+        assert!(!set.matches(&HashMap::new()).unwrap());
+        assert!(set.matches(&single_value("aws:SecureTransport", "yes")).is_err());
+    }
+
+    #[test]
+    fn condition_list_parse_set_operators() {
+        let value = json::parse(r#"{
+            "ForAllValues:StringEquals": {"aws:TagKeys": ["Env", "Owner"]},
+            "ForAnyValue:StringLike": {"aws:CalledVia": "cloudformation.*"}
+        }"#).unwrap();
+        let set = ConditionList::try_from(&value).unwrap();
+        let mut quants: Vec<_> = set.iter().map(|(quant, _)| quant.clone()).collect();
+        quants.sort_by_key(|quant| format!("{:?}", quant));
+        assert_eq!(quants, vec![
+            Quantifier::ForAllValues(Operator::StringEquals),
+            Quantifier::ForAnyValue(Operator::StringLike),
+        ]);
+
+        let values = ConditionValues::from([
+            ("aws:TagKeys".to_string(), vec!["Env".to_string()]),
+            ("aws:CalledVia".to_string(), vec!["cloudformation.amazonaws.com".to_string()]),
+        ]);
+        assert!(set.matches(&values).unwrap());
+        let values = ConditionValues::from([
+            ("aws:TagKeys".to_string(), vec!["Env".to_string(), "Secret".to_string()]),
+            ("aws:CalledVia".to_string(), vec!["cloudformation.amazonaws.com".to_string()]),
+        ]);
+        assert!(!set.matches(&values).unwrap());
+    }
+
+    #[test]
+    fn condition_list_spellings_do_not_collide() {
+        let value = json::parse(r#"{
+            "StringEquals": {"aws:username": "alice"},
+            "ForAnyValue:StringEquals": {"aws:TagKeys": "Env"}
+        }"#).unwrap();
+        let set = ConditionList::try_from(&value).unwrap();
+        assert_eq!(set.iter().count(), 2);
+        let values = |username: &str| ConditionValues::from([
+            ("aws:username".to_string(), vec![username.to_string()]),
+            ("aws:TagKeys".to_string(), vec!["Env".to_string()]),
+        ]);
+        assert!(set.matches(&values("alice")).unwrap());
+        assert!(!set.matches(&values("bob")).unwrap());
+
+        let value = json::parse(r#"{
+            "StringEqualsIfExists": {"ec2:InstanceType": "t2.micro"},
+            "ForAllValues:StringEquals": {"aws:TagKeys": ["Env", "Owner"]}
+        }"#).unwrap();
+        let set = ConditionList::try_from(&value).unwrap();
+        assert_eq!(set.iter().count(), 2);
+        let values = |instance_type: &str| ConditionValues::from([
+            ("ec2:InstanceType".to_string(), vec![instance_type.to_string()]),
+            ("aws:TagKeys".to_string(), vec!["Env".to_string()]),
+        ]);
+        assert!(set.matches(&values("t2.micro")).unwrap());
+        assert!(!set.matches(&values("m5.large")).unwrap());
+
+        // Spellings of the same quantifier are merged
+        let value = json::parse(r#"{
+            "Null": {"aws:TokenIssueTime": "true"},
+            "ForAllValues:Null": {"aws:SourceVpc": "false"}
+        }"#).unwrap();
+        let set = ConditionList::try_from(&value).unwrap();
+        assert_eq!(set.keys().count(), 2);
+    }
+
+    #[test]
+    fn condition_list_parse_aws_example() {
+        let policy: crate::policy::Policy = include_str!("../../testdata/example_cfn_sns_resource_account.json")
+            .parse().unwrap();
+        let conditions = policy.statements[1].conditions.as_ref().unwrap();
+        assert!(conditions.iter().any(|(quant, values)| {
+            *quant == Quantifier::ForAllValues(Operator::StringNotEquals) && values.contains_key("aws:CalledVia")
+        }));
+    }
+
+    fn round_trip(source: &str) -> (ConditionList, json::JsonValue) {
+        let set = ConditionList::try_from(&json::parse(source).unwrap()).unwrap();
+        let value = json::JsonValue::from(&set);
+        let reparsed = ConditionList::try_from(&value).unwrap();
+        assert_eq!(set, reparsed);
+        (set, value)
+    }
+
+    #[test]
+    fn condition_list_serialize_for_any_value() {
+        let (_, value) = round_trip(r#"{"StringEquals": {"aws:username": "alice"}}"#);
+        assert_eq!(value["StringEquals"]["aws:username"], "alice");
+        let (_, value) = round_trip(r#"{"ForAnyValue:StringLike": {"aws:TagKeys": ["Env*", "Owner"]}}"#);
+        assert_eq!(value["ForAnyValue:StringLike"]["aws:TagKeys"], json::array!["Env*", "Owner"]);
+    }
+
+    #[test]
+    fn condition_list_serialize_for_all_values() {
+        let (_, value) = round_trip(r#"{"ForAllValues:StringEquals": {"aws:TagKeys": ["Env", "Owner"]}}"#);
+        assert_eq!(value["ForAllValues:StringEquals"]["aws:TagKeys"], json::array!["Env", "Owner"]);
+    }
+
+    #[test]
+    fn condition_list_serialize_if_exists() {
+        let (_, value) = round_trip(r#"{"StringEqualsIfExists": {"ec2:InstanceType": "t2.micro"}}"#);
+        assert_eq!(value["StringEqualsIfExists"]["ec2:InstanceType"], "t2.micro");
+    }
+
+    #[test]
+    fn for_all_values_if_exists() {
+        let source = json::parse(r#"{"ForAllValues:StringEqualsIfExists": {"aws:TagKeys": ["Env", "Owner"]}}"#).unwrap();
+        let set = ConditionList::try_from(&source).unwrap();
+        let tags = |keys: &[&str]| ConditionValues::from([("aws:TagKeys".to_string(), keys.iter().map(|key| key.to_string()).collect())]);
+        // A missing key passes
+        assert!(set.matches(&HashMap::new()).unwrap());
+        // Every present value must match
+        assert!(set.matches(&tags(&["Env"])).unwrap());
+        assert!(set.matches(&tags(&["Env", "Owner"])).unwrap());
+        assert!(!set.matches(&tags(&["Env", "Project"])).unwrap());
+        // Evaluates like ForAllValues without IfExists, but keeps its spelling
+        let (_, value) = round_trip(r#"{"ForAllValues:StringEqualsIfExists": {"aws:TagKeys": ["Env", "Owner"]}}"#);
+        assert_eq!(value["ForAllValues:StringEqualsIfExists"]["aws:TagKeys"], json::array!["Env", "Owner"]);
+
+        let source = json::parse(r#"{"ForAnyValue:StringEqualsIfExists": {"aws:TagKeys": "Env"}}"#).unwrap();
+        assert!(ConditionList::try_from(&source).is_err());
+    }
+
+    #[test]
+    fn condition_list_serialize_stable() {
+        let source = r#"{
+            "StringEquals": {"aws:username": "alice", "aws:PrincipalAccount": "123456789012"},
+            "ForAnyValue:StringEquals": {"aws:TagKeys": "Env"},
+            "StringEqualsIfExists": {"ec2:InstanceType": "t2.micro"},
+            "ForAllValues:StringEqualsIfExists": {"aws:TagKeys": ["Env", "Owner"]},
+            "Null": {"aws:TokenIssueTime": "true"}
+        }"#;
+        let (_, value) = round_trip(source);
+        let keys: Vec<&str> = value.entries().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["ForAllValues:StringEqualsIfExists", "ForAnyValue:StringEquals", "Null", "StringEquals", "StringEqualsIfExists"]);
+        let names: Vec<&str> = value["StringEquals"].entries().map(|(key, _)| key).collect();
+        assert_eq!(names, vec!["aws:PrincipalAccount", "aws:username"]);
+        assert_eq!(value.dump(), json::JsonValue::from(&ConditionList::try_from(&json::parse(source).unwrap()).unwrap()).dump());
+    }
+
+    #[test]
+    fn condition_list_serialize_null() {
+        let (set, value) = round_trip(r#"{"Null": {"aws:TokenIssueTime": "true"}}"#);
+        assert_eq!(value["Null"]["aws:TokenIssueTime"], "true");
+        assert!(set.matches(&HashMap::new()).unwrap());
+    }
+
+    #[test]
+    fn condition_list_numeric() {
+        let source = json::parse(r#"{"NumericLessThan": {"aws:MultiFactorAuthAge": "3600"}}"#).unwrap();
+        let set = ConditionList::try_from(&source).unwrap();
+        assert!(set.matches(&single_value("aws:MultiFactorAuthAge", "300")).unwrap());
+        assert!(!set.matches(&single_value("aws:MultiFactorAuthAge", "7200")).unwrap());
+        // Numbers are compared numerically rather than as strings
+        assert!(!set.matches(&single_value("aws:MultiFactorAuthAge", "10000")).unwrap());
+        assert!(set.matches(&single_value("aws:MultiFactorAuthAge", "300.5")).unwrap());
+        assert!(set.matches(&single_value("aws:MultiFactorAuthAge", "not-a-number")).is_err());
+        // A missing key does not match a plain operator, but does with IfExists
+        assert!(!set.matches(&HashMap::new()).unwrap());
+        let source = json::parse(r#"{"NumericLessThanIfExists": {"aws:MultiFactorAuthAge": "3600"}}"#).unwrap();
+        let set = ConditionList::try_from(&source).unwrap();
+        assert!(set.matches(&HashMap::new()).unwrap());
+        assert!(!set.matches(&single_value("aws:MultiFactorAuthAge", "7200")).unwrap());
+    }
+
+    #[test]
+    fn condition_list_sample_values() {
+        let source = json::parse(r#"{
+            "StringEquals": {"aws:username": "alice"},
+            "Bool": {"aws:SecureTransport": "true"},
+            "NumericLessThan": {"aws:MultiFactorAuthAge": "3600"},
+            "DateGreaterThan": {"aws:CurrentTime": "2020-01-01T00:00:00Z"},
+            "IpAddress": {"aws:SourceIp": "203.0.113.0/24"},
+            "ForAllValues:StringEquals": {"aws:TagKeys": ["Env", "Owner"]}
+        }"#).unwrap();
+        let set = ConditionList::try_from(&source).unwrap();
+        let values = set.sample_values().unwrap();
+        assert_eq!(values["aws:username"], vec!["alice"]);
+        assert_eq!(values["aws:SecureTransport"], vec!["true"]);
+        assert_eq!(values["aws:MultiFactorAuthAge"], vec!["3599"]);
+        assert_eq!(values["aws:CurrentTime"], vec!["2020-01-01T00:00:01+00:00"]);
+        assert_eq!(values["aws:SourceIp"], vec!["203.0.113.0"]);
+        // A missing key satisfies ForAllValues
+        assert!(!values.contains_key("aws:TagKeys"));
+    }
+
+    #[test]
+    fn condition_list_sample_values_unsatisfiable() {
+        let source = json::parse(r#"{
+            "StringEquals": {"aws:username": "alice"},
+            "Null": {"aws:username": "true"}
+        }"#).unwrap();
+        let set = ConditionList::try_from(&source).unwrap();
+        assert_eq!(set.sample_values(), None);
+
+        let source = json::parse(r#"{
+            "StringNotEquals": {"aws:username": ["alice", "bob"]},
+            "Null": {"aws:PrincipalTag/team": "false"}
+        }"#).unwrap();
+        let set = ConditionList::try_from(&source).unwrap();
+        let values = set.sample_values().unwrap();
+        assert!(!["alice", "bob"].contains(&values["aws:username"][0].as_str()));
+        assert!(values.contains_key("aws:PrincipalTag/team"));
+    }
+
+    #[test]
+    fn condition_list_unknown_operator() {
+        let source = json::parse(r#"{
+            "StringMatches": {"aws:username": "alice"},
+            "ForAllValues:StringMatches": {"aws:TagKeys": ["Env", "Owner"]}
+        }"#).unwrap();
+        assert!(ConditionList::try_from(&source).is_err());
+
+        let set = ConditionList::from_json_with_options(&source, &ParseOptions::lenient()).unwrap();
+        let unknown = Operator::Unknown("StringMatches".to_string());
+        assert!(set.iter().any(|(quant, _)| *quant == Quantifier::Plain(unknown.clone())));
+        assert!(set.iter().any(|(quant, _)| *quant == Quantifier::ForAllValues(unknown.clone())));
+
+        let mut single = ConditionList::new();
+        single.insert((Quantifier::Plain(unknown), single_value("aws:username", "alice")));
+        assert!(!single.matches(&single_value("aws:username", "alice")).unwrap());
+
+        let value = json::JsonValue::from(&set);
+        assert_eq!(value["StringMatches"]["aws:username"], "alice");
+        assert_eq!(value["ForAllValues:StringMatches"]["aws:TagKeys"], json::array!["Env", "Owner"]);
+    }
+
+    #[test]
+    fn condition_list_validate_service_key() {
+        let registry = KeyRegistry::with_builtins();
+        let mut set = ConditionList::new();
+        set.insert((Quantifier::Plain(Operator::NumericLessThanEquals), single_value("s3:max-keys", "10")));
+        assert!(set.validate(&registry).is_ok());
+
+        let mut set = ConditionList::new();
+        set.insert((Quantifier::Plain(Operator::StringEquals), single_value("s3:max-keys", "10")));
+        assert!(set.validate(&registry).is_err());
+    }
 }
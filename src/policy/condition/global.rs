@@ -1,6 +1,7 @@
 // A list of global keys with types and cardinality
 // All these keys have a "aws:" prefix.
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
 	String,
 	Numeric,
@@ -13,6 +14,7 @@ pub enum Type {
 	UnknownType,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Cardinality {
 	Optional,
 	Required,
@@ -20,9 +22,43 @@ pub enum Cardinality {
 	UnknownCardinality,
 }
 
+use super::operator::Operator;
+
 use Type::*;
 use Cardinality::*;
 
+impl Type {
+	// Whether a condition operator can be applied to a key of this type.
+	// String operators also apply to ARN keys.
+	pub fn accepts(&self, op: &Operator) -> bool {
+		use Operator as Op;
+		let is_string = matches!(op, Op::StringEquals | Op::StringNotEquals | Op::StringEqualsIgnoreCase | Op::StringNotEqualsIgnoreCase | Op::StringLike | Op::StringNotLike);
+		let is_numeric = matches!(op, Op::NumericEquals | Op::NumericNotEquals | Op::NumericLessThan | Op::NumericLessThanEquals | Op::NumericGreaterThan | Op::NumericGreaterThanEquals);
+		let is_date = matches!(op, Op::DateEquals | Op::DateNotEquals | Op::DateLessThan | Op::DateLessThanEquals | Op::DateGreaterThan | Op::DateGreaterThanEquals);
+		match self {
+			Self::String => is_string,
+			Self::Numeric => is_numeric,
+			Self::Date => is_date,
+			Self::Epoch => is_numeric || is_date,
+			Self::Bool => matches!(op, Op::Bool),
+			Self::Binary => matches!(op, Op::BinaryEquals),
+			Self::IpAddress => matches!(op, Op::IpAddress | Op::NotIpAddress),
+			Self::ARN => is_string || matches!(op, Op::ArnEquals | Op::ArnLike | Op::ArnNotEquals | Op::ArnNotLike),
+			Self::UnknownType => true,
+		}
+	}
+}
+
+// The values aws:PrincipalType can take.
+// See: https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_variables.html#principaltable
+pub const PRINCIPAL_TYPES: &[&str] = &[
+	"Account",
+	"User",
+	"FederatedUser",
+	"AssumedRole",
+	"Anonymous",
+];
+
 // See: https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_condition-keys.html
 pub const AWS: &[(&str, Type, Cardinality)] = &[
 	("CalledVia", String, Multiple),
@@ -2,7 +2,7 @@ use super::operator::Operator;
 
 use anyhow::anyhow;
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Nullable {
 	// The value must be non-null and match the operator
 	Expect(Operator),
@@ -14,7 +14,7 @@ pub enum Nullable {
 
 impl Nullable {
 	pub fn matches(&self, value: Option<&str>, target: &str) -> anyhow::Result<bool> {
-		match *self {
+		match self {
 			Self::Expect(operator) => if let Some(value) = value {
 				operator.matches(value, target)
 			} else {
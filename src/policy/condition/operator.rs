@@ -1,4 +1,5 @@
-use crate::aws::glob_matches;
+use crate::aws::{glob_matches, glob_parts_match, GlobPart};
+use crate::policy::variables::to_text;
 use super::{
   cmp_numbers,
   cmp_dates,
@@ -14,8 +15,10 @@ use std::ops::Not;
 use std::str::FromStr;
 
 use anyhow::anyhow;
+use chrono::{DateTime, Duration};
+use ipnetwork::IpNetwork;
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Operator {
     StringEquals,
     StringNotEquals,
@@ -52,11 +55,15 @@ pub enum Operator {
 
     // The Null condition is omitted here because it is treated as a
     // quantifier, similar to ...IfExists.
+
+    // An operator this crate does not recognize, only produced by lenient
+    // parsing. It never matches, and it serializes back to its original name.
+    Unknown(String),
 }
 
 impl Operator {
     pub fn matches(&self, value: &str, target: &str) -> anyhow::Result<bool> {
-        match *self {
+        match self {
             Self::StringEquals => Ok(target == value),
             Self::StringNotEquals => Ok(target != value),
             Self::StringEqualsIgnoreCase => Ok(target.to_lowercase() == value.to_lowercase()),
@@ -89,10 +96,135 @@ impl Operator {
             Self::ArnLike => arn_like(value, target),
             Self::ArnNotEquals => arn_eq(value, target).map(bool::not),
             Self::ArnNotLike => arn_like(value, target).map(bool::not),
+
+            Self::Unknown(_) => Ok(false),
+        }
+    }
+
+    // As matches, for a target in which some parts must match literally,
+    // e.g., after substituting policy variables. Only StringLike and
+    // StringNotLike honor this; the other operators compare the target's
+    // text, so a literal '*' in an ArnLike target is still a wildcard.
+    pub fn matches_parts(&self, value: &str, target: &[GlobPart]) -> anyhow::Result<bool> {
+        match self {
+            Self::StringLike => Ok(glob_parts_match(target, value)),
+            Self::StringNotLike => Ok(!glob_parts_match(target, value)),
+            _ => self.matches(value, &to_text(target)),
+        }
+    }
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::StringEquals => "StringEquals",
+            Self::StringNotEquals => "StringNotEquals",
+            Self::StringEqualsIgnoreCase => "StringEqualsIgnoreCase",
+            Self::StringNotEqualsIgnoreCase => "StringNotEqualsIgnoreCase",
+            Self::StringLike => "StringLike",
+            Self::StringNotLike => "StringNotLike",
+            Self::NumericEquals => "NumericEquals",
+            Self::NumericNotEquals => "NumericNotEquals",
+            Self::NumericLessThan => "NumericLessThan",
+            Self::NumericLessThanEquals => "NumericLessThanEquals",
+            Self::NumericGreaterThan => "NumericGreaterThan",
+            Self::NumericGreaterThanEquals => "NumericGreaterThanEquals",
+            Self::DateEquals => "DateEquals",
+            Self::DateNotEquals => "DateNotEquals",
+            Self::DateLessThan => "DateLessThan",
+            Self::DateLessThanEquals => "DateLessThanEquals",
+            Self::DateGreaterThan => "DateGreaterThan",
+            Self::DateGreaterThanEquals => "DateGreaterThanEquals",
+            Self::Bool => "Bool",
+            Self::BinaryEquals => "BinaryEquals",
+            Self::IpAddress => "IpAddress",
+            Self::NotIpAddress => "NotIpAddress",
+            Self::ArnEquals => "ArnEquals",
+            Self::ArnLike => "ArnLike",
+            Self::ArnNotEquals => "ArnNotEquals",
+            Self::ArnNotLike => "ArnNotLike",
+            Self::Unknown(name) => name,
         }
     }
 }
 
+// Formats a number without a fractional part as an integer, since condition
+// values are usually written that way.
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+// Replaces wildcards with the shortest text they match, so the result matches
+// the pattern if it is otherwise literal.
+fn fill_glob(pattern: &str) -> String {
+    pattern.replace('*', "").replace('?', "x")
+}
+
+impl Operator {
+    // Returns a value that satisfies the operator for the given targets, for
+    // building example contexts. Equality operators yield the first target,
+    // but negated and range operators yield one representative value among
+    // many, such as one more than a NumericGreaterThan target. Returns None
+    // if no value could be derived, e.g., for an unknown operator or targets
+    // of the wrong type. The result is not guaranteed to match, so callers
+    // should check it.
+    pub fn sample_value(&self, targets: &[String]) -> Option<String> {
+        let first = targets.first()?;
+        let numbers = || targets.iter().map(|target| target.parse::<f64>().ok()).collect::<Option<Vec<_>>>();
+        let dates = || targets.iter().map(|target| DateTime::parse_from_rfc3339(target).ok()).collect::<Option<Vec<_>>>();
+        let second = Duration::seconds(1);
+        match self {
+            Self::StringEquals | Self::StringEqualsIgnoreCase | Self::Bool | Self::BinaryEquals | Self::ArnEquals => Some(first.clone()),
+            Self::StringLike | Self::ArnLike => Some(fill_glob(first)),
+            // Longer than every target, so it cannot equal any of them
+            Self::StringNotEquals | Self::StringNotEqualsIgnoreCase | Self::StringNotLike => Some(format!("{}-example", targets.join("-"))),
+
+            Self::NumericEquals | Self::NumericLessThanEquals | Self::NumericGreaterThanEquals => numbers().map(|n| format_number(n[0])),
+            Self::NumericLessThan => numbers().map(|n| format_number(n[0] - 1.0)),
+            Self::NumericGreaterThan => numbers().map(|n| format_number(n[0] + 1.0)),
+            Self::NumericNotEquals => numbers().map(|n| format_number(n.into_iter().fold(f64::MIN, f64::max) + 1.0)),
+
+            Self::DateEquals | Self::DateLessThanEquals | Self::DateGreaterThanEquals => dates().map(|d| d[0].to_rfc3339()),
+            Self::DateLessThan => dates().map(|d| (d[0] - second).to_rfc3339()),
+            Self::DateGreaterThan => dates().map(|d| (d[0] + second).to_rfc3339()),
+            Self::DateNotEquals => dates().and_then(|d| d.into_iter().max()).map(|d| (d + second).to_rfc3339()),
+
+            Self::IpAddress => IpNetwork::from_str(first).ok().map(|network| network.network().to_string()),
+            // Documentation addresses, which are unlikely to be targets
+            Self::NotIpAddress => ["192.0.2.1", "198.51.100.1", "2001:db8::1"].iter()
+                .find(|candidate| targets.iter().all(|target| ip_in_cidr(candidate, target).map(bool::not).unwrap_or(false)))
+                .map(|candidate| candidate.to_string()),
+            Self::ArnNotEquals | Self::ArnNotLike => Some("arn:aws:iam::000000000000:root".to_string()),
+
+            Self::Unknown(_) => None,
+        }
+    }
+
+    // Negated operators match a value only if it matches none of the targets,
+    // so each target must hold for the value rather than any one of them.
+    pub fn is_negated(&self) -> bool {
+        matches!(self,
+            Self::StringNotEquals | Self::StringNotEqualsIgnoreCase | Self::StringNotLike
+            | Self::NumericNotEquals | Self::DateNotEquals | Self::NotIpAddress
+            | Self::ArnNotEquals | Self::ArnNotLike)
+    }
+
+    // Like parse, but an unrecognized name becomes Unknown instead of an error.
+    pub fn parse_lenient(s: &str) -> Self {
+        s.parse().unwrap_or_else(|_| Self::Unknown(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl FromStr for Operator {
     type Err = anyhow::Error;
 
@@ -1,78 +1,159 @@
 use super::operator::Operator;
+use crate::aws::GlobPart;
+use crate::policy::variables::to_text;
 
 use anyhow::anyhow;
 
 /*
-In this implementation, ...IfExists is represented by ForAnyValue, since they
-are functionally equivalent.
+Each spelling of an operator key has its own variant, so that two blocks in the
+same Condition element never collide, and so the key can be written back as it
+was parsed. ForAllValues:...IfExists evaluates the same as ForAllValues, since
+that already matches a missing key, and applies the operator to every present
+value.
+
+A key can also be present with no values, e.g., aws:TagKeys for a request
+that sets no tags, which AWS calls a null data set. ForAllValues matches it,
+ForAnyValue does not, even for a negated operator, and Null treats it the same
+as a missing key.
+See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_condition-single-vs-multi-valued-context-keys.html
  */
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Quantifier {
+	// A plain operator without a set qualifier. Returns true if at least one
+	// value in the context key is true. A missing key matches a negated
+	// operator such as StringNotEquals, as it does in AWS.
+	Plain(Operator),
+	// A plain operator with IfExists. Returns true if the key is missing or
+	// has no values, and otherwise is the same as Plain.
+	IfExists(Operator),
 	// Returns true if every value for the context key is true.
 	// This is trivially true if there are no values or the value resolves to
 	// a null data set. Use of ForAllValues with Allow is discouraged because
 	// it is overly permissive.
 	ForAllValues(Operator),
-	// Returns true if at least one value in the context key is true. This
-	// also represents ...IfExists for single-valued keys.
+	ForAllValuesIfExists(Operator),
+	// Returns true if at least one value in the context key is true. Unlike
+	// Plain, a missing key never matches, even for a negated operator.
 	ForAnyValue(Operator),
 	// Returns true if the emptiness of the set matches the condition target.
+	// A key that is missing or has no values is null.
 	Null,
 }
 
 impl Quantifier {
+	pub fn operator(&self) -> Option<&Operator> {
+		match self {
+			Self::Plain(op) | Self::IfExists(op) | Self::ForAllValues(op) | Self::ForAllValuesIfExists(op) | Self::ForAnyValue(op) => Some(op),
+			Self::Null => None,
+		}
+	}
+
 	pub fn matches(&self, values: Option<&Vec<String>>, targets: &[String]) -> anyhow::Result<bool> {
+		let matches = |op: &Operator, value: &str, target: &String| op.matches(value, target);
 		match self {
-			Self::ForAllValues(op) => matches_all(op, values, targets),
-			Self::ForAnyValue(op) => matches_any(op, values, targets),
 			Self::Null => matches_null(values, targets),
+			_ => self.matches_with(values, targets, &matches),
 		}
 	}
 
+	// As matches, for targets that had policy variables substituted, so some
+	// of their parts match literally. See Operator::matches_parts.
+	pub fn matches_parts(&self, values: Option<&Vec<String>>, targets: &[Vec<GlobPart>]) -> anyhow::Result<bool> {
+		let matches = |op: &Operator, value: &str, target: &Vec<GlobPart>| op.matches_parts(value, target);
+		match self {
+			Self::Null => matches_null(values, &targets.iter().map(|target| to_text(target)).collect::<Vec<_>>()),
+			_ => self.matches_with(values, targets, &matches),
+		}
+	}
+
+	fn matches_with<T>(&self, values: Option<&Vec<String>>, targets: &[T], matches: &Matcher<T>) -> anyhow::Result<bool> {
+		// An unknown operator never matches, even where a missing key would
+		if let Some(Operator::Unknown(_)) = self.operator() {
+			return Ok(false);
+		}
+		match self {
+			Self::Plain(op) => match values {
+				Some(values) => matches_any(op, values, targets, matches),
+				None => Ok(op.is_negated()),
+			},
+			Self::ForAnyValue(op) => matches_any(op, values.map_or(&[][..], Vec::as_slice), targets, matches),
+			Self::IfExists(op) => match values {
+				Some(values) if !values.is_empty() => matches_any(op, values, targets, matches),
+				_ => Ok(true),
+			},
+			Self::ForAllValues(op) | Self::ForAllValuesIfExists(op) => matches_all(op, values, targets, matches),
+			Self::Null => unreachable!("Null is matched by matches_null"),
+		}
+	}
 }
 
-fn matches_all(op: &Operator, values: Option<&Vec<String>>, targets: &[String]) -> anyhow::Result<bool> {
-	let values = match values {
-		Some(v) => v,
-		None => return Ok(true),
-	};
-	values.iter().try_fold(true, |result, value| {
-		if !result {
-			return Ok(result);
+// Formats the quantifier as it appears as a key in a Condition block.
+impl std::fmt::Display for Quantifier {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Plain(op) => write!(f, "{}", op),
+			Self::IfExists(op) => write!(f, "{}IfExists", op),
+			Self::ForAllValues(op) => write!(f, "ForAllValues:{}", op),
+			Self::ForAllValuesIfExists(op) => write!(f, "ForAllValues:{}IfExists", op),
+			Self::ForAnyValue(op) => write!(f, "ForAnyValue:{}", op),
+			Self::Null => f.write_str("Null"),
 		}
+	}
+}
+
+type Matcher<T> = dyn Fn(&Operator, &str, &T) -> anyhow::Result<bool>;
+
+// Whether a single value satisfies the operator for a list of targets. The
+// targets are alternatives, so a positive operator needs any target to match,
+// while a negated operator needs the value to differ from every target: "a"
+// does not satisfy StringNotEquals ["a", "b"].
+fn matches_targets<T>(op: &Operator, value: &str, targets: &[T], matches: &Matcher<T>) -> anyhow::Result<bool> {
+	if op.is_negated() {
+		targets.iter().try_fold(true, |result, target| {
+			if !result {
+				Ok(result)
+			} else {
+				matches(op, value, target)
+			}
+		})
+	} else {
 		targets.iter().try_fold(false, |found, target| {
 			if found {
 				Ok(found)
 			} else {
-				op.matches(value, target)
+				matches(op, value, target)
 			}
 		})
-	})
+	}
 }
 
-fn matches_any(op: &Operator, values: Option<&Vec<String>>, targets: &[String]) -> anyhow::Result<bool> {
+fn matches_all<T>(op: &Operator, values: Option<&Vec<String>>, targets: &[T], matches: &Matcher<T>) -> anyhow::Result<bool> {
 	let values = match values {
 		Some(v) => v,
-		None => return Ok(false),
+		None => return Ok(true),
 	};
+	values.iter().try_fold(true, |result, value| {
+		if !result {
+			return Ok(result);
+		}
+		matches_targets(op, value, targets, matches)
+	})
+}
+
+fn matches_any<T>(op: &Operator, values: &[String], targets: &[T], matches: &Matcher<T>) -> anyhow::Result<bool> {
 	values.iter().try_fold(false, |result, value| {
 		if result {
 			return Ok(result);
 		}
-		targets.iter().try_fold(false, |found, target| {
-			if found {
-				Ok(found)
-			} else {
-				op.matches(value, target)
-			}
-		})
+		matches_targets(op, value, targets, matches)
 	})
 }
 
 fn matches_null(values: Option<&Vec<String>>, targets: &[String]) -> anyhow::Result<bool> {
 	if targets.len() == 1 {
-		Ok(values.is_none() == (&targets[0] == "true"))
+		let is_null = values.is_none_or(Vec::is_empty);
+		Ok(is_null == (&targets[0] == "true"))
 	} else {
 		Err(anyhow!("Null condition must take exactly one argument"))
 	}
@@ -127,6 +208,37 @@ mod test {
 		assert!(! quant.matches(Some(&values), &targets).unwrap());
 	}
 
+	#[test]
+	fn forall_not_equals() {
+		let quant = Quantifier::ForAllValues(Operator::StringNotEquals);
+		let targets = vec!["a".to_string()];
+		let values = vec!["a".to_string(), "b".to_string()];
+		assert!(! quant.matches(Some(&values), &targets).unwrap());
+		let values = vec!["b".to_string(), "c".to_string()];
+		assert!(quant.matches(Some(&values), &targets).unwrap());
+	}
+
+	#[test]
+	fn forall_not_equals_multi_targets() {
+		// Every value must differ from every target, not just from one.
+		let quant = Quantifier::ForAllValues(Operator::StringNotEquals);
+		let targets = vec!["a".to_string(), "b".to_string()];
+		let values = vec!["a".to_string()];
+		assert!(! quant.matches(Some(&values), &targets).unwrap());
+		let values = vec!["c".to_string(), "d".to_string()];
+		assert!(quant.matches(Some(&values), &targets).unwrap());
+	}
+
+	#[test]
+	fn forany_not_equals_multi_targets() {
+		let quant = Quantifier::ForAnyValue(Operator::StringNotEquals);
+		let targets = vec!["a".to_string(), "b".to_string()];
+		let values = vec!["a".to_string(), "b".to_string()];
+		assert!(! quant.matches(Some(&values), &targets).unwrap());
+		let values = vec!["a".to_string(), "c".to_string()];
+		assert!(quant.matches(Some(&values), &targets).unwrap());
+	}
+
 	#[test]
 	fn forany_empty() {
 		let op = Operator::StringEquals;
@@ -191,4 +303,52 @@ mod test {
 		assert!(quant.matches(None, &targets_zero).is_err());
 		assert!(quant.matches(None, &targets_multi).is_err());
 	}
+
+	#[test]
+	fn null_data_set() {
+		let targets = vec!["a".to_string()];
+		let empty = Vec::<String>::new();
+		let populated = vec!["a".to_string()];
+		let check = |quant: &Quantifier, targets: &[String]| [None, Some(&empty), Some(&populated)]
+			.map(|values| quant.matches(values, targets).unwrap());
+
+		assert_eq!(check(&Quantifier::ForAllValues(Operator::StringEquals), &targets), [true, true, true]);
+		assert_eq!(check(&Quantifier::ForAnyValue(Operator::StringEquals), &targets), [false, false, true]);
+		assert_eq!(check(&Quantifier::ForAnyValue(Operator::StringNotEquals), &targets), [false, false, false]);
+		// A plain negated operator matches a missing key, but not an empty one
+		assert_eq!(check(&Quantifier::Plain(Operator::StringNotEquals), &targets), [true, false, false]);
+		assert_eq!(check(&Quantifier::IfExists(Operator::StringEquals), &targets), [true, true, true]);
+		assert_eq!(check(&Quantifier::Null, &["true".to_string()]), [true, true, false]);
+		assert_eq!(check(&Quantifier::Null, &["false".to_string()]), [false, false, true]);
+	}
+
+	#[test]
+	fn unknown_operator_never_matches() {
+		let unknown = Operator::Unknown("StringMatches".to_string());
+		let targets = vec!["a".to_string()];
+		let empty = Vec::<String>::new();
+		let populated = vec!["a".to_string()];
+		for quant in [
+			Quantifier::Plain(unknown.clone()),
+			Quantifier::IfExists(unknown.clone()),
+			Quantifier::ForAllValues(unknown.clone()),
+			Quantifier::ForAllValuesIfExists(unknown.clone()),
+			Quantifier::ForAnyValue(unknown.clone()),
+		] {
+			for values in [None, Some(&empty), Some(&populated)] {
+				assert!(!quant.matches(values, &targets).unwrap(), "{} matched {:?}", quant, values);
+			}
+		}
+	}
+
+	#[test]
+	fn forany_negated_missing_key() {
+		let targets = vec!["vpc-12345".to_string()];
+		assert!(Quantifier::Plain(Operator::StringNotEquals).matches(None, &targets).unwrap());
+		assert!(Quantifier::Plain(Operator::NotIpAddress).matches(None, &["10.0.0.0/8".to_string()]).unwrap());
+		assert!(!Quantifier::Plain(Operator::StringEquals).matches(None, &targets).unwrap());
+		// An explicit ForAnyValue needs a value, even when negated
+		assert!(!Quantifier::ForAnyValue(Operator::StringNotEquals).matches(None, &targets).unwrap());
+		assert!(!Quantifier::ForAnyValue(Operator::NotIpAddress).matches(None, &["10.0.0.0/8".to_string()]).unwrap());
+	}
 }
@@ -0,0 +1,107 @@
+use super::global::{self, Cardinality, Type};
+use super::operator::Operator;
+use super::s3;
+use super::ConditionError;
+
+use std::collections::HashMap;
+
+pub type KeyTable = &'static [(&'static str, Type, Cardinality)];
+
+// Condition keys are partitioned by their prefix, e.g., "aws:" for global
+// keys or "s3:" for keys specific to Amazon S3. Keys are case-insensitive,
+// and keys of the form prefix:Name/tag-key are looked up as prefix:Name.
+#[derive(Debug, Clone)]
+pub struct KeyRegistry {
+	tables: HashMap<String, KeyTable>,
+}
+
+impl KeyRegistry {
+	pub fn new() -> Self {
+		KeyRegistry{ tables: HashMap::new() }
+	}
+
+	// A registry containing the global keys and the built-in service tables.
+	pub fn with_builtins() -> Self {
+		let mut registry = Self::new();
+		registry.register("aws", global::AWS);
+		registry.register("s3", s3::S3);
+		registry
+	}
+
+	pub fn register(&mut self, prefix: &str, table: KeyTable) -> Option<KeyTable> {
+		self.tables.insert(prefix.to_lowercase(), table)
+	}
+
+	pub fn lookup(&self, key: &str) -> Option<(Type, Cardinality)> {
+		let (prefix, name) = key.split_once(':')?;
+		let name = name.split_once('/').map_or(name, |(name, _)| name);
+		let table = self.tables.get(&prefix.to_lowercase())?;
+		table.iter()
+			.find(|(entry, _, _)| entry.eq_ignore_ascii_case(name))
+			.map(|(_, ty, cardinality)| (*ty, *cardinality))
+	}
+
+	// Unknown keys are accepted, since they cannot be validated.
+	pub fn check_operator(&self, key: &str, op: &Operator) -> Result<(), ConditionError> {
+		match self.lookup(key) {
+			Some((ty, _)) if !ty.accepts(op) => Err(ConditionError::TypeMismatch),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl Default for KeyRegistry {
+	fn default() -> Self { KeyRegistry::new() }
+}
+
+#[cfg(test)]
+mod test {
+	use super::KeyRegistry;
+	use super::super::global::{Cardinality, Type};
+	use super::super::operator::Operator;
+
+	#[test]
+	fn lookup_global_key() {
+		let registry = KeyRegistry::with_builtins();
+		assert_eq!(registry.lookup("aws:SourceIp"), Some((Type::IpAddress, Cardinality::Optional)));
+		assert_eq!(registry.lookup("aws:sourceip"), Some((Type::IpAddress, Cardinality::Optional)));
+		assert_eq!(registry.lookup("aws:PrincipalTag/Department"), Some((Type::String, Cardinality::Optional)));
+		assert_eq!(registry.lookup("aws:NoSuchKey"), None);
+	}
+
+	#[test]
+	fn lookup_service_key() {
+		let registry = KeyRegistry::with_builtins();
+		assert_eq!(registry.lookup("s3:max-keys"), Some((Type::Numeric, Cardinality::Optional)));
+		assert_eq!(registry.lookup("ec2:InstanceType"), None);
+		assert_eq!(KeyRegistry::new().lookup("s3:max-keys"), None);
+	}
+
+	#[test]
+	fn register_service_table() {
+		const EC2: &[(&str, Type, Cardinality)] = &[
+			("InstanceType", Type::String, Cardinality::Optional),
+		];
+		let mut registry = KeyRegistry::with_builtins();
+		registry.register("ec2", EC2);
+		assert_eq!(registry.lookup("ec2:InstanceType"), Some((Type::String, Cardinality::Optional)));
+		assert!(registry.check_operator("ec2:InstanceType", &Operator::StringEquals).is_ok());
+		assert!(registry.check_operator("ec2:InstanceType", &Operator::NumericEquals).is_err());
+	}
+
+	#[test]
+	fn check_numeric_service_key() {
+		let registry = KeyRegistry::with_builtins();
+		assert!(registry.check_operator("s3:max-keys", &Operator::NumericLessThanEquals).is_ok());
+		assert!(registry.check_operator("s3:max-keys", &Operator::StringEquals).is_err());
+		assert!(registry.check_operator("s3:unknown", &Operator::StringEquals).is_ok());
+	}
+
+	#[test]
+	fn check_epoch_key() {
+		let registry = KeyRegistry::with_builtins();
+		assert!(registry.check_operator("aws:EpochTime", &Operator::NumericGreaterThan).is_ok());
+		assert!(registry.check_operator("aws:EpochTime", &Operator::DateGreaterThan).is_ok());
+		assert!(registry.check_operator("aws:EpochTime", &Operator::Bool).is_err());
+	}
+}
@@ -0,0 +1,31 @@
+// A list of Amazon S3 service keys with types and cardinality
+// All these keys have a "s3:" prefix.
+
+use super::global::{Cardinality, Type};
+
+use Type::*;
+use Cardinality::*;
+
+// See: https://docs.aws.amazon.com/service-authorization/latest/reference/list_amazons3.html#amazons3-policy-keys
+pub const S3: &[(&str, Type, Cardinality)] = &[
+	("authType", String, Optional),
+	("delimiter", String, Optional),
+	// Used in the form s3:ExistingObjectTag/tag-key
+	("ExistingObjectTag", String, Optional),
+	("LocationConstraint", String, Optional),
+	("max-keys", Numeric, Optional),
+	("object-lock-remaining-retention-days", Numeric, Optional),
+	("object-lock-retain-until-date", Date, Optional),
+	("prefix", String, Optional),
+	// Used in the form s3:RequestObjectTag/tag-key
+	("RequestObjectTag", String, Optional),
+	("RequestObjectTagKeys", String, Multiple),
+	("signatureAge", Numeric, Optional),
+	("signatureversion", String, Optional),
+	("TlsVersion", Numeric, Optional),
+	("VersionId", String, Optional),
+	("x-amz-acl", String, Optional),
+	("x-amz-content-sha256", String, Optional),
+	("x-amz-server-side-encryption", String, Optional),
+	("x-amz-server-side-encryption-aws-kms-key-id", ARN, Optional),
+];
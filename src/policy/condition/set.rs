@@ -0,0 +1,255 @@
+use super::{ConditionList, ConditionValues};
+use super::global::Cardinality;
+use super::operator::Operator;
+use super::quantifier::Quantifier;
+use super::registry::KeyRegistry;
+use crate::policy::options::ParseOptions;
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+/*
+A statement's conditions, made up of one or more condition lists that must all
+match. Parsing a Condition element yields a single list, but more can be added,
+e.g., to combine the conditions of two statements.
+
+No two lists constrain the same key with the same quantifier, so the set can
+always be written back as a single Condition element.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConditionSet {
+    lists: Vec<ConditionList>,
+}
+
+impl ConditionSet {
+    pub fn new() -> Self {
+        ConditionSet{ lists: Vec::new() }
+    }
+
+    // Adds a list that must match in addition to the existing ones. Fails if
+    // the list constrains a key with a quantifier already used for that key.
+    pub fn push(&mut self, list: ConditionList) -> anyhow::Result<()> {
+        for (quant, values) in list.iter() {
+            for key in values.keys() {
+                if self.iter().any(|(other, values)| other == quant && values.contains_key(key)) {
+                    return Err(anyhow!("condition set already has {} on {}", quant, key));
+                }
+            }
+        }
+        self.lists.push(list);
+        Ok(())
+    }
+
+    pub fn lists(&self) -> std::slice::Iter<'_, ConditionList> {
+        self.lists.iter()
+    }
+
+    // Iterates over the operator blocks of every list.
+    pub fn iter(&self) -> impl Iterator<Item = (&Quantifier, &ConditionValues)> {
+        self.lists.iter().flat_map(|list| list.iter())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.lists.iter().flat_map(|list| list.keys())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn matches(&self, value_map: &HashMap<String, Vec<String>>) -> anyhow::Result<bool> {
+        self.lists.iter().try_fold(true, |result, list| {
+            // Short-circuit on the first failure to match
+            if !result {
+                return Ok(result);
+            }
+            list.matches(value_map)
+        })
+    }
+
+    // As matches, substituting policy variables. See
+    // ConditionList::matches_with_variables.
+    pub fn matches_with_variables(&self, value_map: &HashMap<String, Vec<String>>) -> anyhow::Result<bool> {
+        for list in &self.lists {
+            if !list.matches_with_variables(value_map)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Best-effort detection of conditions that no context can satisfy. Keys
+    // are assumed to be single-valued unless they are known multi-valued
+    // keys, e.g. aws:TagKeys. Detects:
+    // - a plain operator that needs a value, but has no targets
+    // - an equality operator alongside its negation on the same key, where
+    //   the negation excludes every target, e.g. StringEquals "a" with
+    //   StringNotEquals ["a", "b"]
+    // - Null "true" alongside a plain operator that needs a value
+    pub fn is_satisfiable(&self) -> bool {
+        let registry = KeyRegistry::with_builtins();
+        let entries: Vec<(&Quantifier, &String, &Vec<String>)> = self.iter()
+            .flat_map(|(quant, values)| values.iter().map(move |(key, targets)| (quant, key, targets)))
+            .collect();
+        let needs_value = |quant: &Quantifier| matches!(quant, Quantifier::Plain(op) | Quantifier::ForAnyValue(op) if !op.is_negated());
+        if entries.iter().any(|(quant, _, targets)| needs_value(quant) && targets.is_empty()) {
+            return false;
+        }
+        !entries.iter().any(|first| entries.iter().any(|second| {
+            if !first.1.eq_ignore_ascii_case(second.1) {
+                return false;
+            }
+            match (first.0, second.0) {
+                (Quantifier::Plain(op) | Quantifier::ForAnyValue(op), Quantifier::Plain(negated) | Quantifier::ForAnyValue(negated)) => {
+                    let multi_valued = matches!(registry.lookup(first.1), Some((_, Cardinality::Multiple)));
+                    !multi_valued && negation_of(op).as_ref() == Some(negated) && first.2.iter().all(|target| {
+                        !second.0.matches(Some(&vec![target.clone()]), second.2).unwrap_or(true)
+                    })
+                }
+                (Quantifier::Null, quant) if needs_value(quant) => first.2.iter().all(|target| target == "true"),
+                _ => false,
+            }
+        }))
+    }
+
+    pub fn validate(&self, registry: &KeyRegistry) -> anyhow::Result<()> {
+        self.lists.iter().try_for_each(|list| list.validate(registry))
+    }
+
+    // Combines the lists into one, which is equivalent since no two lists
+    // constrain the same key with the same quantifier.
+    pub fn to_list(&self) -> ConditionList {
+        let mut result = ConditionList::new();
+        for (quant, values) in self.iter() {
+            result.extend(quant, values);
+        }
+        result
+    }
+
+    pub fn sample_values(&self) -> Option<ConditionValues> {
+        self.to_list().sample_values()
+    }
+
+    pub fn from_json_with_options(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Self> {
+        ConditionList::from_json_with_options(value, options).map(Self::from)
+    }
+}
+
+// The negation of an equality operator, for which a value that equals a
+// target always fails the negation for that target.
+fn negation_of(op: &Operator) -> Option<Operator> {
+    match op {
+        Operator::StringEquals => Some(Operator::StringNotEquals),
+        Operator::StringEqualsIgnoreCase => Some(Operator::StringNotEqualsIgnoreCase),
+        Operator::NumericEquals => Some(Operator::NumericNotEquals),
+        Operator::DateEquals => Some(Operator::DateNotEquals),
+        Operator::ArnEquals => Some(Operator::ArnNotEquals),
+        _ => None,
+    }
+}
+
+impl From<ConditionList> for ConditionSet {
+    fn from(list: ConditionList) -> Self {
+        ConditionSet{ lists: vec![list] }
+    }
+}
+
+impl TryFrom<&json::JsonValue> for ConditionSet {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+        Self::from_json_with_options(value, &ParseOptions::default())
+    }
+}
+
+impl From<&ConditionSet> for json::JsonValue {
+    fn from(set: &ConditionSet) -> Self {
+        json::JsonValue::from(&set.to_list())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConditionSet;
+    use crate::policy::condition::ConditionList;
+
+    use std::collections::HashMap;
+
+    fn values(entries: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+        entries.iter().map(|(key, value)| (key.to_string(), vec![value.to_string()])).collect()
+    }
+
+    #[test]
+    fn parse_and_match() {
+        let source = json::parse(r#"{
+            "StringEquals": {"aws:PrincipalTag/team": "storage"},
+            "IpAddress": {"aws:SourceIp": "203.0.113.0/24"},
+            "Bool": {"aws:SecureTransport": "true"}
+        }"#).unwrap();
+        let set = ConditionSet::try_from(&source).unwrap();
+        assert_eq!(set.lists().count(), 1);
+        assert_eq!(set.iter().count(), 3);
+        let mut keys: Vec<&String> = set.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["aws:PrincipalTag/team", "aws:SecureTransport", "aws:SourceIp"]);
+
+        let passing = [("aws:PrincipalTag/team", "storage"), ("aws:SourceIp", "203.0.113.5"), ("aws:SecureTransport", "true")];
+        assert!(set.matches(&values(&passing)).unwrap());
+        let failing = [("aws:PrincipalTag/team", "storage"), ("aws:SourceIp", "198.51.100.5"), ("aws:SecureTransport", "true")];
+        assert!(!set.matches(&values(&failing)).unwrap());
+
+        assert_eq!(json::JsonValue::from(&set), source);
+    }
+
+    #[test]
+    fn is_satisfiable() {
+        let parse = |source: &str| ConditionSet::try_from(&json::parse(source).unwrap()).unwrap();
+        let unsatisfiable = [
+            r#"{"StringEquals": {"aws:username": "alice"}, "StringNotEquals": {"aws:username": "alice"}}"#,
+            r#"{"StringEquals": {"aws:username": "alice"}, "StringNotEquals": {"AWS:UserName": ["alice", "bob"]}}"#,
+            r#"{"StringEqualsIgnoreCase": {"aws:username": "Alice"}, "StringNotEqualsIgnoreCase": {"aws:username": "ALICE"}}"#,
+            r#"{"NumericEquals": {"s3:max-keys": "10"}, "NumericNotEquals": {"s3:max-keys": "10.0"}}"#,
+            r#"{"Null": {"aws:SourceIp": "true"}, "IpAddress": {"aws:SourceIp": "203.0.113.0/24"}}"#,
+            r#"{"StringEquals": {"aws:username": []}}"#,
+        ];
+        for source in unsatisfiable {
+            assert!(!parse(source).is_satisfiable(), "{}", source);
+        }
+
+        let satisfiable = [
+            r#"{"StringEquals": {"aws:username": ["alice", "bob"]}, "StringNotEquals": {"aws:username": "alice"}}"#,
+            r#"{"StringEquals": {"aws:username": "alice"}, "StringNotEquals": {"aws:userid": "alice"}}"#,
+            r#"{"Null": {"aws:SourceIp": "false"}, "IpAddress": {"aws:SourceIp": "203.0.113.0/24"}}"#,
+            r#"{"Null": {"aws:SourceIp": "true"}, "NotIpAddress": {"aws:SourceIp": "203.0.113.0/24"}}"#,
+            r#"{"Null": {"aws:SourceIp": "true"}, "ForAllValues:StringEquals": {"aws:SourceIp": "203.0.113.1"}}"#,
+            // A multi-valued key can have a value equal to a target and another
+            // value that differs from it
+            r#"{"StringEquals": {"aws:TagKeys": "Env"}, "StringNotEquals": {"aws:TagKeys": "Env"}}"#,
+            r#"{"StringLike": {"aws:username": "a*"}, "StringNotLike": {"aws:username": "a*"}}"#,
+        ];
+        for source in satisfiable {
+            assert!(parse(source).is_satisfiable(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn multiple_lists() {
+        let mut set = ConditionSet::try_from(&json::parse(r#"{"StringEquals": {"aws:username": "alice"}}"#).unwrap()).unwrap();
+        let mfa = ConditionList::try_from(&json::parse(r#"{"Bool": {"aws:MultiFactorAuthPresent": "true"}}"#).unwrap()).unwrap();
+        set.push(mfa).unwrap();
+        assert_eq!(set.lists().count(), 2);
+        assert!(set.matches(&values(&[("aws:username", "alice"), ("aws:MultiFactorAuthPresent", "true")])).unwrap());
+        assert!(!set.matches(&values(&[("aws:username", "alice"), ("aws:MultiFactorAuthPresent", "false")])).unwrap());
+
+        let serialized = json::JsonValue::from(&set);
+        assert_eq!(serialized["StringEquals"]["aws:username"], "alice");
+        assert_eq!(serialized["Bool"]["aws:MultiFactorAuthPresent"], "true");
+
+        // The same key with the same operator cannot be combined
+        let conflict = ConditionList::try_from(&json::parse(r#"{"StringEquals": {"aws:username": "bob"}}"#).unwrap()).unwrap();
+        assert!(set.push(conflict).is_err());
+        assert_eq!(set.lists().count(), 2);
+    }
+}
@@ -1,19 +1,101 @@
-use crate::aws::{glob_matches, ARN};
+use crate::aws::{glob_covers, glob_parts_match, globs_overlap, CompiledGlob, ARN};
 use crate::iam::{Action, Principal};
+use super::context::ResourceContext;
+use super::options::ParseOptions;
+use super::variables::{has_variables, split_parts, substitute, VariableError};
+
+use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::anyhow;
 
+/*
+Action, resource and principal patterns are compiled when they are parsed,
+since a policy is usually checked against many requests. A pattern that cannot
+be compiled is a parse error.
+ */
+
+// An action pattern with its service and action name compiled for matching,
+// which ignores case. It dereferences to the pattern as written.
 #[derive(Debug, Clone)]
+pub struct ActionPattern {
+    action: Action,
+    service: CompiledGlob,
+    name: CompiledGlob,
+}
+
+impl ActionPattern {
+    pub fn new(action: Action) -> anyhow::Result<Self> {
+        let service = CompiledGlob::new_ignore_case(action.service())?;
+        let name = CompiledGlob::new_ignore_case(action.action())?;
+        Ok(ActionPattern{ action, service, name })
+    }
+
+    pub fn matches(&self, action: &Action) -> bool {
+        self.service.is_match(action.service()) && self.name.is_match(action.action())
+    }
+}
+
+impl Deref for ActionPattern {
+    type Target = Action;
+
+    fn deref(&self) -> &Action {
+        &self.action
+    }
+}
+
+impl PartialEq for ActionPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.action == other.action
+    }
+}
+
+impl Eq for ActionPattern {}
+
+impl FromStr for ActionPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        let action = value.parse().map_err(|_| anyhow!("expected an action pattern, found {}", value))?;
+        Self::new(action)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ActionConstraint {
     Any,
-    Pattern(Action),
+    Pattern(ActionPattern),
 }
 
 impl ActionConstraint {
     pub fn matches(&self, action: &Action) -> bool {
         match self {
             Self::Any => true,
-            Self::Pattern(pattern) => glob_matches(pattern.service(), action.service()) && glob_matches(pattern.action(), action.action()),
+            Self::Pattern(pattern) => pattern.matches(action),
+        }
+    }
+
+    // Whether this constraint matches at least one of the actions matched by
+    // the action pattern.
+    pub fn overlaps(&self, actions: &Action) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Pattern(pattern) => {
+                let (pattern, actions) = (pattern.to_lowercase(), actions.to_lowercase());
+                globs_overlap(pattern.service(), actions.service()) && globs_overlap(pattern.action(), actions.action())
+            }
+        }
+    }
+
+    // Whether this constraint matches every action matched by the action
+    // pattern.
+    pub fn covers(&self, actions: &Action) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Pattern(pattern) => {
+                let (pattern, actions) = (pattern.to_lowercase(), actions.to_lowercase());
+                glob_covers(pattern.service(), actions.service()) && glob_covers(pattern.action(), actions.action())
+            }
         }
     }
 }
@@ -28,48 +110,121 @@ impl TryFrom<&json::JsonValue> for ActionConstraint {
             return Ok(Self::Any);
         }
         value.parse().map(Self::Pattern)
-            .map_err(|_| anyhow!("expected Action to be an action pattern"))
+            .map_err(|err| anyhow!("expected Action to be an action pattern: {}", err))
     }
 }
 
-// TODO: You can specify multiple principals, including of different types.
+impl From<&ActionConstraint> for json::JsonValue {
+    fn from(constraint: &ActionConstraint) -> Self {
+        match constraint {
+            ActionConstraint::Any => "*".into(),
+            ActionConstraint::Pattern(action) => action.to_string().into(),
+        }
+    }
+}
+
+// A principal pattern compiled for matching. AWS principals are matched
+// segment by segment, as with ResourcePattern, and so are SAML and OIDC
+// providers named by ARN, so a wildcard in the provider name stays in the
+// resource. It dereferences to the principal as written.
 #[derive(Debug, Clone)]
-pub enum PrincipalConstraint {
-    Any,
-    AWSAny,
-    Pattern(Principal),
+pub struct PrincipalPattern {
+    principal: Principal,
+    matcher: PrincipalMatcher,
 }
 
-impl PrincipalConstraint {
-    fn matches_aws(arn: &ARN, other: &Principal) -> bool {
-        if let Principal::AWS(other) = other {
-            glob_matches(arn.raw(), other.raw())
-        } else {
-            false
-        }
+#[derive(Debug, Clone)]
+enum PrincipalMatcher {
+    Arn(ResourcePattern),
+    // A provider ARN, if the pattern is one, and the whole pattern for
+    // providers that are not named by ARN.
+    Federated(Option<ResourcePattern>, CompiledGlob),
+    Service(CompiledGlob),
+    CanonicalUser(CompiledGlob),
+    Anonymous,
+}
+
+impl PrincipalPattern {
+    pub fn new(principal: Principal) -> anyhow::Result<Self> {
+        let matcher = match &principal {
+            Principal::AWS(arn) => PrincipalMatcher::Arn(ResourcePattern::new(arn.clone())?),
+            Principal::Federated(s) => {
+                let arn = s.parse::<ARN>().ok().map(ResourcePattern::new).transpose()?;
+                PrincipalMatcher::Federated(arn, CompiledGlob::new(s)?)
+            },
+            Principal::Service(s) => PrincipalMatcher::Service(CompiledGlob::new(s)?),
+            Principal::CanonicalUser(s) => PrincipalMatcher::CanonicalUser(CompiledGlob::new(s)?),
+            Principal::Anonymous => PrincipalMatcher::Anonymous,
+        };
+        Ok(PrincipalPattern{ principal, matcher })
     }
 
-    fn matches_federated(s: &str, other: &Principal) -> bool {
-        if let Principal::Federated(other) = other {
-            glob_matches(s, other)
-        } else {
-            false
+    pub fn matches(&self, other: &Principal) -> bool {
+        match (&self.matcher, other) {
+            (PrincipalMatcher::Arn(pattern), Principal::AWS(arn)) => pattern.matches(arn),
+            (PrincipalMatcher::Federated(Some(pattern), glob), Principal::Federated(id)) => match id.parse::<ARN>() {
+                Ok(arn) => pattern.matches(&arn),
+                Err(_) => glob.is_match(id),
+            },
+            (PrincipalMatcher::Federated(None, glob), Principal::Federated(id))
+                | (PrincipalMatcher::Service(glob), Principal::Service(id))
+                | (PrincipalMatcher::CanonicalUser(glob), Principal::CanonicalUser(id)) => glob.is_match(id),
+            (PrincipalMatcher::Anonymous, Principal::Anonymous) => true,
+            _ => false,
         }
     }
+}
+
+impl Deref for PrincipalPattern {
+    type Target = Principal;
+
+    fn deref(&self) -> &Principal {
+        &self.principal
+    }
+}
+
+impl PartialEq for PrincipalPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.principal == other.principal
+    }
+}
+
+impl Eq for PrincipalPattern {}
 
-    fn matches_service(s: &str, other: &Principal) -> bool {
-        if let Principal::Service(other) = other {
-            glob_matches(s, other)
-        } else {
-            false
+// TODO: You can specify multiple principals, including of different types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrincipalConstraint {
+    Any,
+    AWSAny,
+    Pattern(PrincipalPattern),
+}
+
+impl PrincipalConstraint {
+
+    // Whether the constraint applies to the principal as an element of a
+    // Principal clause. There, an account root such as
+    // arn:aws:iam::123456789012:root, or a bare account ID, which parses to
+    // the same thing, grants access to every identity in the account, since
+    // the account's own IAM policies decide who can use it. Under
+    // NotPrincipal, use matches instead, since there the root only exempts
+    // the root itself.
+    // See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_elements_principal.html#principal-accounts
+    pub fn grants(&self, other: &Principal) -> bool {
+        match (self.account_root(), other) {
+            (Some(root), Principal::AWS(arn)) => {
+                root.partition() == arn.partition() && root.account() == arn.account()
+            },
+            _ => self.matches(other),
         }
     }
 
-    fn matches_canonicaluser(s: &str, other: &Principal) -> bool {
-        if let Principal::CanonicalUser(other) = other {
-            glob_matches(s, other)
-        } else {
-            false
+    fn account_root(&self) -> Option<&ARN> {
+        match self {
+            Self::Pattern(pattern) => match &**pattern {
+                Principal::AWS(arn) if arn.service() == "iam" && arn.resource() == "root" => Some(arn),
+                _ => None,
+            },
+            _ => None,
         }
     }
 
@@ -77,41 +232,309 @@ impl PrincipalConstraint {
         match self {
             Self::Any => true,
             Self::AWSAny => matches![other, Principal::AWS(_)],
-            Self::Pattern(principal) => match principal {
-                Principal::AWS(arn) => Self::matches_aws(arn, other),
-                Principal::Federated(s) => Self::matches_federated(s, other),
-                Principal::Service(s) => Self::matches_service(s, other),
-                Principal::CanonicalUser(s) => Self::matches_canonicaluser(s, other),
-            }
+            Self::Pattern(pattern) => pattern.matches(other),
         }
     }
 }
 
+// An ARN pattern with each segment compiled for matching. As with
+// ARN::matches_pattern, wildcards stay within their segment. It dereferences
+// to the pattern as written.
 #[derive(Debug, Clone)]
+pub struct ResourcePattern {
+    arn: ARN,
+    segments: Vec<CompiledGlob>,
+}
+
+impl ResourcePattern {
+    pub fn new(arn: ARN) -> anyhow::Result<Self> {
+        let segments = [arn.partition(), arn.service(), arn.region(), arn.account(), arn.resource()].into_iter()
+            .map(CompiledGlob::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ResourcePattern{ arn, segments })
+    }
+
+    pub fn matches(&self, resource: &ARN) -> bool {
+        let targets = [resource.partition(), resource.service(), resource.region(), resource.account(), resource.resource()];
+        self.segments.iter().zip(targets).all(|(segment, target)| segment.is_match(target))
+    }
+
+    pub fn matches_partition(&self, partition: &str) -> bool {
+        self.segments[0].is_match(partition)
+    }
+}
+
+impl Deref for ResourcePattern {
+    type Target = ARN;
+
+    fn deref(&self) -> &ARN {
+        &self.arn
+    }
+}
+
+impl PartialEq for ResourcePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.arn == other.arn
+    }
+}
+
+impl Eq for ResourcePattern {}
+
+impl FromStr for ResourcePattern {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        let arn = value.parse().map_err(|err| anyhow!("expected an ARN pattern, found {}: {:?}", value, err))?;
+        Self::new(arn)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ResourceConstraint {
     Any,
-    Pattern(ARN),
+    Pattern(ResourcePattern),
 }
 
 impl ResourceConstraint {
     pub fn matches(&self, resource: &ARN) -> bool {
         match self {
             Self::Any => true,
-            Self::Pattern(pattern) => glob_matches(pattern.raw(), resource.raw()),
+            Self::Pattern(pattern) => pattern.matches(resource),
         }
     }
-}
 
-impl TryFrom<&json::JsonValue> for ResourceConstraint {
-    type Error = anyhow::Error;
+    pub fn has_variables(&self) -> bool {
+        matches!(self, Self::Pattern(pattern) if has_variables(pattern.raw()))
+    }
 
-    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+    // As matches, after substituting policy variables in the pattern with
+    // the given values. Segments are split after substitution, so a variable
+    // can supply the account, and a colon in a value stays in its segment.
+    pub fn matches_with_variables(&self, resource: &ARN, values: &ResourceContext) -> Result<bool, VariableError> {
+        let pattern = match self {
+            Self::Pattern(pattern) if has_variables(pattern.raw()) => pattern,
+            _ => return Ok(self.matches(resource)),
+        };
+        let targets = ["arn", resource.partition(), resource.service(), resource.region(), resource.account(), resource.resource()];
+        let segments = split_parts(substitute(pattern.raw(), values)?, ':', targets.len());
+        Ok(segments.len() == targets.len()
+            && segments.iter().zip(targets).all(|(segment, target)| glob_parts_match(segment, target)))
+    }
+
+    // Whether some concrete resource is matched by both constraints. As with
+    // matching, each segment of the ARN patterns is compared separately.
+    pub fn overlaps(&self, other: &ResourceConstraint) -> bool {
+        match (self, other) {
+            (Self::Any, _) | (_, Self::Any) => true,
+            (Self::Pattern(lhs), Self::Pattern(rhs)) => {
+                globs_overlap(lhs.partition(), rhs.partition())
+                    && globs_overlap(lhs.service(), rhs.service())
+                    && globs_overlap(lhs.region(), rhs.region())
+                    && globs_overlap(lhs.account(), rhs.account())
+                    && globs_overlap(lhs.resource(), rhs.resource())
+            }
+        }
+    }
+
+    // Whether the value looks like an S3 bucket name, optionally followed by
+    // an object key, e.g. "my-bucket" or "my-bucket/path/*".
+    fn is_bare_s3_resource(value: &str) -> bool {
+        let bucket = value.split_once('/').map_or(value, |(bucket, _)| bucket);
+        !bucket.is_empty() && !value.contains(':')
+            && bucket.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-' | '*' | '?'))
+    }
+
+    pub fn from_json_with_options(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Self> {
         let value = value.as_str()
             .ok_or_else(|| anyhow!("expected Resource to be a string"))?;
         if value == "*" {
             return Ok(Self::Any);
         }
+        if options.allow_bare_s3_resources && !value.starts_with("arn:") && Self::is_bare_s3_resource(value) {
+            return ResourcePattern::new(ARN::new("s3", "", "", value)).map(Self::Pattern);
+        }
         value.parse().map(Self::Pattern)
-            .map_err(|_| anyhow!("expected Resource to be an ARN pattern, found {}", value))
+            .map_err(|err| anyhow!("expected Resource to be an ARN pattern: {}", err))
+    }
+}
+
+impl TryFrom<&json::JsonValue> for ResourceConstraint {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+        Self::from_json_with_options(value, &ParseOptions::default())
+    }
+}
+
+impl From<&ResourceConstraint> for json::JsonValue {
+    fn from(constraint: &ResourceConstraint) -> Self {
+        match constraint {
+            ResourceConstraint::Any => "*".into(),
+            ResourceConstraint::Pattern(arn) => arn.raw().into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ActionConstraint, PrincipalConstraint, PrincipalPattern, ResourceConstraint};
+    use crate::iam::{Action, Principal};
+    use crate::policy::options::ParseOptions;
+
+    fn aws(arn: &str) -> Principal {
+        Principal::AWS(arn.parse().unwrap())
+    }
+
+    fn principal_pattern(principal: Principal) -> PrincipalConstraint {
+        PrincipalConstraint::Pattern(PrincipalPattern::new(principal).unwrap())
+    }
+
+    #[test]
+    fn action_case_insensitive() {
+        let constraint = ActionConstraint::Pattern("s3:GetObject".parse().unwrap());
+        assert!(constraint.matches(&"s3:getobject".parse().unwrap()));
+        assert!(constraint.matches(&"S3:GETOBJECT".parse().unwrap()));
+        assert!(!constraint.matches(&"s3:getobjectacl".parse().unwrap()));
+
+        let wildcard = ActionConstraint::Pattern("S3:Get*".parse().unwrap());
+        let action: Action = "s3:getbucketpolicy".parse().unwrap();
+        assert!(wildcard.matches(&action));
+        assert!(wildcard.covers(&"s3:getObject*".parse().unwrap()));
+        assert!(wildcard.overlaps(&"s3:*object".parse().unwrap()));
+    }
+
+    #[test]
+    fn bare_s3_resources() {
+        let tolerant = ParseOptions{ allow_bare_s3_resources: true, ..ParseOptions::default() };
+        let parse = |value: &str, options: &ParseOptions| ResourceConstraint::from_json_with_options(&value.into(), options);
+
+        let bucket = parse("my-bucket", &tolerant).unwrap();
+        assert!(bucket.matches(&"arn:aws:s3:::my-bucket".parse().unwrap()));
+        assert!(!bucket.matches(&"arn:aws:s3:::my-bucket/key".parse().unwrap()));
+        let object = parse("my-bucket/key", &tolerant).unwrap();
+        assert!(object.matches(&"arn:aws:s3:::my-bucket/key".parse().unwrap()));
+        assert!(!object.matches(&"arn:aws:s3:::my-bucket".parse().unwrap()));
+        assert!(parse("my-bucket/*", &tolerant).unwrap().matches(&"arn:aws:s3:::my-bucket/path/key".parse().unwrap()));
+        assert_eq!(json::JsonValue::from(&object), "arn:aws:s3:::my-bucket/key");
+
+        // Strict parsing is the default
+        assert!(parse("my-bucket", &ParseOptions::default()).is_err());
+        assert!(parse("my-bucket/key", &ParseOptions::default()).is_err());
+        // Things that cannot be bucket names are still rejected
+        assert!(parse("My_Bucket", &tolerant).is_err());
+        assert!(parse("iam:user/Alice", &tolerant).is_err());
+    }
+
+    #[test]
+    fn resource_overlaps() {
+        let pattern = |value: &str| ResourceConstraint::Pattern(value.parse().unwrap());
+        let overlapping = [
+            ("arn:aws:s3:::bucket/*", "arn:aws:s3:::bucket/logs/*"),
+            ("arn:aws:s3:::bucket/*", "arn:aws:s3:::*/logs/today"),
+            ("arn:aws:s3:::bucket*", "arn:aws:s3:::bucket-prod/key"),
+            ("arn:aws:ec2:*:123456789012:instance/*", "arn:aws:ec2:us-east-1:*:instance/i-1234"),
+            ("arn:*:s3:::bucket/*", "arn:aws-cn:s3:::bucket/key"),
+        ];
+        for (lhs, rhs) in overlapping {
+            assert!(pattern(lhs).overlaps(&pattern(rhs)), "{} and {}", lhs, rhs);
+            assert!(pattern(rhs).overlaps(&pattern(lhs)), "{} and {}", rhs, lhs);
+        }
+
+        let disjoint = [
+            ("arn:aws:s3:::bucket/*", "arn:aws:s3:::other/*"),
+            ("arn:aws:s3:::bucket/*", "arn:aws:s3:::bucket"),
+            ("arn:aws:s3:::bucket/*", "arn:aws-cn:s3:::bucket/*"),
+            ("arn:aws:ec2:us-east-1:*:instance/*", "arn:aws:ec2:us-west-2:*:instance/*"),
+            ("arn:aws:ec2:*:123456789012:*", "arn:aws:ec2:*:210987654321:*"),
+            // A wildcard cannot match across segments
+            ("arn:aws:s3:::*", "arn:aws:sqs:us-east-1:123456789012:queue"),
+        ];
+        for (lhs, rhs) in disjoint {
+            assert!(!pattern(lhs).overlaps(&pattern(rhs)), "{} and {}", lhs, rhs);
+            assert!(!pattern(rhs).overlaps(&pattern(lhs)), "{} and {}", rhs, lhs);
+        }
+
+        assert!(ResourceConstraint::Any.overlaps(&pattern("arn:aws:s3:::bucket/key")));
+        assert!(pattern("arn:aws:s3:::bucket/key").overlaps(&ResourceConstraint::Any));
+    }
+
+    #[test]
+    fn principal_anonymous() {
+        assert!(PrincipalConstraint::Any.matches(&Principal::Anonymous));
+        assert!(!PrincipalConstraint::AWSAny.matches(&Principal::Anonymous));
+        assert!(!principal_pattern(aws("arn:aws:iam::123456789012:root")).matches(&Principal::Anonymous));
+    }
+
+    #[test]
+    fn principal_wildcard_federated() {
+        let federated = Principal::Federated("accounts.google.com".to_string());
+        // "*" matches every kind of principal, but {"AWS": "*"} only matches
+        // AWS principals
+        assert!(PrincipalConstraint::Any.matches(&federated));
+        assert!(PrincipalConstraint::Any.grants(&federated));
+        assert!(!PrincipalConstraint::AWSAny.matches(&federated));
+        assert!(!PrincipalConstraint::AWSAny.grants(&federated));
+        assert!(!PrincipalConstraint::AWSAny.matches(&Principal::Service("ecs.amazonaws.com".to_string())));
+        assert!(PrincipalConstraint::AWSAny.matches(&aws("arn:aws:iam::123456789012:role/S3Access")));
+    }
+
+    #[test]
+    fn principal_saml_provider_wildcard() {
+        let federated = |provider: &str| Principal::Federated(provider.to_string());
+        let constraint = principal_pattern(federated("arn:aws:iam::123456789012:saml-provider/*"));
+        assert!(constraint.matches(&federated("arn:aws:iam::123456789012:saml-provider/Okta")));
+        assert!(!constraint.matches(&federated("arn:aws:iam::123456789012:oidc-provider/example.com")));
+        assert!(!constraint.matches(&federated("arn:aws:iam::210987654321:saml-provider/Okta")));
+        let constraint = principal_pattern(federated("arn:aws:iam::*:saml-provider/Okta"));
+        assert!(constraint.matches(&federated("arn:aws:iam::123456789012:saml-provider/Okta")));
+        assert!(!constraint.matches(&federated("arn:aws:iam::123456789012:saml-provider/Okta2")));
+
+        let constraint = principal_pattern(federated("cognito-identity.amazonaws.com"));
+        assert!(constraint.matches(&federated("cognito-identity.amazonaws.com")));
+        assert!(!constraint.matches(&federated("accounts.google.com")));
+    }
+
+    #[test]
+    fn principal_role_wildcard() {
+        let constraint = principal_pattern(aws("arn:aws:iam::123456789012:role/*"));
+        assert!(constraint.matches(&aws("arn:aws:iam::123456789012:role/S3Access")));
+        assert!(constraint.matches(&aws("arn:aws:iam::123456789012:role/path/S3Access")));
+        assert!(!constraint.matches(&aws("arn:aws:iam::123456789012:user/Alice")));
+        assert!(!constraint.matches(&aws("arn:aws:iam::210987654321:role/S3Access")));
+        assert!(!constraint.matches(&Principal::Service("ecs.amazonaws.com".to_string())));
+    }
+
+    #[test]
+    fn principal_specific_role() {
+        let constraint = principal_pattern(aws("arn:aws:iam::123456789012:role/S3Access"));
+        assert!(constraint.matches(&aws("arn:aws:iam::123456789012:role/S3Access")));
+        assert!(!constraint.matches(&aws("arn:aws:iam::123456789012:role/S3AccessAdmin")));
+        assert!(!constraint.matches(&aws("arn:aws:iam::123456789012:root")));
+    }
+
+    #[test]
+    fn principal_account_root() {
+        let constraint = principal_pattern(aws("arn:aws:iam::123456789012:root"));
+        assert!(constraint.matches(&aws("arn:aws:iam::123456789012:root")));
+        assert!(!constraint.matches(&aws("arn:aws:iam::210987654321:root")));
+    }
+
+    #[test]
+    fn principal_account_wildcard() {
+        let wildcard = principal_pattern(aws("arn:aws:iam::123456789012:*"));
+        let root = principal_pattern(aws("arn:aws:iam::123456789012:root"));
+        for principal in ["arn:aws:iam::123456789012:user/Alice", "arn:aws:iam::123456789012:role/R", "arn:aws:iam::123456789012:root"] {
+            assert!(wildcard.matches(&aws(principal)), "{}", principal);
+            assert!(wildcard.grants(&aws(principal)), "{}", principal);
+            assert!(root.grants(&aws(principal)), "{}", principal);
+        }
+        // Assumed role sessions belong to the account, but are STS ARNs
+        let session = aws("arn:aws:sts::123456789012:assumed-role/R/session");
+        assert!(root.grants(&session));
+        assert!(!wildcard.grants(&session));
+
+        assert!(!root.matches(&aws("arn:aws:iam::123456789012:user/Alice")));
+        assert!(!root.grants(&aws("arn:aws:iam::210987654321:user/Alice")));
+        assert!(!wildcard.grants(&aws("arn:aws:iam::210987654321:user/Alice")));
     }
 }
@@ -1,12 +1,15 @@
 use crate::aws::ARN;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::str::FromStr;
 
 use anyhow::anyhow;
+use chrono::{DateTime, SecondsFormat, Utc};
 
 pub type ResourceContext = HashMap<String, Vec<String>>;
 
+#[derive(Clone)]
 pub struct Context {
     global: ResourceContext,
     resources: HashMap<ARN, ResourceContext>,
@@ -24,14 +27,135 @@ impl Context {
         &self.global
     }
 
+    // Returns the context for a resource. Resource keys may be ARN patterns,
+    // e.g., "arn:aws:s3:::bucket/*", to give a whole class of resources the
+    // same values. An exact match takes precedence over any pattern, and
+    // among matching patterns the longest, i.e., most specific, one wins.
+    // Contexts are not merged across patterns.
     pub fn resource(&self, arn: &ARN) -> Option<&ResourceContext> {
-        self.resources.get(arn)
+        if let Some(context) = self.resources.get(arn) {
+            return Some(context);
+        }
+        self.resources.iter()
+            .filter(|(pattern, _)| arn.matches_pattern(pattern))
+            .max_by(|(lhs, _), (rhs, _)| lhs.raw().len().cmp(&rhs.raw().len()).then_with(|| rhs.raw().cmp(lhs.raw())))
+            .map(|(_, context)| context)
+    }
+
+    pub fn insert_global(&mut self, key: &str, values: Vec<String>) -> Option<Vec<String>> {
+        self.global.insert(key.to_string(), values)
+    }
+
+    // Sets a key that is present but has no values, a null data set, which
+    // conditions treat differently from a missing key. See Quantifier.
+    pub fn insert_global_null(&mut self, key: &str) -> Option<Vec<String>> {
+        self.insert_global(key, Vec::new())
+    }
+
+    // Sets aws:userid as it appears for an assumed role session, which is
+    // the role's unique ID (e.g., AROAEXAMPLEID) and the session name joined
+    // by a colon. Policies usually match it with StringLike "AROAEXAMPLEID:*".
+    // See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_variables.html#principaltable
+    pub fn insert_assumed_role_userid(&mut self, role_id: &str, session_name: &str) -> Option<Vec<String>> {
+        self.insert_global("aws:userid", vec![format!("{}:{}", role_id, session_name)])
+    }
+
+    // Sets aws:CurrentTime and aws:EpochTime from the given time, so the
+    // caller controls the clock that date conditions are evaluated against.
+    pub fn set_current_time(&mut self, now: DateTime<Utc>) {
+        self.insert_global("aws:CurrentTime", vec![now.to_rfc3339_opts(SecondsFormat::Secs, true)]);
+        self.insert_global("aws:EpochTime", vec![now.timestamp().to_string()]);
+    }
+
+    // Merges another context into this one. Keys in the other context
+    // replace the same keys in this one, for globals and per resource.
+    pub fn merge(&mut self, other: Context) {
+        self.global.extend(other.global);
+        for (arn, context) in other.resources {
+            self.resources.entry(arn).or_default().extend(context);
+        }
+    }
+
+    // Sorts and deduplicates every key's values. Conditions treat values as
+    // a set, so this never changes a match result, but it avoids repeated
+    // comparisons for large multi-valued keys and gives a stable order.
+    pub fn normalize(&mut self) {
+        let values = self.global.values_mut()
+            .chain(self.resources.values_mut().flat_map(|context| context.values_mut()));
+        for values in values {
+            values.sort();
+            values.dedup();
+        }
+    }
+
+    // Returns the global and resource keys not among the used keys, in sorted
+    // order. See Policy::check_with_usage for the keys an evaluation consulted.
+    pub fn unused_keys(&self, used: &HashSet<String>) -> Vec<String> {
+        let mut keys: Vec<String> = self.global.keys()
+            .chain(self.resources.values().flat_map(|context| context.keys()))
+            .filter(|key| !used.contains(*key))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    // Builds a context from entries in the shape accepted by the IAM policy
+    // simulator, e.g., {"ContextKeyName": "aws:SourceIp", "ContextKeyValues":
+    // ["203.0.113.1"], "ContextKeyType": "ip"}. Values are validated against
+    // the declared type and populate the global context.
+    // See https://docs.aws.amazon.com/IAM/latest/APIReference/API_ContextEntry.html
+    pub fn from_simulation_entries(entries: &json::JsonValue) -> anyhow::Result<Self> {
+        if !entries.is_array() {
+            return Err(anyhow!("expected simulation context entries to be an array"));
+        }
+        let mut context = Self::new();
+        for entry in entries.members() {
+            let name = entry["ContextKeyName"].as_str()
+                .ok_or_else(|| anyhow!("expected ContextKeyName to be a string"))?;
+            let key_type = entry["ContextKeyType"].as_str()
+                .ok_or_else(|| anyhow!("expected ContextKeyType to be a string"))?;
+            let values = entry["ContextKeyValues"].members()
+                .map(|value| value.as_str().map(String::from).ok_or_else(|| anyhow!("expected ContextKeyValues to be strings")))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let (scalar_type, is_list) = match key_type.strip_suffix("List") {
+                Some(scalar_type) => (scalar_type, true),
+                None => (key_type, false),
+            };
+            if !is_list && values.len() != 1 {
+                return Err(anyhow!("expected exactly one value for {} of type {}", name, key_type));
+            }
+            for value in &values {
+                if !Self::is_valid_simulation_value(scalar_type, value)? {
+                    return Err(anyhow!("invalid {} value for {}: {}", scalar_type, name, value));
+                }
+            }
+            context.global.insert(name.to_string(), values);
+        }
+        Ok(context)
+    }
+
+    fn is_valid_simulation_value(key_type: &str, value: &str) -> anyhow::Result<bool> {
+        let valid = match key_type {
+            "string" => true,
+            "numeric" => value.parse::<f64>().is_ok_and(f64::is_finite),
+            "boolean" => value.parse::<bool>().is_ok(),
+            "ip" => value.parse::<IpAddr>().is_ok(),
+            "binary" => base64::decode(value).is_ok(),
+            "date" => DateTime::parse_from_rfc3339(value).is_ok(),
+            _ => return Err(anyhow!("unrecognized ContextKeyType: {}", key_type)),
+        };
+        Ok(valid)
     }
 
     fn try_context_from(value: &json::JsonValue) -> anyhow::Result<ResourceContext> {
         value.entries().map(|(key, value)| {
             let values = if let Some(value) = value.as_str() {
                 Ok(vec![value.to_string()])
+            } else if value.is_null() {
+                // A null data set, the same as an empty array
+                Ok(Vec::new())
             } else if value.is_array() {
                 value.members().map(|value| value.as_str().map(String::from).ok_or_else(|| anyhow!("expected array of string values")))
                     .collect::<anyhow::Result<Vec<_>>>()
@@ -85,3 +209,241 @@ impl FromStr for Context {
         Self::try_from(&value)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Context;
+    use crate::policy::{CheckResult, EvaluationOptions, Policy};
+
+    use std::collections::HashSet;
+
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn unused_global_key() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {"Bool": {"aws:SecureTransport": "true"}}
+            }
+        }"#.parse().unwrap();
+        let context: Context = r#"{
+            "global": {
+                "aws:SecureTransport": "true",
+                "aws:SourceIP": "203.0.113.1"
+            }
+        }"#.parse().unwrap();
+        assert_eq!(context.unused_keys(&HashSet::new()), vec!["aws:SecureTransport", "aws:SourceIP"]);
+        let (result, used) = policy.check_with_usage(None, &"s3:GetObject".parse().unwrap(), &"arn:aws:s3:::bucket/key".parse().unwrap(), &context, &EvaluationOptions::default()).unwrap();
+        assert_eq!(result, CheckResult::Allow);
+        assert_eq!(context.unused_keys(&used), vec!["aws:SourceIP"]);
+        // Statements whose other clauses do not match never consult their
+        // conditions.
+        let (_, used) = policy.check_with_usage(None, &"s3:PutObject".parse().unwrap(), &"arn:aws:s3:::bucket/key".parse().unwrap(), &context, &EvaluationOptions::default()).unwrap();
+        assert!(used.is_empty());
+    }
+
+    #[test]
+    fn unused_resource_key() {
+        let context: Context = r#"{
+            "resources": {
+                "arn:aws:s3:::bucket": {"aws:ResourceTag/Env": "prod"}
+            }
+        }"#.parse().unwrap();
+        assert_eq!(context.unused_keys(&HashSet::new()), vec!["aws:ResourceTag/Env"]);
+        let used = HashSet::from(["aws:ResourceTag/Env".to_string()]);
+        assert!(context.unused_keys(&used).is_empty());
+    }
+
+    #[test]
+    fn wildcard_resource() {
+        let context: Context = r#"{
+            "resources": {
+                "arn:aws:s3:::bucket/*": {"aws:ResourceTag/Env": "prod"},
+                "arn:aws:s3:::bucket/logs/*": {"aws:ResourceTag/Env": "logs"},
+                "arn:aws:s3:::bucket/special": {"aws:ResourceTag/Env": "special"}
+            }
+        }"#.parse().unwrap();
+        let env = |resource: &str| context.resource(&resource.parse().unwrap()).map(|values| values["aws:ResourceTag/Env"][0].clone());
+        assert_eq!(env("arn:aws:s3:::bucket/file"), Some("prod".to_string()));
+        // The most specific pattern wins
+        assert_eq!(env("arn:aws:s3:::bucket/logs/today"), Some("logs".to_string()));
+        // An exact key takes precedence over patterns
+        assert_eq!(env("arn:aws:s3:::bucket/special"), Some("special".to_string()));
+        assert_eq!(env("arn:aws:s3:::other/file"), None);
+        assert_eq!(env("arn:aws:s3:::bucket"), None);
+
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {"StringEquals": {"aws:ResourceTag/Env": "prod"}}
+            }
+        }"#.parse().unwrap();
+        let check = |resource: &str| policy.check_action(&"s3:GetObject".parse().unwrap(), &resource.parse().unwrap(), &context).unwrap();
+        assert_eq!(check("arn:aws:s3:::bucket/file"), CheckResult::Allow);
+        assert_eq!(check("arn:aws:s3:::bucket/logs/today"), CheckResult::Unspecified);
+    }
+
+    #[test]
+    fn null_data_set() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "AllValues", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*", "Condition": {"ForAllValues:StringEquals": {"aws:TagKeys": "Env"}}},
+                {"Sid": "AnyValue", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*", "Condition": {"ForAnyValue:StringEquals": {"aws:TagKeys": "Env"}}},
+                {"Sid": "IsNull", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*", "Condition": {"Null": {"aws:TagKeys": "true"}}}
+            ]
+        }"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let check = |context: &Context| ["AllValues", "AnyValue", "IsNull"]
+            .map(|sid| policy.check_statement(sid, None, &action, &resource, context).unwrap());
+
+        let absent = Context::new();
+        assert!(absent.globals().get("aws:TagKeys").is_none());
+        let mut empty = Context::new();
+        empty.insert_global_null("aws:TagKeys");
+        assert_eq!(empty.globals()["aws:TagKeys"], Vec::<String>::new());
+        let populated: Context = r#"{"global": {"aws:TagKeys": ["Env"]}}"#.parse().unwrap();
+
+        use CheckResult::{Allow, Unspecified};
+        assert_eq!(check(&absent), [Allow, Unspecified, Allow]);
+        assert_eq!(check(&empty), [Allow, Unspecified, Allow]);
+        assert_eq!(check(&populated), [Allow, Allow, Unspecified]);
+
+        // JSON null and an empty array both give a null data set
+        let from_json: Context = r#"{"global": {"aws:TagKeys": null, "aws:PrincipalTag/team": []}}"#.parse().unwrap();
+        assert_eq!(from_json.globals()["aws:TagKeys"], Vec::<String>::new());
+        assert_eq!(from_json.globals()["aws:PrincipalTag/team"], Vec::<String>::new());
+    }
+
+    #[test]
+    fn merge() {
+        let mut base: Context = r#"{
+            "global": {"aws:username": "bob", "aws:SecureTransport": "true"},
+            "resources": {"arn:aws:s3:::bucket": {"aws:ResourceTag/Env": "dev", "aws:ResourceTag/Owner": "bob"}}
+        }"#.parse().unwrap();
+        let request: Context = r#"{
+            "global": {"aws:username": "alice"},
+            "resources": {"arn:aws:s3:::bucket": {"aws:ResourceTag/Env": "prod"}}
+        }"#.parse().unwrap();
+        base.merge(request);
+        assert_eq!(base.globals()["aws:username"], vec!["alice"]);
+        assert_eq!(base.globals()["aws:SecureTransport"], vec!["true"]);
+        let resource = base.resource(&"arn:aws:s3:::bucket".parse().unwrap()).unwrap();
+        assert_eq!(resource["aws:ResourceTag/Env"], vec!["prod"]);
+        assert_eq!(resource["aws:ResourceTag/Owner"], vec!["bob"]);
+    }
+
+    #[test]
+    fn assumed_role_userid() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {"StringLike": {"aws:userid": "AROAEXAMPLEID:*"}}
+            }
+        }"#.parse().unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+
+        let mut context = Context::new();
+        context.insert_assumed_role_userid("AROAEXAMPLEID", "alice@example.com");
+        assert_eq!(context.globals()["aws:userid"], vec!["AROAEXAMPLEID:alice@example.com"]);
+        assert_eq!(CheckResult::Allow, policy.check_action(&action, &resource, &context).unwrap());
+
+        let mut context = Context::new();
+        context.insert_assumed_role_userid("AROAOTHERID", "alice@example.com");
+        assert_eq!(CheckResult::Unspecified, policy.check_action(&action, &resource, &context).unwrap());
+
+        // An IAM user's unique ID has no session suffix
+        let mut context = Context::new();
+        context.insert_global("aws:userid", vec!["AROAEXAMPLEID".to_string()]);
+        assert_eq!(CheckResult::Unspecified, policy.check_action(&action, &resource, &context).unwrap());
+    }
+
+    #[test]
+    fn normalize() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*", "Condition": {"ForAllValues:StringEquals": {"aws:TagKeys": ["Env", "Owner"]}}},
+                {"Effect": "Allow", "Action": "s3:PutObject", "Resource": "*", "Condition": {"ForAnyValue:StringEquals": {"aws:TagKeys": "Owner"}}},
+                {"Effect": "Allow", "Action": "s3:ListBucket", "Resource": "*", "Condition": {"StringLike": {"s3:prefix": "home/*"}}}
+            ]
+        }"#.parse().unwrap();
+        let contexts = [
+            r#"{"global": {"aws:TagKeys": ["Owner", "Env", "Owner", "Env"]}, "resources": {"arn:aws:s3:::bucket": {"s3:prefix": ["tmp/", "home/alice/", "tmp/"]}}}"#,
+            r#"{"global": {"aws:TagKeys": ["Owner", "Cost", "Owner"]}, "resources": {"arn:aws:s3:::bucket": {"s3:prefix": ["tmp/", "tmp/"]}}}"#,
+            r#"{"global": {"aws:TagKeys": ["Env", "Env"]}}"#,
+        ];
+        let requests = [("s3:GetObject", "arn:aws:s3:::bucket/key"), ("s3:PutObject", "arn:aws:s3:::bucket/key"), ("s3:ListBucket", "arn:aws:s3:::bucket")];
+        for source in contexts {
+            let context: Context = source.parse().unwrap();
+            let mut normalized = context.clone();
+            normalized.normalize();
+            for (action, resource) in requests {
+                let (action, resource) = (action.parse().unwrap(), resource.parse().unwrap());
+                assert_eq!(
+                    policy.check_action(&action, &resource, &context).unwrap(),
+                    policy.check_action(&action, &resource, &normalized).unwrap(),
+                    "{:?} on {:?} with {}", action, resource, source,
+                );
+            }
+        }
+
+        let mut context: Context = contexts[0].parse().unwrap();
+        context.normalize();
+        assert_eq!(context.globals()["aws:TagKeys"], vec!["Env", "Owner"]);
+        assert_eq!(context.resource(&"arn:aws:s3:::bucket".parse().unwrap()).unwrap()["s3:prefix"], vec!["home/alice/", "tmp/"]);
+    }
+
+    #[test]
+    fn set_current_time() {
+        let mut context = Context::new();
+        context.set_current_time(Utc.ymd(2020, 4, 1).and_hms(12, 30, 0));
+        assert_eq!(context.globals()["aws:CurrentTime"], vec!["2020-04-01T12:30:00Z"]);
+        assert_eq!(context.globals()["aws:EpochTime"], vec!["1585744200"]);
+    }
+
+    #[test]
+    fn from_simulation_entries() {
+        let entries = json::parse(r#"[
+            {"ContextKeyName": "aws:SourceIp", "ContextKeyValues": ["203.0.113.1"], "ContextKeyType": "ip"},
+            {"ContextKeyName": "aws:MultiFactorAuthPresent", "ContextKeyValues": ["true"], "ContextKeyType": "boolean"},
+            {"ContextKeyName": "aws:TagKeys", "ContextKeyValues": ["Env", "Owner"], "ContextKeyType": "stringList"},
+            {"ContextKeyName": "aws:CurrentTime", "ContextKeyValues": ["2020-04-01T00:00:00Z"], "ContextKeyType": "date"}
+        ]"#).unwrap();
+        let context = Context::from_simulation_entries(&entries).unwrap();
+        let globals = context.globals();
+        assert_eq!(globals["aws:SourceIp"], vec!["203.0.113.1"]);
+        assert_eq!(globals["aws:MultiFactorAuthPresent"], vec!["true"]);
+        assert_eq!(globals["aws:TagKeys"], vec!["Env", "Owner"]);
+        assert_eq!(globals["aws:CurrentTime"], vec!["2020-04-01T00:00:00Z"]);
+    }
+
+    #[test]
+    fn from_simulation_entries_invalid() {
+        let cases = [
+            r#"[{"ContextKeyName": "aws:SourceIp", "ContextKeyValues": ["not-an-ip"], "ContextKeyType": "ip"}]"#,
+            r#"[{"ContextKeyName": "s3:max-keys", "ContextKeyValues": ["ten"], "ContextKeyType": "numeric"}]"#,
+            r#"[{"ContextKeyName": "aws:SecureTransport", "ContextKeyValues": ["yes"], "ContextKeyType": "boolean"}]"#,
+            r#"[{"ContextKeyName": "aws:SecureTransport", "ContextKeyValues": ["true", "false"], "ContextKeyType": "boolean"}]"#,
+            r#"[{"ContextKeyName": "aws:CurrentTime", "ContextKeyValues": ["2020-04-01"], "ContextKeyType": "dateList"}]"#,
+            r#"[{"ContextKeyName": "aws:Username", "ContextKeyValues": ["alice"], "ContextKeyType": "text"}]"#,
+            r#"{"ContextKeyName": "aws:Username", "ContextKeyValues": ["alice"], "ContextKeyType": "string"}"#,
+        ];
+        for case in cases {
+            let entries = json::parse(case).unwrap();
+            assert!(Context::from_simulation_entries(&entries).is_err(), "{}", case);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use super::constraint::{ActionConstraint, ResourceConstraint};
+use super::statement::CheckResult;
+
+// The result of evaluating a single statement, identified by its position in
+// the policy's Statement list and its Sid, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementTrace {
+    pub index: usize,
+    pub sid: Option<String>,
+    pub result: CheckResult,
+}
+
+// The overall result of a request and the statements that decided it, built
+// from a trace of every statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub result: CheckResult,
+    // The first statement whose result is the overall result, or None if no
+    // statement matched.
+    pub deciding: Option<StatementTrace>,
+    // When a Deny wins, the first Allow statement that also matched, i.e.,
+    // the access that was granted but overridden.
+    pub overridden_allow: Option<StatementTrace>,
+}
+
+impl Decision {
+    pub fn from_traces(traces: &[StatementTrace]) -> Self {
+        let first = |result: CheckResult| traces.iter().find(|trace| trace.result == result).cloned();
+        let result = traces.iter().fold(CheckResult::Unspecified, |result, trace| result.combine(trace.result));
+        let (deciding, overridden_allow) = match result {
+            CheckResult::Deny => (first(CheckResult::Deny), first(CheckResult::Allow)),
+            CheckResult::Allow => (first(CheckResult::Allow), None),
+            CheckResult::Unspecified => (None, None),
+        };
+        Decision{ result, deciding, overridden_allow }
+    }
+
+    // The Sids of the Allow and Deny statements when a Deny overrode an
+    // Allow. Either Sid is None if that statement has no Sid.
+    pub fn override_sids(&self) -> Option<(Option<&str>, Option<&str>)> {
+        let allow = self.overridden_allow.as_ref()?;
+        let deny = self.deciding.as_ref()?;
+        Some((allow.sid.as_deref(), deny.sid.as_deref()))
+    }
+}
+
+// The result of a request along with every statement that matched it. Each
+// matched statement's result is its Effect, so an Allow that was overridden
+// still appears as Allow. The deciding statement is chosen as in Decision, so
+// it is None for an implicit deny.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckExplanation {
+    pub result: CheckResult,
+    pub deciding: Option<StatementTrace>,
+    pub matched: Vec<StatementTrace>,
+}
+
+impl CheckExplanation {
+    pub fn from_matched(matched: Vec<StatementTrace>) -> Self {
+        let Decision{ result, deciding, .. } = Decision::from_traces(&matched);
+        CheckExplanation{ result, deciding, matched }
+    }
+
+    // The index and Sid of the deciding statement, if any.
+    pub fn decided_by(&self) -> Option<(usize, Option<&str>)> {
+        self.deciding.as_ref().map(|trace| (trace.index, trace.sid.as_deref()))
+    }
+}
+
+// Why a statement did or did not match a request. The clauses are checked in
+// the order Principal, Action, Resource, Condition, and only the first one
+// that fails to match is reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchOutcome<'a> {
+    // The Action and Resource patterns that matched the request. These are
+    // None for a NotAction or NotResource clause, which matches because no
+    // pattern does.
+    Matched {
+        action: Option<&'a ActionConstraint>,
+        resource: Option<&'a ResourceConstraint>,
+    },
+    PrincipalMismatch,
+    ActionMismatch,
+    // The request's resource is in a partition, e.g., aws-cn or aws-us-gov,
+    // that none of the statement's Resource patterns allow.
+    PartitionMismatch,
+    ResourceMismatch,
+    // A policy variable in the Resource clause names a key the context does
+    // not have, and gives no default, so the statement cannot match.
+    MissingVariable(String),
+    ConditionsNotMet,
+}
+
+impl MatchOutcome<'_> {
+    pub fn is_match(&self) -> bool {
+        matches!(self, Self::Matched{ .. })
+    }
+}
@@ -0,0 +1,44 @@
+// The kinds of policy document, which IAM and other services limit to
+// different sizes.
+// See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_iam-quotas.html#reference_iam-quotas-entity-length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyKind {
+    Managed,
+    InlineUser,
+    InlineGroup,
+    InlineRole,
+    // A role's trust policy, at its default quota
+    RoleTrust,
+    ServiceControl,
+    S3Bucket,
+}
+
+impl PolicyKind {
+    // The maximum number of characters, not counting whitespace.
+    pub fn size_limit(&self) -> usize {
+        match self {
+            Self::Managed => 6144,
+            Self::InlineUser => 2048,
+            Self::InlineGroup => 5120,
+            Self::InlineRole => 10240,
+            Self::RoleTrust => 2048,
+            Self::ServiceControl => 5120,
+            Self::S3Bucket => 20480,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicyTooLarge {
+    pub kind: PolicyKind,
+    pub size: usize,
+    pub limit: usize,
+}
+
+impl std::fmt::Display for PolicyTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} policy is {} characters, exceeding the limit of {}", self.kind, self.size, self.limit)
+    }
+}
+
+impl std::error::Error for PolicyTooLarge {}
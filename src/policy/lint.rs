@@ -0,0 +1,491 @@
+use super::condition::global::PRINCIPAL_TYPES;
+use super::condition::operator::Operator;
+use super::condition::quantifier::Quantifier;
+use super::condition::set::ConditionSet;
+use super::constraint::ResourceConstraint;
+use super::statement::{Effect, ResourceClause, Statement};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    // aws:SourceIp is absent for requests made through a VPC endpoint, so an
+    // IpAddress condition on it never matches alongside aws:SourceVpce.
+    // See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_condition-keys.html#condition-keys-sourceip
+    SourceIpWithSourceVpce,
+    // ForAllValues matches when the key is missing or has no values, so using
+    // it with Allow can grant access to requests that omit the key.
+    // See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_condition-single-vs-multi-valued-context-keys.html
+    AllowWithForAllValues,
+    // NotResource "*" excludes every resource, so the statement never
+    // matches. This is almost certainly meant to be Resource "*".
+    NotResourceAny,
+    // A condition on a key that always matches when another condition on the
+    // same key matches, e.g. StringLike "v*" alongside StringEquals "v".
+    RedundantCondition {
+        key: String,
+        redundant: Quantifier,
+        implied_by: Quantifier,
+    },
+    // Two conditions on the same key that can never both match, e.g.
+    // StringEquals "a" alongside ForAllValues:StringEquals "b".
+    ContradictoryConditions {
+        key: String,
+        first: Quantifier,
+        second: Quantifier,
+    },
+    // An Effect other than exactly "Allow" or "Deny", e.g. "allow", which
+    // only parses with ParseOptions::case_insensitive_effect.
+    EffectCase(String),
+    // An aws:PrincipalType value that AWS never sets, e.g. "Users" instead
+    // of "User", so an equality condition on it never matches.
+    UnknownPrincipalType(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub statement: usize,
+    pub sid: Option<String>,
+    pub kind: WarningKind,
+}
+
+impl std::fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SourceIpWithSourceVpce => f.write_str("aws:SourceIp is not available for requests through a VPC endpoint, use aws:VpcSourceIp instead"),
+            Self::AllowWithForAllValues => f.write_str("ForAllValues with Allow also matches requests where the key is missing or empty"),
+            Self::NotResourceAny => f.write_str("NotResource \"*\" excludes every resource, so the statement never matches"),
+            Self::RedundantCondition{key, redundant, implied_by} => write!(f, "{} on {} is implied by {} on the same key", redundant, key, implied_by),
+            Self::ContradictoryConditions{key, first, second} => write!(f, "{} and {} on {} can never both match", first, second, key),
+            Self::EffectCase(effect) => write!(f, "Effect \"{}\" must be written as Allow or Deny", effect),
+            Self::UnknownPrincipalType(value) => write!(f, "\"{}\" is not a valid aws:PrincipalType, expected one of {}", value, PRINCIPAL_TYPES.join(", ")),
+        }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.sid {
+            Some(sid) => write!(f, "statement {} ({}): {}", self.statement, sid, self.kind),
+            None => write!(f, "statement {}: {}", self.statement, self.kind),
+        }
+    }
+}
+
+// Condition keys are case-insensitive.
+fn has_key(conditions: &ConditionSet, key: &str) -> bool {
+    conditions.iter().any(|(_, values)| values.keys().any(|k| k.eq_ignore_ascii_case(key)))
+}
+
+fn has_ip_condition(conditions: &ConditionSet, key: &str) -> bool {
+    conditions.iter().any(|(quant, values)| {
+        let is_ip_op = matches!(quant.operator(), Some(Operator::IpAddress | Operator::NotIpAddress));
+        is_ip_op && values.keys().any(|k| k.eq_ignore_ascii_case(key))
+    })
+}
+
+fn lint_source_ip(conditions: &ConditionSet) -> Option<WarningKind> {
+    if has_ip_condition(conditions, "aws:SourceIp") && has_key(conditions, "aws:SourceVpce") {
+        Some(WarningKind::SourceIpWithSourceVpce)
+    } else {
+        None
+    }
+}
+
+// Only conditions written with the ForAllValues: prefix are flagged, not
+// ...IfExists, which also matches a missing key but says so explicitly.
+fn lint_for_all_values(effect: Effect, conditions: &ConditionSet) -> Option<WarningKind> {
+    let uses_for_all = conditions.iter().any(|(quant, _)| matches!(quant, Quantifier::ForAllValues(_) | Quantifier::ForAllValuesIfExists(_)));
+    if effect == Effect::Allow && uses_for_all {
+        Some(WarningKind::AllowWithForAllValues)
+    } else {
+        None
+    }
+}
+
+fn lint_not_resource(resources: &ResourceClause) -> Option<WarningKind> {
+    match resources {
+        ResourceClause::NotResource(resources) if resources.iter().any(|constraint| matches!(constraint, ResourceConstraint::Any)) => Some(WarningKind::NotResourceAny),
+        _ => None,
+    }
+}
+
+// Only equality operators are checked, since a StringLike pattern need not
+// spell out a principal type.
+fn lint_principal_type(conditions: &ConditionSet) -> Vec<WarningKind> {
+    let mut values: Vec<&String> = conditions.iter()
+        .filter_map(|(quant, values)| {
+            let ignore_case = match quant.operator()? {
+                Operator::StringEquals | Operator::StringNotEquals => false,
+                Operator::StringEqualsIgnoreCase | Operator::StringNotEqualsIgnoreCase => true,
+                _ => return None,
+            };
+            let targets = values.iter().find(|(key, _)| key.eq_ignore_ascii_case("aws:PrincipalType"))?.1;
+            Some(targets.iter().filter(move |target| !PRINCIPAL_TYPES.iter().any(|known| {
+                if ignore_case { known.eq_ignore_ascii_case(target) } else { known == target }
+            })))
+        })
+        .flatten()
+        .collect();
+    values.sort();
+    values.dedup();
+    values.into_iter().map(|value| WarningKind::UnknownPrincipalType(value.clone())).collect()
+}
+
+type ConditionEntry<'a> = (&'a Quantifier, &'a String, &'a Vec<String>);
+
+fn string_values(value: &str) -> Vec<String> {
+    vec![value.to_string()]
+}
+
+// Both conditions use the same set qualifier, and every value matching the
+// StringEquals targets also matches a StringLike pattern.
+fn is_redundant(implied_by: ConditionEntry, redundant: ConditionEntry) -> bool {
+    let same_quantifier = std::mem::discriminant(implied_by.0) == std::mem::discriminant(redundant.0)
+        && implied_by.0.operator() == Some(&Operator::StringEquals)
+        && redundant.0.operator() == Some(&Operator::StringLike);
+    same_quantifier && implied_by.2.iter().all(|target| {
+        redundant.0.matches(Some(&string_values(target)), redundant.2).unwrap_or(false)
+    })
+}
+
+fn is_contradictory(first: ConditionEntry, second: ConditionEntry) -> bool {
+    match (first.0, second.0) {
+        // The key must have a value equal to one of the first targets, and
+        // all of its values must satisfy the second condition.
+        (Quantifier::Plain(Operator::StringEquals) | Quantifier::ForAnyValue(Operator::StringEquals), Quantifier::ForAllValues(_) | Quantifier::ForAllValuesIfExists(_)) => first.2.iter().all(|target| {
+            !second.0.matches(Some(&string_values(target)), second.2).unwrap_or(true)
+        }),
+        // The key must be missing, but a plain operator needs a value unless
+        // it is negated.
+        (Quantifier::Null, Quantifier::Plain(op) | Quantifier::ForAnyValue(op)) if !op.is_negated() => first.2.iter().all(|target| target == "true"),
+        _ => false,
+    }
+}
+
+fn lint_condition_pairs(conditions: &ConditionSet) -> Vec<WarningKind> {
+    let mut entries: Vec<ConditionEntry> = conditions.iter()
+        .flat_map(|(quant, values)| values.iter().map(move |(key, targets)| (quant, key, targets)))
+        .collect();
+    // Sort for a stable warning order, since conditions are stored unordered
+    entries.sort_by_key(|(quant, key, _)| (key.to_ascii_lowercase(), quant.to_string()));
+    let mut kinds = Vec::new();
+    for first in &entries {
+        for second in &entries {
+            if std::ptr::eq(first, second) || !first.1.eq_ignore_ascii_case(second.1) {
+                continue;
+            }
+            if is_redundant(*first, *second) {
+                kinds.push(WarningKind::RedundantCondition{
+                    key: first.1.clone(),
+                    redundant: second.0.clone(),
+                    implied_by: first.0.clone(),
+                });
+            }
+            if is_contradictory(*first, *second) {
+                kinds.push(WarningKind::ContradictoryConditions{
+                    key: first.1.clone(),
+                    first: first.0.clone(),
+                    second: second.0.clone(),
+                });
+            }
+        }
+    }
+    kinds
+}
+
+pub fn lint_statement(index: usize, stmt: &Statement) -> Vec<Warning> {
+    let mut kinds = Vec::new();
+    kinds.extend(lint_not_resource(&stmt.resources));
+    if let Some(conditions) = &stmt.conditions {
+        kinds.extend(lint_source_ip(conditions));
+        kinds.extend(lint_for_all_values(stmt.effect, conditions));
+        kinds.extend(lint_condition_pairs(conditions));
+        kinds.extend(lint_principal_type(conditions));
+    }
+    kinds.into_iter().map(|kind| Warning{
+        statement: index,
+        sid: stmt.sid.clone(),
+        kind,
+    }).collect()
+}
+
+// Checks for problems that are lost when parsing, so it works on the policy
+// document itself rather than a parsed Policy.
+pub fn lint_source(value: &json::JsonValue) -> Vec<Warning> {
+    let statements: Vec<&json::JsonValue> = match &value["Statement"] {
+        statement if statement.is_object() => vec![statement],
+        statements => statements.members().collect(),
+    };
+    statements.into_iter().enumerate().filter_map(|(index, stmt)| {
+        let effect = stmt["Effect"].as_str()?;
+        let is_canonical = effect == "Allow" || effect == "Deny";
+        let is_effect = effect.eq_ignore_ascii_case("Allow") || effect.eq_ignore_ascii_case("Deny");
+        (is_effect && !is_canonical).then(|| Warning{
+            statement: index,
+            sid: stmt["Sid"].as_str().map(|sid| sid.to_string()),
+            kind: WarningKind::EffectCase(effect.to_string()),
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lint_source, WarningKind};
+    use crate::policy::{CheckResult, ParseOptions, Policy};
+    use crate::policy::context::Context;
+    use crate::policy::condition::operator::Operator;
+    use crate::policy::condition::quantifier::Quantifier;
+
+    #[test]
+    fn source_ip_with_source_vpce() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Sid": "VpceAndIp",
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {
+                    "IpAddress": {"aws:SourceIp": "203.0.113.0/24"},
+                    "StringEquals": {"aws:SourceVpce": "vpce-1a2b3c4d"}
+                }
+            }
+        }"#.parse().unwrap();
+        let warnings = policy.lint();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].statement, 0);
+        assert_eq!(warnings[0].sid.as_deref(), Some("VpceAndIp"));
+        assert_eq!(warnings[0].kind, WarningKind::SourceIpWithSourceVpce);
+    }
+
+    #[test]
+    fn source_ip_without_source_vpce() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {
+                    "IpAddress": {"aws:SourceIp": "203.0.113.0/24"}
+                }
+            }
+        }"#.parse().unwrap();
+        assert!(policy.lint().is_empty());
+    }
+
+    #[test]
+    fn vpc_source_ip_with_source_vpce() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {
+                    "IpAddress": {"aws:VpcSourceIp": "10.0.0.0/16"},
+                    "StringEquals": {"aws:SourceVpce": "vpce-1a2b3c4d"}
+                }
+            }
+        }"#.parse().unwrap();
+        assert!(policy.lint().is_empty());
+    }
+
+    #[test]
+    fn allow_with_for_all_values() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "ec2:CreateTags",
+                    "Resource": "*",
+                    "Condition": {"ForAllValues:StringEquals": {"aws:TagKeys": ["Env", "Owner"]}}
+                },
+                {
+                    "Effect": "Deny",
+                    "Action": "ec2:CreateTags",
+                    "Resource": "*",
+                    "Condition": {"ForAllValues:StringNotEquals": {"aws:TagKeys": ["Env", "Owner"]}}
+                },
+                {
+                    "Effect": "Allow",
+                    "Action": "ec2:CreateTags",
+                    "Resource": "*",
+                    "Condition": {"ForAnyValue:StringEquals": {"aws:TagKeys": ["Env", "Owner"]}}
+                },
+                {
+                    "Effect": "Allow",
+                    "Action": "ec2:RunInstances",
+                    "Resource": "*",
+                    "Condition": {"StringEqualsIfExists": {"ec2:InstanceType": "t2.micro"}}
+                },
+                {
+                    "Effect": "Allow",
+                    "Action": "ec2:CreateTags",
+                    "Resource": "*",
+                    "Condition": {"ForAllValues:StringEqualsIfExists": {"aws:TagKeys": ["Env", "Owner"]}}
+                }
+            ]
+        }"#.parse().unwrap();
+        let warnings = policy.lint();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].statement, 0);
+        assert_eq!(warnings[0].kind, WarningKind::AllowWithForAllValues);
+        // Statement 3 uses IfExists without the ForAllValues: prefix, so it is
+        // not flagged
+        assert_eq!(warnings[1].statement, 4);
+        assert_eq!(warnings[1].kind, WarningKind::AllowWithForAllValues);
+    }
+
+    #[test]
+    fn redundant_condition() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {
+                    "StringEquals": {"aws:username": "alice"},
+                    "StringLike": {"aws:username": "al*"}
+                }
+            }
+        }"#.parse().unwrap();
+        let warnings = policy.lint();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::RedundantCondition{
+            key: "aws:username".to_string(),
+            redundant: Quantifier::Plain(Operator::StringLike),
+            implied_by: Quantifier::Plain(Operator::StringEquals),
+        });
+        assert_eq!(warnings[0].to_string(), "statement 0: StringLike on aws:username is implied by StringEquals on the same key");
+    }
+
+    #[test]
+    fn contradictory_conditions() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:GetObject",
+                    "Resource": "*",
+                    "Condition": {
+                        "StringEquals": {"aws:PrincipalTag/team": "a"},
+                        "ForAllValues:StringEquals": {"aws:PrincipalTag/team": "b"}
+                    }
+                },
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:GetObject",
+                    "Resource": "*",
+                    "Condition": {
+                        "StringEquals": {"aws:PrincipalTag/team": ["a", "b"]},
+                        "ForAllValues:StringEquals": {"aws:PrincipalTag/team": "b"}
+                    }
+                }
+            ]
+        }"#.parse().unwrap();
+        let warnings = policy.lint();
+        let contradictions: Vec<_> = warnings.iter()
+            .filter(|warning| matches!(warning.kind, WarningKind::ContradictoryConditions{..}))
+            .collect();
+        assert_eq!(contradictions.len(), 1);
+        assert_eq!(contradictions[0].statement, 0);
+        assert_eq!(contradictions[0].kind, WarningKind::ContradictoryConditions{
+            key: "aws:PrincipalTag/team".to_string(),
+            first: Quantifier::Plain(Operator::StringEquals),
+            second: Quantifier::ForAllValues(Operator::StringEquals),
+        });
+    }
+
+    #[test]
+    fn null_contradicts_plain_operator() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Deny",
+                "Action": "s3:GetObject",
+                "Resource": "*",
+                "Condition": {
+                    "Null": {"aws:SourceVpc": "true"},
+                    "StringEquals": {"aws:SourceVpc": "vpc-111bbb22"}
+                }
+            }
+        }"#.parse().unwrap();
+        let warnings = policy.lint();
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(warnings[0].kind, WarningKind::ContradictoryConditions{..}));
+    }
+
+    #[test]
+    fn not_resource_any() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": "s3:GetObject", "NotResource": ["arn:aws:s3:::secret/*", "*"]},
+                {"Effect": "Allow", "Action": "s3:GetObject", "NotResource": "arn:aws:s3:::secret/*"}
+            ]
+        }"#.parse().unwrap();
+        let warnings = policy.lint();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].statement, 0);
+        assert_eq!(warnings[0].kind, WarningKind::NotResourceAny);
+
+        let action = "s3:GetObject".parse().unwrap();
+        let context = Context::new();
+        for resource in ["arn:aws:s3:::bucket/key", "arn:aws:s3:::secret/key", "arn:aws:iam::123456789012:user/Alice"] {
+            let resource = resource.parse().unwrap();
+            assert_eq!(policy.statements[0].check_action(&action, &resource, &context).unwrap(), CheckResult::Unspecified);
+        }
+    }
+
+    #[test]
+    fn principal_type() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Deny", "Action": "*", "Resource": "*", "Condition": {"StringEquals": {"aws:PrincipalType": ["User", "AssumedRole"]}}},
+                {"Sid": "Typo", "Effect": "Deny", "Action": "*", "Resource": "*", "Condition": {"StringNotEquals": {"aws:PrincipalType": ["Account", "Users"]}}},
+                {"Effect": "Deny", "Action": "*", "Resource": "*", "Condition": {"StringEqualsIgnoreCase": {"aws:principaltype": "federateduser"}}},
+                {"Effect": "Deny", "Action": "*", "Resource": "*", "Condition": {"StringEquals": {"aws:PrincipalType": "anonymous"}}},
+                {"Effect": "Deny", "Action": "*", "Resource": "*", "Condition": {"StringLike": {"aws:PrincipalType": "*User"}}}
+            ]
+        }"#.parse().unwrap();
+        let warnings = policy.lint();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].statement, 1);
+        assert_eq!(warnings[0].sid.as_deref(), Some("Typo"));
+        assert_eq!(warnings[0].kind, WarningKind::UnknownPrincipalType("Users".to_string()));
+        // StringEquals is case-sensitive
+        assert_eq!(warnings[1].statement, 3);
+        assert_eq!(warnings[1].kind, WarningKind::UnknownPrincipalType("anonymous".to_string()));
+    }
+
+    #[test]
+    fn effect_case() {
+        let source = json::parse(r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Sid": "Loud", "Effect": "ALLOW", "Action": "s3:GetObject", "Resource": "*"},
+                {"Effect": "Deny", "Action": "s3:PutObject", "Resource": "*"}
+            ]
+        }"#).unwrap();
+        assert!(Policy::try_from(&source).is_err());
+        let policy = Policy::from_json_with_options(&source, &ParseOptions::lenient()).unwrap();
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(policy.check_action(&action, &resource, &Context::new()).unwrap(), CheckResult::Allow);
+
+        let warnings = lint_source(&source);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].statement, 0);
+        assert_eq!(warnings[0].sid.as_deref(), Some("Loud"));
+        assert_eq!(warnings[0].kind, WarningKind::EffectCase("ALLOW".to_string()));
+
+        // Anything other than Allow or Deny is rejected regardless
+        let source = json::parse(r#"{"Statement": {"Effect": "Permit", "Action": "s3:GetObject", "Resource": "*"}}"#).unwrap();
+        assert!(Policy::from_json_with_options(&source, &ParseOptions::lenient()).is_err());
+        assert!(lint_source(&source).is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+// Controls how strictly policy documents are parsed. The default is strict,
+// matching what IAM itself accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    // Parse unrecognized condition operators as Operator::Unknown, which
+    // never matches, instead of rejecting the policy. This allows loading
+    // policies that use operators newer than this crate.
+    pub allow_unknown_operators: bool,
+    // Accept any casing of Allow and Deny in Effect, e.g. "allow", which IAM
+    // rejects. lint::lint_source reports such statements.
+    pub case_insensitive_effect: bool,
+    // Accept a bare S3 bucket or object, e.g. "my-bucket/key", as a Resource
+    // and match it as arn:aws:s3:::my-bucket/key.
+    pub allow_bare_s3_resources: bool,
+}
+
+impl ParseOptions {
+    pub fn lenient() -> Self {
+        ParseOptions{
+            allow_unknown_operators: true,
+            case_insensitive_effect: true,
+            allow_bare_s3_resources: true,
+        }
+    }
+}
+
+// What to do when a condition cannot be evaluated, e.g., a DateLessThan
+// condition whose target is not a date.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnConditionError {
+    // Abort the evaluation with the error
+    #[default]
+    Fail,
+    // Treat the statement as not matching, as AWS does
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvaluationOptions {
+    pub on_condition_error: OnConditionError,
+    // The most statements to evaluate before failing with
+    // EvaluationError::BudgetExceeded, or None for no limit. Statements that
+    // cannot change the result are skipped and do not count.
+    pub budget: Option<usize>,
+}
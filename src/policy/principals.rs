@@ -0,0 +1,100 @@
+use crate::iam::Principal;
+use super::statement::{Effect, PrincipalClause, Statement};
+
+/*
+Summarizes who a resource policy grants access to, as the principals some
+Allow statement names minus those a Deny statement refuses outright. Each side
+is a union of Principal and NotPrincipal clauses, so a NotPrincipal statement,
+which applies to everyone except the principals it lists, is kept as is rather
+than expanded.
+
+Like effective_actions, this is an over-approximation of what is allowed: an
+Allow counts even if it only covers some actions or has conditions, but a Deny
+only counts if it covers every action and resource unconditionally.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectivePrincipals {
+    pub allowed: Vec<PrincipalClause>,
+    pub denied: Vec<PrincipalClause>,
+}
+
+impl EffectivePrincipals {
+    // Statements without a Principal or NotPrincipal belong to an identity
+    // policy and are ignored, as are statements that can never match.
+    pub fn from_statements<'a>(statements: impl IntoIterator<Item = &'a Statement>) -> Self {
+        let mut result = EffectivePrincipals{ allowed: Vec::new(), denied: Vec::new() };
+        for stmt in statements {
+            if stmt.principals == PrincipalClause::None || stmt.is_dead() {
+                continue;
+            }
+            match stmt.effect {
+                Effect::Allow => result.allowed.push(stmt.principals.clone()),
+                Effect::Deny if stmt.covers_everything() => result.denied.push(stmt.principals.clone()),
+                Effect::Deny => {}
+            }
+        }
+        result
+    }
+
+    // Whether some Allow statement applies to the principal and no
+    // unconditional Deny refuses it.
+    pub fn grants(&self, principal: &Principal) -> bool {
+        self.allowed.iter().any(|clause| clause.matches(principal))
+            && !self.denied.iter().any(|clause| clause.matches(principal))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::iam::Principal;
+    use crate::policy::Policy;
+    use crate::policy::statement::PrincipalClause;
+
+    #[test]
+    fn effective_principals() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::111122223333:root"}, "Action": "s3:*", "Resource": "*"},
+                {"Effect": "Allow", "NotPrincipal": {"AWS": "arn:aws:iam::444455556666:user/Mallory"}, "Action": "s3:GetObject", "Resource": "arn:aws:s3:::public/*"},
+                {"Effect": "Deny", "NotPrincipal": {"AWS": ["arn:aws:iam::111122223333:user/Alice", "arn:aws:iam::444455556666:root"]}, "Action": "*", "Resource": "*"},
+                {"Effect": "Deny", "Principal": {"AWS": "arn:aws:iam::444455556666:user/Carol"}, "Action": "s3:DeleteObject", "Resource": "*"},
+                {"Effect": "Deny", "Principal": "*", "Action": "*", "Resource": "*", "Condition": {"Bool": {"aws:SecureTransport": "false"}}},
+                {"Effect": "Allow", "Principal": {"AWS": []}, "Action": "*", "Resource": "*"},
+                {"Effect": "Allow", "Action": "*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let effective = policy.effective_principals();
+        assert_eq!(effective.allowed.len(), 2);
+        assert!(matches!(effective.allowed[1], PrincipalClause::NotPrincipal(_)));
+        assert_eq!(effective.denied.len(), 1);
+
+        let grants = |principal: &str| effective.grants(&principal.parse::<Principal>().unwrap());
+        assert!(grants("arn:aws:iam::111122223333:user/Alice"));
+        // Granted by the account, but not exempt from the NotPrincipal deny
+        assert!(!grants("arn:aws:iam::111122223333:user/Bob"));
+        // Only the root itself is exempt from the deny
+        assert!(grants("arn:aws:iam::444455556666:root"));
+        assert!(!grants("arn:aws:iam::444455556666:user/Carol"));
+        // Neither allowed by the account nor exempt from the deny
+        assert!(!grants("arn:aws:iam::777788889999:user/Dave"));
+    }
+
+    #[test]
+    fn not_principal_allow() {
+        let policy: Policy = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "NotPrincipal": {"AWS": "arn:aws:iam::444455556666:user/Mallory"}, "Action": "s3:GetObject", "Resource": "*"},
+                {"Effect": "Deny", "Principal": {"AWS": "arn:aws:iam::444455556666:user/Carol"}, "Action": "*", "Resource": "*"}
+            ]
+        }"#.parse().unwrap();
+        let effective = policy.effective_principals();
+        let grants = |principal: &str| effective.grants(&principal.parse::<Principal>().unwrap());
+        assert!(grants("arn:aws:iam::111122223333:user/Alice"));
+        assert!(grants("arn:aws:iam::444455556666:user/Bob"));
+        assert!(!grants("arn:aws:iam::444455556666:user/Mallory"));
+        assert!(!grants("arn:aws:iam::444455556666:user/Carol"));
+    }
+}
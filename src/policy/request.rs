@@ -0,0 +1,43 @@
+/*
+Parsing of a request given as strings, shared by Policy::check_strings and the
+command line, so that both accept the same forms.
+ */
+
+use crate::aws::ARN;
+use crate::iam::{Action, Principal};
+
+use anyhow::anyhow;
+
+// The principal's kind is inferred from its form, see Principal::from_str, so
+// a bare account ID stands for the account's root user.
+pub fn parse_principal(value: &str) -> anyhow::Result<Principal> {
+    value.parse().map_err(|_| anyhow!("invalid principal: {}", value))
+}
+
+pub fn parse_action(value: &str) -> anyhow::Result<Action> {
+    value.parse().map_err(|_| anyhow!("invalid action: {}", value))
+}
+
+// Degenerate ARNs are rejected, see ARN::is_well_formed.
+pub fn parse_resource(value: &str) -> anyhow::Result<ARN> {
+    value.parse::<ARN>().ok()
+        .filter(ARN::is_well_formed)
+        .ok_or_else(|| anyhow!("invalid resource: {}", value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_action, parse_principal, parse_resource};
+    use crate::iam::Principal;
+
+    #[test]
+    fn parse_request_parts() {
+        assert_eq!(parse_principal("123456789012").unwrap(), Principal::AWS("arn:aws:iam::123456789012:root".parse().unwrap()));
+        assert!(parse_principal("").is_err());
+        assert!(parse_action("s3:GetObject").is_ok());
+        assert!(parse_action("GetObject").is_err());
+        assert!(parse_resource("arn:aws:s3:::bucket/key").is_ok());
+        assert!(parse_resource("arn:aws:s3:::").is_err());
+        assert!(parse_resource("bucket/key").is_err());
+    }
+}
@@ -1,8 +1,15 @@
 use crate::aws::ARN;
-use crate::iam::{Action, Principal};
-use super::condition::ConditionList;
-use super::constraint::{ActionConstraint, PrincipalConstraint, ResourceConstraint};
-use super::context::Context;
+use crate::iam::{account_root, Action, Principal, ServiceTargetCatalog};
+use super::condition::set::ConditionSet;
+use super::condition::operator::Operator;
+use super::condition::quantifier::Quantifier;
+use super::constraint::{ActionConstraint, PrincipalConstraint, PrincipalPattern, ResourceConstraint};
+use super::context::{Context, ResourceContext};
+use super::explain::MatchOutcome;
+use super::options::ParseOptions;
+use super::variables::VariableError;
+
+use std::collections::HashSet;
 
 use anyhow::anyhow;
 
@@ -19,93 +26,495 @@ pub enum CheckResult {
     Unspecified,
 }
 
-#[derive(Debug, Clone)]
+impl Effect {
+    pub fn as_check_result(&self) -> CheckResult {
+        match self {
+            Self::Allow => CheckResult::Allow,
+            Self::Deny => CheckResult::Deny,
+        }
+    }
+}
+
+impl Effect {
+    pub fn inverted(&self) -> Self {
+        match self {
+            Self::Allow => Self::Deny,
+            Self::Deny => Self::Allow,
+        }
+    }
+}
+
+impl CheckResult {
+    // An explicit deny overrides an allow, which overrides no decision.
+    pub fn priority(&self) -> u8 {
+        match self {
+            Self::Deny => 2,
+            Self::Allow => 1,
+            Self::Unspecified => 0,
+        }
+    }
+
+    // A statement matched the request and denied it.
+    pub fn is_explicit_deny(&self) -> bool {
+        *self == Self::Deny
+    }
+
+    // No statement allowed the request, which AWS also treats as a deny.
+    pub fn is_implicit_deny(&self) -> bool {
+        *self == Self::Unspecified
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Allow => "allowed",
+            Self::Deny => "explicitly denied by a statement",
+            Self::Unspecified => "implicitly denied because no statement allows it",
+        }
+    }
+
+    // Combines two results, keeping whichever takes precedence.
+    pub fn combine(self, other: Self) -> Self {
+        if other.priority() > self.priority() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrincipalError {
+    // An IAM group ARN in a Principal or NotPrincipal clause, which AWS
+    // rejects
+    GroupPrincipal(String),
+}
+
+impl std::fmt::Display for PrincipalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::GroupPrincipal(arn) => write!(f, "IAM groups cannot be principals: {}", arn),
+        }
+    }
+}
+
+impl std::error::Error for PrincipalError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PrincipalClause {
     None,
     Principal(Vec<PrincipalConstraint>),
     NotPrincipal(Vec<PrincipalConstraint>),
 }
 
-#[derive(Debug, Clone)]
+impl PrincipalClause {
+    // Whether the clause applies to the principal. An absent clause applies
+    // to every principal.
+    pub fn matches(&self, principal: &Principal) -> bool {
+        match self {
+            Self::None => true,
+            Self::Principal(principals) => principals.iter().any(|constraint| constraint.grants(principal)),
+            Self::NotPrincipal(principals) => !principals.iter().any(|constraint| constraint.matches(principal)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ActionClause {
     Action(Vec<ActionConstraint>),
     NotAction(Vec<ActionConstraint>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ResourceClause {
     Resource(Vec<ResourceConstraint>),
     NotResource(Vec<ResourceConstraint>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Statement {
     pub sid: Option<String>,
     pub effect: Effect,
     pub principals: PrincipalClause,
     pub actions: ActionClause,
     pub resources: ResourceClause,
-    pub conditions: Option<ConditionList>,
+    pub conditions: Option<ConditionSet>,
 }
 
 impl Statement {
-    fn matches_conditions(&self, resource: &ARN, context: &Context) -> anyhow::Result<bool> {
+    // Returns whether the context satisfies every condition of the statement,
+    // using the resource's context values alongside the global ones. A
+    // statement without conditions is always satisfied.
+    pub fn conditions_satisfied(&self, resource: &ARN, context: &Context) -> anyhow::Result<bool> {
+        self.conditions_satisfied_with(resource, context, true, None)
+    }
+
+    // As conditions_satisfied, also adding the condition keys it consults to
+    // used, if given.
+    fn conditions_satisfied_with(&self, resource: &ARN, context: &Context, variables: bool, used: Option<&mut HashSet<String>>) -> anyhow::Result<bool> {
         let conditions = match &self.conditions {
             Some(conditions) => conditions,
             None => return Ok(true),
         };
+        let key_values = Self::key_values(resource, context);
+        if let Some(used) = used {
+            used.extend(conditions.keys().cloned());
+        }
+        let matches = if variables {
+            conditions.matches_with_variables(&key_values)?
+        } else {
+            conditions.matches(&key_values)?
+        };
+        Ok(matches)
+    }
+
+    // The values of the request's context keys: the global values, replaced
+    // by the resource's own values where it has them.
+    fn key_values(resource: &ARN, context: &Context) -> ResourceContext {
         let mut key_values = context.globals().clone();
         if let Some(rsrc_values) = context.resource(resource) {
-            key_values.extend(rsrc_values.clone().into_iter());
+            key_values.extend(rsrc_values.clone());
         }
-        let matches = conditions.matches(&key_values)?;
-        Ok(matches)
+        key_values
     }
 
-    pub fn check_action(&self, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
-        let matches_action = match &self.actions {
-            ActionClause::Action(actions) => actions.iter().any(|constraint| constraint.matches(action)),
-            ActionClause::NotAction(actions) => !actions.iter().any(|constraint| constraint.matches(action)),
+    pub fn matches_action(&self, action: &Action) -> bool {
+        self.matching_action(action).is_some()
+    }
+
+    fn matches_resource(&self, resource: &ARN) -> bool {
+        self.matching_resource(resource).is_some()
+    }
+
+    // Returns None if the Action clause does not match, otherwise the first
+    // constraint that matched. A matching NotAction clause yields Some(None).
+    fn matching_action(&self, action: &Action) -> Option<Option<&ActionConstraint>> {
+        match &self.actions {
+            ActionClause::Action(actions) => actions.iter().find(|constraint| constraint.matches(action)).map(Some),
+            ActionClause::NotAction(actions) => (!actions.iter().any(|constraint| constraint.matches(action))).then_some(None),
+        }
+    }
+
+    // As matching_action, for the Resource clause.
+    fn matching_resource(&self, resource: &ARN) -> Option<Option<&ResourceConstraint>> {
+        match &self.resources {
+            ResourceClause::Resource(resources) => resources.iter().find(|constraint| constraint.matches(resource)).map(Some),
+            ResourceClause::NotResource(resources) => (!resources.iter().any(|constraint| constraint.matches(resource))).then_some(None),
+        }
+    }
+
+    // As matching_resource, after substituting policy variables in the
+    // patterns. A variable whose key is missing fails the whole clause, even
+    // if another pattern matches.
+    fn matching_resource_with_variables(&self, resource: &ARN, values: &ResourceContext) -> Result<Option<Option<&ResourceConstraint>>, VariableError> {
+        let (ResourceClause::Resource(constraints) | ResourceClause::NotResource(constraints)) = &self.resources;
+        let results = constraints.iter()
+            .map(|constraint| constraint.matches_with_variables(resource, values).map(|matched| (constraint, matched)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut matched = results.into_iter().filter(|(_, matched)| *matched).map(|(constraint, _)| constraint);
+        Ok(match &self.resources {
+            ResourceClause::Resource(_) => matched.next().map(Some),
+            ResourceClause::NotResource(_) => match matched.next() {
+                Some(_) => None,
+                None => Some(None),
+            },
+        })
+    }
+
+    // Matches the Resource clause, substituting policy variables if
+    // variables is true, or returns the outcome explaining why it did not
+    // match.
+    fn match_resource(&self, resource: &ARN, context: &Context, variables: bool) -> anyhow::Result<Result<Option<&ResourceConstraint>, MatchOutcome<'_>>> {
+        let (ResourceClause::Resource(constraints) | ResourceClause::NotResource(constraints)) = &self.resources;
+        let matched = if variables && constraints.iter().any(ResourceConstraint::has_variables) {
+            match self.matching_resource_with_variables(resource, &Self::key_values(resource, context)) {
+                Ok(matched) => matched,
+                Err(VariableError::Missing(key)) => return Ok(Err(MatchOutcome::MissingVariable(key))),
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            self.matching_resource(resource)
         };
-        if !matches_action {
-            return Ok(CheckResult::Unspecified);
+        Ok(match matched {
+            Some(constraint) => Ok(constraint),
+            None if self.is_partition_mismatch(resource) => Err(MatchOutcome::PartitionMismatch),
+            None => Err(MatchOutcome::ResourceMismatch),
+        })
+    }
+
+    // Best-effort detection of statements that can never match any request,
+    // such as an empty Action list, a NotAction or NotResource of "*", or
+    // conditions that can never be satisfied. See
+    // ConditionSet::is_satisfiable.
+    pub fn is_dead(&self) -> bool {
+        let dead_principals = match &self.principals {
+            PrincipalClause::None => false,
+            PrincipalClause::Principal(principals) => principals.is_empty(),
+            PrincipalClause::NotPrincipal(principals) => principals.iter().any(|constraint| matches!(constraint, PrincipalConstraint::Any)),
+        };
+        let dead_actions = match &self.actions {
+            ActionClause::Action(actions) => actions.is_empty(),
+            ActionClause::NotAction(actions) => actions.iter().any(|constraint| matches!(constraint, ActionConstraint::Any)),
+        };
+        let dead_resources = match &self.resources {
+            ResourceClause::Resource(resources) => resources.is_empty(),
+            ResourceClause::NotResource(resources) => resources.iter().any(|constraint| matches!(constraint, ResourceConstraint::Any)),
+        };
+        let dead_conditions = self.conditions.as_ref().is_some_and(|conditions| !conditions.is_satisfiable());
+        dead_principals || dead_actions || dead_resources || dead_conditions
+    }
+
+    // Whether none of the statement's actions can apply to any of its
+    // resources because their services differ, e.g., s3:GetObject on an EC2
+    // instance. Patterns with a wildcard in the service are assumed to match,
+    // as are services the catalog lists as targets of the action's service,
+    // such as sts:AssumeRole on an IAM role.
+    pub fn has_service_mismatch(&self, targets: &ServiceTargetCatalog) -> bool {
+        let has_wildcard = |service: &str| service.contains(['*', '?']);
+        let (actions, resources) = match (&self.actions, &self.resources) {
+            (ActionClause::Action(actions), ResourceClause::Resource(resources)) => (actions, resources),
+            _ => return false,
+        };
+        if actions.is_empty() || resources.is_empty() {
+            return false;
         }
+        actions.iter().all(|action| resources.iter().all(|resource| match (action, resource) {
+            (ActionConstraint::Pattern(action), ResourceConstraint::Pattern(resource)) => {
+                let (action, resource) = (action.service(), resource.service());
+                !has_wildcard(action) && !has_wildcard(resource) && !targets.allows(action, resource)
+            },
+            _ => false,
+        }))
+    }
+
+    // Whether the statement applies regardless of the resource and context,
+    // i.e., it has no conditions and its Resource clause includes "*".
+    pub fn is_unconditional(&self) -> bool {
+        let any_resource = match &self.resources {
+            ResourceClause::Resource(resources) => resources.iter().any(|constraint| matches!(constraint, ResourceConstraint::Any)),
+            ResourceClause::NotResource(_) => false,
+        };
+        any_resource && self.conditions.is_none()
+    }
+
+    // Whether the statement denies every action on every resource for every
+    // principal, regardless of context.
+    pub fn is_blanket_deny(&self) -> bool {
+        let any_principal = match &self.principals {
+            PrincipalClause::None => true,
+            PrincipalClause::Principal(principals) => principals.iter().any(|constraint| matches!(constraint, PrincipalConstraint::Any)),
+            PrincipalClause::NotPrincipal(_) => false,
+        };
+        self.effect == Effect::Deny && any_principal && self.covers_everything()
+    }
 
-        let matches_resource = match &self.resources {
-            ResourceClause::Resource(resources) => resources.iter().any(|constraint| constraint.matches(resource)),
-            ResourceClause::NotResource(resources) => !resources.iter().any(|constraint| constraint.matches(resource)),
+    // Whether the statement applies to every action on every resource,
+    // regardless of context, for the principals it names.
+    pub fn covers_everything(&self) -> bool {
+        let any_action = match &self.actions {
+            ActionClause::Action(actions) => actions.iter().any(|constraint| matches!(constraint, ActionConstraint::Any)),
+            ActionClause::NotAction(_) => false,
         };
-        if !matches_resource {
-            return Ok(CheckResult::Unspecified);
+        any_action && self.is_unconditional()
+    }
+
+    // Whether the statement applies to at least one action matched by the
+    // action pattern. For NotAction, this is conservative: it only reports a
+    // match if no excluded pattern covers the entire action pattern.
+    pub fn overlaps_actions(&self, actions: &Action) -> bool {
+        match &self.actions {
+            ActionClause::Action(constraints) => constraints.iter().any(|constraint| constraint.overlaps(actions)),
+            ActionClause::NotAction(constraints) => !constraints.iter().any(|constraint| constraint.covers(actions)),
         }
+    }
 
-        if !self.matches_conditions(resource, context)? {
-            return Ok(CheckResult::Unspecified);
+    // Whether the statement applies to every action matched by the action
+    // pattern.
+    pub fn covers_actions(&self, actions: &Action) -> bool {
+        match &self.actions {
+            ActionClause::Action(constraints) => constraints.iter().any(|constraint| constraint.covers(actions)),
+            ActionClause::NotAction(constraints) => !constraints.iter().any(|constraint| constraint.overlaps(actions)),
         }
+    }
 
-        Ok(match self.effect {
-            Effect::Allow => CheckResult::Allow,
-            Effect::Deny => CheckResult::Deny,
-        })
+    // Whether the two statements differ only in their Action lists, so they
+    // can be combined into one statement allowing or denying both lists.
+    // NotAction clauses cannot be combined, since a request must avoid every
+    // excluded action in a single statement but only one statement's
+    // exclusions in a pair.
+    pub fn can_merge_with(&self, other: &Statement) -> bool {
+        matches!((&self.actions, &other.actions), (ActionClause::Action(_), ActionClause::Action(_)))
+            && self.effect == other.effect
+            && self.principals == other.principals
+            && self.resources == other.resources
+            && self.conditions == other.conditions
     }
 
-    pub fn check(&self, principal: &Principal, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
-        let matches_principals = match &self.principals {
-            PrincipalClause::None => true,
-            PrincipalClause::Principal(principals) => principals.iter().any(|constraint| constraint.matches(principal)),
-            PrincipalClause::NotPrincipal(principals) => !principals.iter().any(|constraint| constraint.matches(principal)),
+    // Combines the statements, per can_merge_with, keeping this statement's
+    // Sid and the union of both Action lists in order. Returns None if they
+    // cannot be merged.
+    pub fn merge_with(&self, other: &Statement) -> Option<Statement> {
+        if !self.can_merge_with(other) {
+            return None;
+        }
+        let (mut actions, additional) = match (&self.actions, &other.actions) {
+            (ActionClause::Action(lhs), ActionClause::Action(rhs)) => (lhs.clone(), rhs),
+            _ => return None,
+        };
+        for constraint in additional {
+            if !actions.contains(constraint) {
+                actions.push(constraint.clone());
+            }
+        }
+        Some(Statement{ actions: ActionClause::Action(actions), ..self.clone() })
+    }
+
+    // Returns whether the statement's resource and condition clauses match,
+    // ignoring its action and principal clauses.
+    pub fn applies_to(&self, resource: &ARN, context: &Context) -> anyhow::Result<bool> {
+        if self.match_resource(resource, context, true)?.is_err() {
+            return Ok(false);
+        }
+        self.conditions_satisfied(resource, context)
+    }
+
+    // Whether the statement's action and resource clauses match, ignoring its
+    // conditions and principals. When this is true but check_action does not
+    // return the statement's effect, the conditions alone are the blocker.
+    pub fn would_match_without_conditions(&self, action: &Action, resource: &ARN) -> bool {
+        self.matches_action(action) && self.matches_resource(resource)
+    }
+
+    // Returns a context whose global values satisfy the statement's
+    // conditions, for testing it against a request. Some operators, such as
+    // negated and range operators, yield a representative value rather than
+    // the only one. Returns None if no satisfying context could be derived.
+    // See ConditionList::sample_values.
+    pub fn minimal_context(&self) -> Option<Context> {
+        let mut context = Context::new();
+        if let Some(conditions) = &self.conditions {
+            for (key, values) in conditions.sample_values()? {
+                context.insert_global(&key, values);
+            }
+        }
+        Some(context)
+    }
+
+    fn matches_principal(&self, principal: &Principal) -> bool {
+        self.principals.matches(principal)
+    }
+
+    // Whether the resource failed to match only because it is in a partition
+    // none of the Resource patterns allow.
+    fn is_partition_mismatch(&self, resource: &ARN) -> bool {
+        match &self.resources {
+            ResourceClause::Resource(resources) => !resources.is_empty() && resources.iter().all(|constraint| match constraint {
+                ResourceConstraint::Any => false,
+                ResourceConstraint::Pattern(pattern) => !pattern.matches_partition(resource.partition()),
+            }),
+            ResourceClause::NotResource(_) => false,
+        }
+    }
+
+    // Explains whether the statement matches a request, regardless of its
+    // effect. Without a principal, the Principal and NotPrincipal clauses are
+    // ignored, as in check_action.
+    pub fn explain(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<MatchOutcome<'_>> {
+        self.explain_with(principal, action, resource, context, true, None)
+    }
+
+    // As explain, but policy variables are only substituted if variables is
+    // true, since policies before Version 2012-10-17 treat them as text. The
+    // condition keys consulted are added to used, if given.
+    fn explain_with(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, variables: bool, used: Option<&mut HashSet<String>>) -> anyhow::Result<MatchOutcome<'_>> {
+        if let Some(principal) = principal {
+            if !self.matches_principal(principal) {
+                return Ok(MatchOutcome::PrincipalMismatch);
+            }
+        }
+
+        let action = match self.matching_action(action) {
+            Some(constraint) => constraint,
+            None => return Ok(MatchOutcome::ActionMismatch),
         };
-        if matches_principals {
-            self.check_action(action, resource, context)
+
+        let matched_resource = match self.match_resource(resource, context, variables)? {
+            Ok(constraint) => constraint,
+            Err(outcome) => return Ok(outcome),
+        };
+
+        if !self.conditions_satisfied_with(resource, context, variables, used)? {
+            return Ok(MatchOutcome::ConditionsNotMet);
+        }
+
+        Ok(MatchOutcome::Matched{ action, resource: matched_resource })
+    }
+
+    // Evaluates the statement as check does, or as check_action without a
+    // principal. See explain_with for variables.
+    pub(crate) fn evaluate(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, variables: bool) -> anyhow::Result<CheckResult> {
+        self.evaluate_with(principal, action, resource, context, variables, None)
+    }
+
+    // As evaluate, also adding the condition keys consulted to used, if
+    // given. See explain_with.
+    pub(crate) fn evaluate_with(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, variables: bool, used: Option<&mut HashSet<String>>) -> anyhow::Result<CheckResult> {
+        if self.explain_with(principal, action, resource, context, variables, used)?.is_match() {
+            Ok(self.effect.as_check_result())
         } else {
             Ok(CheckResult::Unspecified)
         }
     }
 
-    fn parse_effect(value: &json::JsonValue) -> anyhow::Result<Effect> {
+    // Whether every clause matches the request, regardless of the Effect.
+    // Without a principal, the Principal clause is ignored as in check_action.
+    pub fn matches_request(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<bool> {
+        self.matches_with(principal, action, resource, context, true)
+    }
+
+    pub(crate) fn matches_with(&self, principal: Option<&Principal>, action: &Action, resource: &ARN, context: &Context, variables: bool) -> anyhow::Result<bool> {
+        Ok(self.explain_with(principal, action, resource, context, variables, None)?.is_match())
+    }
+
+    // Describes the statement in one line of plain English, e.g. "Allow
+    // s3:GetObject on arn:aws:s3:::bucket/* when aws:SecureTransport is
+    // true." Conditions are listed in order of key.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{:?} {}", self.effect, describe_actions(&self.actions));
+        summary.push_str(" on ");
+        summary.push_str(&describe_resources(&self.resources));
+        if let Some(principals) = describe_principals(&self.principals) {
+            summary.push_str(" for ");
+            summary.push_str(&principals);
+        }
+        if let Some(conditions) = &self.conditions {
+            let mut clauses: Vec<(&String, String, String)> = conditions.iter()
+                .flat_map(|(quant, values)| values.iter().map(move |(key, targets)| (key, quant.to_string(), describe_condition(quant, key, targets))))
+                .collect();
+            clauses.sort();
+            if !clauses.is_empty() {
+                let clauses: Vec<String> = clauses.into_iter().map(|(_, _, clause)| clause).collect();
+                summary.push_str(" when ");
+                summary.push_str(&clauses.join(" and "));
+            }
+        }
+        summary.push('.');
+        summary
+    }
+
+    pub fn check_action(&self, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
+        self.evaluate(None, action, resource, context, true)
+    }
+
+    pub fn check(&self, principal: &Principal, action: &Action, resource: &ARN, context: &Context) -> anyhow::Result<CheckResult> {
+        self.evaluate(Some(principal), action, resource, context, true)
+    }
+
+    fn parse_effect(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Effect> {
         match value.as_str() {
             Some("Allow") => Ok(Effect::Allow),
             Some("Deny") => Ok(Effect::Deny),
+            Some(s) if options.case_insensitive_effect && s.eq_ignore_ascii_case("Allow") => Ok(Effect::Allow),
+            Some(s) if options.case_insensitive_effect && s.eq_ignore_ascii_case("Deny") => Ok(Effect::Deny),
             Some(_) => Err(anyhow!("expected Effect to be Allow or Deny")),
             _ => Err(anyhow!("expected Effect to be a string")),
         }
@@ -124,19 +533,21 @@ impl Statement {
         if value == "*" {
             return Ok(PrincipalConstraint::AWSAny);
         }
-        let re = regex::Regex::new("^\\d+$").map_err(|_| anyhow!("unable to compile regular expression"))?;
-        let account = if re.is_match(value) {
-            let mut arn = String::from("arn:aws:iam::");
-            arn.push_str(value);
-            arn.push_str(":root");
-            Some(arn)
-        } else {
-            None
+        let arn = match account_root(value) {
+            Some(arn) => arn,
+            None => value.parse().map_err(|_| anyhow!("expected AWS principal to be an ARN or '*'"))?,
         };
-        account.as_deref().unwrap_or(value).parse()
-            .map_err(|_| anyhow!("expected AWS principal to be an ARN or '*'"))
-            .map(Principal::AWS)
-            .map(PrincipalConstraint::Pattern)
+        // Groups are not principals, since a request is never made by one
+        if arn.service() == "iam" && arn.resource_type() == Some("group") {
+            return Err(PrincipalError::GroupPrincipal(arn.to_string()).into());
+        }
+        Self::principal_pattern(Principal::AWS(arn))
+    }
+
+    fn principal_pattern(principal: Principal) -> anyhow::Result<PrincipalConstraint> {
+        let value = principal.to_string();
+        PrincipalPattern::new(principal).map(PrincipalConstraint::Pattern)
+            .map_err(|err| anyhow!("expected principal to be a valid pattern, found {}: {}", value, err))
     }
 
     fn parse_aws_principals(value: &json::JsonValue) -> anyhow::Result<Vec<PrincipalConstraint>> {
@@ -149,7 +560,7 @@ impl Statement {
 
     fn parse_federated_principal(value: &json::JsonValue) -> anyhow::Result<PrincipalConstraint> {
         let value = value.as_str().ok_or_else(|| anyhow!("expected Federated principal to be a string"))?;
-        Ok(PrincipalConstraint::Pattern(Principal::Federated(value.to_string())))
+        Self::principal_pattern(Principal::Federated(value.to_string()))
     }
 
     fn parse_federated_principals(value: &json::JsonValue) -> anyhow::Result<Vec<PrincipalConstraint>> {
@@ -162,7 +573,7 @@ impl Statement {
 
     fn parse_service_principal(value: &json::JsonValue) -> anyhow::Result<PrincipalConstraint> {
         let value = value.as_str().ok_or_else(|| anyhow!("expected Federated principal to be a string"))?;
-        Ok(PrincipalConstraint::Pattern(Principal::Service(value.to_string())))
+        Self::principal_pattern(Principal::Service(value.to_string()))
     }
 
     fn parse_service_principals(value: &json::JsonValue) -> anyhow::Result<Vec<PrincipalConstraint>> {
@@ -175,7 +586,7 @@ impl Statement {
 
     fn parse_canonicaluser_principal(value: &json::JsonValue) -> anyhow::Result<PrincipalConstraint> {
         let value = value.as_str().ok_or_else(|| anyhow!("expected Federated principal to be a string"))?;
-        Ok(PrincipalConstraint::Pattern(Principal::CanonicalUser(value.to_string())))
+        Self::principal_pattern(Principal::CanonicalUser(value.to_string()))
     }
 
     fn parse_canonicaluser_principals(value: &json::JsonValue) -> anyhow::Result<Vec<PrincipalConstraint>> {
@@ -206,41 +617,33 @@ impl Statement {
                 "CanonicalUser" => Self::parse_canonicaluser_principals(value),
                 _ => Err(anyhow!("expected Principal to be *, AWS, Federated, Service, or CanonicalUser")),
             }
-        }).fold(Ok(Vec::new()), |acc, value| {
-            if let Ok(other) = value {
-                acc.map(|mut constraints| {
-                    constraints.extend_from_slice(&other);
-                    constraints
-                })
-            } else {
-                value
-            }
+        }).try_fold(Vec::new(), |mut constraints, value| {
+            constraints.extend_from_slice(&value?);
+            Ok(constraints)
         })
     }
 
-    fn parse_resources(value: &json::JsonValue) -> anyhow::Result<Vec<ResourceConstraint>> {
+    fn parse_resources(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Vec<ResourceConstraint>> {
         if value.is_string() {
-            ResourceConstraint::try_from(value).map(|resource| vec![resource])
+            ResourceConstraint::from_json_with_options(value, options).map(|resource| vec![resource])
         } else {
-            value.members().map(ResourceConstraint::try_from).collect::<anyhow::Result<Vec<_>>>()
+            value.members().map(|value| ResourceConstraint::from_json_with_options(value, options)).collect::<anyhow::Result<Vec<_>>>()
         }
     }
 
-    fn parse_conditions(value: &json::JsonValue) -> anyhow::Result<Option<ConditionList>> {
+    fn parse_conditions(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Option<ConditionSet>> {
         if value.is_null() {
             Ok(None)
         } else if value.is_object() {
-            ConditionList::try_from(value).map(Some)
+            ConditionSet::from_json_with_options(value, options).map(Some)
         } else {
             Err(anyhow!("expected Condition to be an object"))
         }
     }
 }
 
-impl TryFrom<&json::JsonValue> for Statement {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+impl Statement {
+    pub fn from_json_with_options(value: &json::JsonValue, options: &ParseOptions) -> anyhow::Result<Self> {
         let sid = &value["Sid"];
         let sid = if let Some(s) = sid.as_str() {
             Some(s.to_string())
@@ -249,7 +652,7 @@ impl TryFrom<&json::JsonValue> for Statement {
         } else {
             return Err(anyhow!("expected Sid to be a string"));
         };
-        let effect = Self::parse_effect(&value["Effect"])?;
+        let effect = Self::parse_effect(&value["Effect"], options)?;
         // According to https://docs.aws.amazon.com/IAM/latest/UserGuide/access-analyzer-reference-policy-checks.html#access-analyzer-reference-policy-checks-error-unsupported-element-combination
         // Principal/NotPrincipal, Action/NotAction, and Resource/NotResource
         // are mutually exclusive, and it is an error to include both.
@@ -258,7 +661,7 @@ impl TryFrom<&json::JsonValue> for Statement {
         let actions = match(action.is_null(), not_action.is_null()) {
             (true, true) => return Err(anyhow!("missing Action or NotAction")),
             (false, true) => ActionClause::Action(Self::parse_actions(action)?),
-            (true, false) => ActionClause::NotAction(Self::parse_actions(action)?),
+            (true, false) => ActionClause::NotAction(Self::parse_actions(not_action)?),
             (false, false) => return Err(anyhow!("cannot have both Action and NotAction in same statement")),
         };
         let principal = &value["Principal"];
@@ -273,11 +676,11 @@ impl TryFrom<&json::JsonValue> for Statement {
         let not_resource = &value["NotResource"];
         let resources = match(resource.is_null(), not_resource.is_null()) {
             (true, true) => return Err(anyhow!("missing Resource or NotResource")),
-            (false, true) => ResourceClause::Resource(Self::parse_resources(resource)?),
-            (true, false) => ResourceClause::NotResource(Self::parse_resources(not_resource)?),
+            (false, true) => ResourceClause::Resource(Self::parse_resources(resource, options)?),
+            (true, false) => ResourceClause::NotResource(Self::parse_resources(not_resource, options)?),
             (false, false) => return Err(anyhow!("cannot have both Resource and NotResource in same statement")),
         };
-        let conditions = Self::parse_conditions(&value["Condition"])?;
+        let conditions = Self::parse_conditions(&value["Condition"], options)?;
         Ok(Statement{
             sid,
             effect,
@@ -288,3 +691,539 @@ impl TryFrom<&json::JsonValue> for Statement {
         })
     }
 }
+
+impl TryFrom<&json::JsonValue> for Statement {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &json::JsonValue) -> anyhow::Result<Self> {
+        Self::from_json_with_options(value, &ParseOptions::default())
+    }
+}
+
+// Joins a clause's patterns, or returns None if one of them is "*".
+fn join_patterns<T>(constraints: &[T], describe: impl Fn(&T) -> Option<String>) -> Option<String> {
+    let patterns: Option<Vec<String>> = constraints.iter().map(describe).collect();
+    patterns.map(|patterns| patterns.join(", "))
+}
+
+fn describe_actions(actions: &ActionClause) -> String {
+    let describe = |constraint: &ActionConstraint| match constraint {
+        ActionConstraint::Any => None,
+        ActionConstraint::Pattern(action) => Some(action.to_string()),
+    };
+    match actions {
+        ActionClause::Action(actions) => join_patterns(actions, describe).unwrap_or_else(|| "any action".to_string()),
+        ActionClause::NotAction(actions) => match join_patterns(actions, describe) {
+            Some(excluded) => format!("any action except {}", excluded),
+            None => "no action".to_string(),
+        },
+    }
+}
+
+fn describe_resources(resources: &ResourceClause) -> String {
+    let describe = |constraint: &ResourceConstraint| match constraint {
+        ResourceConstraint::Any => None,
+        ResourceConstraint::Pattern(arn) => Some(arn.to_string()),
+    };
+    match resources {
+        ResourceClause::Resource(resources) => join_patterns(resources, describe).unwrap_or_else(|| "any resource".to_string()),
+        ResourceClause::NotResource(resources) => match join_patterns(resources, describe) {
+            Some(excluded) => format!("any resource except {}", excluded),
+            None => "no resource".to_string(),
+        },
+    }
+}
+
+fn describe_principals(principals: &PrincipalClause) -> Option<String> {
+    let describe = |constraint: &PrincipalConstraint| match constraint {
+        PrincipalConstraint::Any => None,
+        PrincipalConstraint::AWSAny => Some("any AWS principal".to_string()),
+        PrincipalConstraint::Pattern(pattern) => Some(match &**pattern {
+            Principal::AWS(arn) => arn.to_string(),
+            Principal::Federated(s) | Principal::Service(s) | Principal::CanonicalUser(s) => s.clone(),
+            Principal::Anonymous => "anonymous users".to_string(),
+        }),
+    };
+    match principals {
+        PrincipalClause::None => None,
+        PrincipalClause::Principal(principals) => Some(join_patterns(principals, describe).unwrap_or_else(|| "anyone".to_string())),
+        PrincipalClause::NotPrincipal(principals) => Some(match join_patterns(principals, describe) {
+            Some(excluded) => format!("anyone except {}", excluded),
+            None => "no one".to_string(),
+        }),
+    }
+}
+
+fn describe_operator(op: &Operator) -> String {
+    use Operator as Op;
+    let phrase = match op {
+        Op::StringEquals | Op::NumericEquals | Op::DateEquals | Op::Bool | Op::BinaryEquals | Op::ArnEquals => "is",
+        Op::StringEqualsIgnoreCase => "is, ignoring case,",
+        Op::StringNotEquals | Op::NumericNotEquals | Op::DateNotEquals | Op::ArnNotEquals => "is not",
+        Op::StringNotEqualsIgnoreCase => "is not, ignoring case,",
+        Op::StringLike | Op::ArnLike => "matches",
+        Op::StringNotLike | Op::ArnNotLike => "does not match",
+        Op::NumericLessThan => "is less than",
+        Op::NumericLessThanEquals => "is at most",
+        Op::NumericGreaterThan => "is greater than",
+        Op::NumericGreaterThanEquals => "is at least",
+        Op::DateLessThan => "is before",
+        Op::DateLessThanEquals => "is at or before",
+        Op::DateGreaterThan => "is after",
+        Op::DateGreaterThanEquals => "is at or after",
+        Op::IpAddress => "is in",
+        Op::NotIpAddress => "is not in",
+        Op::Unknown(name) => return format!("satisfies {}", name),
+    };
+    phrase.to_string()
+}
+
+fn describe_condition(quant: &Quantifier, key: &str, targets: &[String]) -> String {
+    // Negated operators need the value to differ from every target
+    let connective = match quant {
+        _ if quant.operator().is_some_and(Operator::is_negated) => " nor ",
+        _ => " or ",
+    };
+    let targets = targets.join(connective);
+    match quant {
+        Quantifier::Plain(op) | Quantifier::ForAnyValue(op) => format!("{} {} {}", key, describe_operator(op), targets),
+        Quantifier::IfExists(op) => format!("{} {} {} if present", key, describe_operator(op), targets),
+        Quantifier::ForAllValues(op) | Quantifier::ForAllValuesIfExists(op) => format!("every value of {} {} {}", key, describe_operator(op), targets),
+        Quantifier::Null if targets == "true" => format!("{} is absent", key),
+        Quantifier::Null if targets == "false" => format!("{} is present", key),
+        Quantifier::Null => format!("{} is Null {}", key, targets),
+    }
+}
+
+// Lists with a single element are written as a plain value, which is how
+// policies are usually written by hand.
+fn compact(mut values: Vec<json::JsonValue>) -> json::JsonValue {
+    if values.len() == 1 {
+        values.remove(0)
+    } else {
+        json::JsonValue::Array(values)
+    }
+}
+
+fn principals_to_json(principals: &[PrincipalConstraint]) -> json::JsonValue {
+    // A Pattern(Anonymous) constraint has no JSON form of its own, and only
+    // "*" matches anonymous requests.
+    let any = principals.iter().any(|constraint| match constraint {
+        PrincipalConstraint::Any => true,
+        PrincipalConstraint::Pattern(pattern) => matches!(**pattern, Principal::Anonymous),
+        PrincipalConstraint::AWSAny => false,
+    });
+    if any {
+        return "*".into();
+    }
+    let mut result = json::JsonValue::new_object();
+    for kind in ["AWS", "Federated", "Service", "CanonicalUser"] {
+        let values: Vec<json::JsonValue> = principals.iter().filter_map(|constraint| match (kind, constraint) {
+            ("AWS", PrincipalConstraint::AWSAny) => Some("*".into()),
+            (_, PrincipalConstraint::Pattern(pattern)) => match (kind, &**pattern) {
+                ("AWS", Principal::AWS(arn)) => Some(arn.raw().into()),
+                ("Federated", Principal::Federated(id)) => Some(id.as_str().into()),
+                ("Service", Principal::Service(id)) => Some(id.as_str().into()),
+                ("CanonicalUser", Principal::CanonicalUser(id)) => Some(id.as_str().into()),
+                _ => None,
+            },
+            _ => None,
+        }).collect();
+        if !values.is_empty() {
+            result[kind] = compact(values);
+        }
+    }
+    result
+}
+
+impl From<&Statement> for json::JsonValue {
+    fn from(statement: &Statement) -> Self {
+        let mut result = json::JsonValue::new_object();
+        if let Some(sid) = &statement.sid {
+            result["Sid"] = sid.as_str().into();
+        }
+        result["Effect"] = match statement.effect {
+            Effect::Allow => "Allow",
+            Effect::Deny => "Deny",
+        }.into();
+        match &statement.principals {
+            PrincipalClause::None => {},
+            PrincipalClause::Principal(principals) => result["Principal"] = principals_to_json(principals),
+            PrincipalClause::NotPrincipal(principals) => result["NotPrincipal"] = principals_to_json(principals),
+        }
+        match &statement.actions {
+            ActionClause::Action(actions) => result["Action"] = compact(actions.iter().map(json::JsonValue::from).collect()),
+            ActionClause::NotAction(actions) => result["NotAction"] = compact(actions.iter().map(json::JsonValue::from).collect()),
+        }
+        match &statement.resources {
+            ResourceClause::Resource(resources) => result["Resource"] = compact(resources.iter().map(json::JsonValue::from).collect()),
+            ResourceClause::NotResource(resources) => result["NotResource"] = compact(resources.iter().map(json::JsonValue::from).collect()),
+        }
+        if let Some(conditions) = &statement.conditions {
+            result["Condition"] = conditions.into();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ActionClause, CheckResult, Effect, PrincipalError, ResourceClause, Statement};
+    use crate::iam::Principal;
+    use crate::policy::context::Context;
+    use crate::policy::explain::MatchOutcome;
+
+    #[test]
+    fn effect_as_check_result() {
+        assert_eq!(Effect::Allow.as_check_result(), CheckResult::Allow);
+        assert_eq!(Effect::Deny.as_check_result(), CheckResult::Deny);
+    }
+
+    #[test]
+    fn check_result_priority() {
+        assert!(CheckResult::Deny.priority() > CheckResult::Allow.priority());
+        assert!(CheckResult::Allow.priority() > CheckResult::Unspecified.priority());
+    }
+
+    #[test]
+    fn check_result_explicit_deny() {
+        assert!(CheckResult::Deny.is_explicit_deny());
+        assert!(!CheckResult::Deny.is_implicit_deny());
+        assert!(CheckResult::Unspecified.is_implicit_deny());
+        assert!(!CheckResult::Unspecified.is_explicit_deny());
+        assert!(!CheckResult::Allow.is_explicit_deny());
+        assert!(!CheckResult::Allow.is_implicit_deny());
+        assert_ne!(CheckResult::Deny.description(), CheckResult::Unspecified.description());
+    }
+
+    #[test]
+    fn check_result_combine() {
+        use CheckResult::{Allow, Deny, Unspecified};
+        let cases = [
+            (Unspecified, Unspecified, Unspecified),
+            (Unspecified, Allow, Allow),
+            (Unspecified, Deny, Deny),
+            (Allow, Unspecified, Allow),
+            (Allow, Allow, Allow),
+            (Allow, Deny, Deny),
+            (Deny, Unspecified, Deny),
+            (Deny, Allow, Deny),
+            (Deny, Deny, Deny),
+        ];
+        for (lhs, rhs, expected) in cases {
+            assert_eq!(expected, lhs.combine(rhs));
+        }
+    }
+
+    fn parse(value: &str) -> Statement {
+        Statement::try_from(&json::parse(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_not_action() {
+        let stmt = parse(r#"{"Effect": "Deny", "NotAction": "sns:*", "Resource": "*"}"#);
+        match &stmt.actions {
+            ActionClause::NotAction(actions) => assert_eq!(actions.len(), 1),
+            ActionClause::Action(_) => panic!("expected NotAction"),
+        }
+        assert!(!stmt.matches_action(&"sns:Publish".parse().unwrap()));
+        assert!(stmt.matches_action(&"s3:GetObject".parse().unwrap()));
+    }
+
+    #[test]
+    fn dead_statements() {
+        let cases = [
+            r#"{"Effect": "Allow", "Action": [], "Resource": "*"}"#,
+            r#"{"Effect": "Allow", "NotAction": "*", "Resource": "*"}"#,
+            r#"{"Effect": "Allow", "Action": "s3:*", "Resource": []}"#,
+            r#"{"Effect": "Allow", "Action": "s3:*", "NotResource": "*"}"#,
+            r#"{"Effect": "Allow", "NotPrincipal": "*", "Action": "s3:*", "Resource": "*"}"#,
+            r#"{"Effect": "Allow", "Action": "s3:*", "Resource": "*", "Condition": {"StringEquals": {"aws:username": []}}}"#,
+            r#"{"Effect": "Allow", "Action": "s3:*", "Resource": "*", "Condition": {"StringEquals": {"aws:username": "alice"}, "StringNotEquals": {"aws:username": "alice"}}}"#,
+        ];
+        for case in cases {
+            assert!(parse(case).is_dead(), "{}", case);
+        }
+    }
+
+    #[test]
+    fn live_statements() {
+        let cases = [
+            r#"{"Effect": "Allow", "Action": "s3:*", "Resource": "*"}"#,
+            r#"{"Effect": "Allow", "NotAction": "iam:*", "Resource": "*"}"#,
+            r#"{"Effect": "Allow", "Action": "s3:*", "NotResource": "arn:aws:s3:::secret/*"}"#,
+            r#"{"Effect": "Allow", "Action": "s3:*", "Resource": "*", "Condition": {"ForAllValues:StringEquals": {"aws:TagKeys": []}}}"#,
+        ];
+        for case in cases {
+            assert!(!parse(case).is_dead(), "{}", case);
+        }
+    }
+
+    #[test]
+    fn not_resource_segment_aware() {
+        let stmt = parse(r#"{"Effect": "Allow", "Action": "s3:GetObject", "NotResource": "arn:aws:s3:::secret/*"}"#);
+        let action = "s3:GetObject".parse().unwrap();
+        let context = Context::new();
+        let check = |resource: &str| stmt.check_action(&action, &resource.parse().unwrap(), &context).unwrap();
+        assert_eq!(check("arn:aws:s3:::public/key"), CheckResult::Allow);
+        assert_eq!(check("arn:aws:s3:::secret/key"), CheckResult::Unspecified);
+        assert_eq!(check("arn:aws:s3:::secret/nested/key"), CheckResult::Unspecified);
+        // Only the bucket's objects are excluded, not the bucket or similarly named buckets
+        assert_eq!(check("arn:aws:s3:::secret"), CheckResult::Allow);
+        assert_eq!(check("arn:aws:s3:::secret-archive/key"), CheckResult::Allow);
+
+        // A wildcard in one segment does not reach into the next
+        let stmt = parse(r#"{"Effect": "Deny", "Action": "iam:*", "NotResource": "arn:aws:iam::*:role/admin"}"#);
+        let action = "iam:PassRole".parse().unwrap();
+        assert_eq!(stmt.check_action(&action, &"arn:aws:iam::123456789012:role/admin".parse().unwrap(), &context).unwrap(), CheckResult::Unspecified);
+        assert_eq!(stmt.check_action(&action, &"arn:aws:iam::123456789012:role/path/admin".parse().unwrap(), &context).unwrap(), CheckResult::Deny);
+        assert_eq!(stmt.check_action(&action, &"arn:aws:iam:us-east-1:123456789012:role/admin".parse().unwrap(), &context).unwrap(), CheckResult::Deny);
+    }
+
+    #[test]
+    fn would_match_without_conditions() {
+        let stmt = parse(r#"{
+            "Effect": "Allow",
+            "Action": "s3:GetObject",
+            "Resource": "arn:aws:s3:::bucket/*",
+            "Condition": {"Bool": {"aws:SecureTransport": "true"}}
+        }"#);
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let context = Context::new();
+        // Only the condition prevents the statement from matching
+        assert_eq!(stmt.check_action(&action, &resource, &context).unwrap(), CheckResult::Unspecified);
+        assert!(stmt.would_match_without_conditions(&action, &resource));
+
+        assert!(!stmt.would_match_without_conditions(&"s3:PutObject".parse().unwrap(), &resource));
+        assert!(!stmt.would_match_without_conditions(&action, &"arn:aws:s3:::other/key".parse().unwrap()));
+    }
+
+    #[test]
+    fn minimal_context() {
+        let stmt = parse(r#"{
+            "Effect": "Allow",
+            "Action": "s3:GetObject",
+            "Resource": "*",
+            "Condition": {
+                "StringEquals": {"aws:PrincipalTag/team": "storage"},
+                "Bool": {"aws:MultiFactorAuthPresent": "true"}
+            }
+        }"#);
+        let context = stmt.minimal_context().unwrap();
+        assert_eq!(context.globals()["aws:PrincipalTag/team"], vec!["storage"]);
+        assert_eq!(context.globals()["aws:MultiFactorAuthPresent"], vec!["true"]);
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        assert_eq!(stmt.check_action(&action, &resource, &context).unwrap(), CheckResult::Allow);
+
+        let stmt = parse(r#"{"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}"#);
+        assert!(stmt.minimal_context().unwrap().globals().is_empty());
+    }
+
+    #[test]
+    fn conditions_satisfied() {
+        let stmt = parse(r#"{
+            "Effect": "Allow",
+            "Action": "s3:GetObject",
+            "Resource": "arn:aws:s3:::bucket/*",
+            "Condition": {"Bool": {"aws:MultiFactorAuthPresent": "true"}}
+        }"#);
+        // The resource only selects resource-specific context values, it
+        // does not have to match the statement's Resource clause
+        let resource = "arn:aws:s3:::other/key".parse().unwrap();
+        let mut context = Context::new();
+        assert!(!stmt.conditions_satisfied(&resource, &context).unwrap());
+        context.insert_global("aws:MultiFactorAuthPresent", vec!["false".to_string()]);
+        assert!(!stmt.conditions_satisfied(&resource, &context).unwrap());
+        context.insert_global("aws:MultiFactorAuthPresent", vec!["true".to_string()]);
+        assert!(stmt.conditions_satisfied(&resource, &context).unwrap());
+
+        let stmt = parse(r#"{"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}"#);
+        assert!(stmt.conditions_satisfied(&resource, &Context::new()).unwrap());
+    }
+
+    #[test]
+    fn explain_partition_mismatch() {
+        let stmt = parse(r#"{"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}"#);
+        fn explain<'a>(stmt: &'a Statement, resource: &str) -> MatchOutcome<'a> {
+            stmt.explain(None, &"s3:GetObject".parse().unwrap(), &resource.parse().unwrap(), &Context::new()).unwrap()
+        }
+
+        assert!(explain(&stmt, "arn:aws:s3:::bucket/key").is_match());
+        assert_eq!(explain(&stmt, "arn:aws-cn:s3:::bucket/key"), MatchOutcome::PartitionMismatch);
+        assert_eq!(explain(&stmt, "arn:aws-us-gov:s3:::bucket/key"), MatchOutcome::PartitionMismatch);
+        assert_eq!(explain(&stmt, "arn:aws:s3:::other/key"), MatchOutcome::ResourceMismatch);
+        // Only reported when no pattern allows the partition
+        assert_eq!(explain(&stmt, "arn:aws-cn:s3:::other/key"), MatchOutcome::PartitionMismatch);
+
+        let stmt = parse(r#"{"Effect": "Allow", "Action": "s3:GetObject", "Resource": ["arn:aws:s3:::bucket/*", "arn:aws-cn:s3:::bucket/*"]}"#);
+        assert!(explain(&stmt, "arn:aws-cn:s3:::bucket/key").is_match());
+        assert_eq!(explain(&stmt, "arn:aws-cn:s3:::other/key"), MatchOutcome::ResourceMismatch);
+        assert_eq!(explain(&stmt, "arn:aws-us-gov:s3:::bucket/key"), MatchOutcome::PartitionMismatch);
+
+        let stmt = parse(r#"{"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:*:s3:::bucket/*"}"#);
+        assert!(explain(&stmt, "arn:aws-us-gov:s3:::bucket/key").is_match());
+
+        let stmt = parse(r#"{"Effect": "Allow", "Action": "s3:GetObject", "NotResource": "arn:aws:s3:::bucket/*"}"#);
+        assert_eq!(explain(&stmt, "arn:aws:s3:::bucket/key"), MatchOutcome::ResourceMismatch);
+    }
+
+    #[test]
+    fn explain_mismatches() {
+        let stmt = parse(r#"{
+            "Effect": "Deny",
+            "Principal": {"AWS": "arn:aws:iam::123456789012:root"},
+            "Action": "s3:GetObject",
+            "Resource": "*",
+            "Condition": {"Bool": {"aws:SecureTransport": "false"}}
+        }"#);
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let principal = Principal::AWS("arn:aws:iam::123456789012:user/Alice".parse().unwrap());
+        let other = Principal::AWS("arn:aws:iam::210987654321:user/Bob".parse().unwrap());
+        let mut context = Context::new();
+        assert_eq!(stmt.explain(Some(&other), &action, &resource, &context).unwrap(), MatchOutcome::PrincipalMismatch);
+        assert_eq!(stmt.explain(Some(&principal), &"s3:PutObject".parse().unwrap(), &resource, &context).unwrap(), MatchOutcome::ActionMismatch);
+        assert_eq!(stmt.explain(Some(&principal), &action, &resource, &context).unwrap(), MatchOutcome::ConditionsNotMet);
+        context.insert_global("aws:SecureTransport", vec!["false".to_string()]);
+        assert!(stmt.explain(Some(&principal), &action, &resource, &context).unwrap().is_match());
+        // Principals are ignored when none is given
+        assert!(stmt.explain(None, &action, &resource, &context).unwrap().is_match());
+    }
+
+    #[test]
+    fn explain_matched_constraints() {
+        let stmt = parse(r#"{
+            "Effect": "Allow",
+            "Action": ["ec2:*", "s3:Get*", "s3:GetObject"],
+            "Resource": ["arn:aws:s3:::other/*", "arn:aws:s3:::bucket/*", "*"]
+        }"#);
+        let (actions, resources) = match (&stmt.actions, &stmt.resources) {
+            (ActionClause::Action(actions), ResourceClause::Resource(resources)) => (actions, resources),
+            _ => panic!("expected Action and Resource clauses"),
+        };
+        fn explain<'a>(stmt: &'a Statement, action: &str, resource: &str) -> MatchOutcome<'a> {
+            stmt.explain(None, &action.parse().unwrap(), &resource.parse().unwrap(), &Context::new()).unwrap()
+        }
+
+        // The first matching pattern of each clause is reported
+        assert_eq!(
+            explain(&stmt, "s3:GetObject", "arn:aws:s3:::bucket/key"),
+            MatchOutcome::Matched{ action: Some(&actions[1]), resource: Some(&resources[1]) },
+        );
+        assert_eq!(
+            explain(&stmt, "ec2:RunInstances", "arn:aws:ec2:us-east-1:123456789012:instance/i-1"),
+            MatchOutcome::Matched{ action: Some(&actions[0]), resource: Some(&resources[2]) },
+        );
+        assert_eq!(explain(&stmt, "s3:PutObject", "arn:aws:s3:::bucket/key"), MatchOutcome::ActionMismatch);
+
+        // Not clauses match without any one pattern matching
+        let stmt = parse(r#"{"Effect": "Allow", "NotAction": "iam:*", "NotResource": "arn:aws:s3:::secret/*"}"#);
+        assert_eq!(
+            explain(&stmt, "s3:GetObject", "arn:aws:s3:::bucket/key"),
+            MatchOutcome::Matched{ action: None, resource: None },
+        );
+    }
+
+    #[test]
+    fn merge_with() {
+        let read = parse(r#"{"Sid": "Read", "Effect": "Allow", "Action": ["s3:GetObject", "s3:ListBucket"], "Resource": "arn:aws:s3:::bucket/*"}"#);
+        let write = parse(r#"{"Sid": "Write", "Effect": "Allow", "Action": ["s3:PutObject", "s3:GetObject"], "Resource": "arn:aws:s3:::bucket/*"}"#);
+        assert!(read.can_merge_with(&write));
+        let merged = read.merge_with(&write).unwrap();
+        assert_eq!(merged, parse(r#"{
+            "Sid": "Read",
+            "Effect": "Allow",
+            "Action": ["s3:GetObject", "s3:ListBucket", "s3:PutObject"],
+            "Resource": "arn:aws:s3:::bucket/*"
+        }"#));
+        // Actions that differ only in case are not repeated
+        let lowercase = parse(r#"{"Effect": "Allow", "Action": "s3:getobject", "Resource": "arn:aws:s3:::bucket/*"}"#);
+        assert_eq!(read.merge_with(&lowercase).unwrap().actions, read.actions);
+
+        let others = [
+            r#"{"Effect": "Deny", "Action": "s3:PutObject", "Resource": "arn:aws:s3:::bucket/*"}"#,
+            r#"{"Effect": "Allow", "Action": "s3:PutObject", "Resource": "arn:aws:s3:::other/*"}"#,
+            r#"{"Effect": "Allow", "Action": "s3:PutObject", "NotResource": "arn:aws:s3:::bucket/*"}"#,
+            r#"{"Effect": "Allow", "NotAction": "s3:PutObject", "Resource": "arn:aws:s3:::bucket/*"}"#,
+            r#"{"Effect": "Allow", "Principal": "*", "Action": "s3:PutObject", "Resource": "arn:aws:s3:::bucket/*"}"#,
+            r#"{"Effect": "Allow", "Action": "s3:PutObject", "Resource": "arn:aws:s3:::bucket/*", "Condition": {"Bool": {"aws:SecureTransport": "true"}}}"#,
+        ];
+        for other in others {
+            let other = parse(other);
+            assert!(!read.can_merge_with(&other), "{:?}", other);
+            assert!(read.merge_with(&other).is_none());
+        }
+
+        // Merged statements allow what either one did
+        let context = Context::new();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        for action in ["s3:GetObject", "s3:ListBucket", "s3:PutObject"] {
+            assert_eq!(merged.check_action(&action.parse().unwrap(), &resource, &context).unwrap(), CheckResult::Allow);
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let cases = [
+            (
+                r#"{"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*", "Condition": {"Bool": {"aws:SecureTransport": "true"}}}"#,
+                "Allow s3:GetObject on arn:aws:s3:::bucket/* when aws:SecureTransport is true.",
+            ),
+            (
+                r#"{"Effect": "Deny", "NotAction": ["iam:*", "sts:*"], "Resource": "*"}"#,
+                "Deny any action except iam:*, sts:* on any resource.",
+            ),
+            (
+                r#"{"Effect": "Allow", "Action": "*", "NotResource": "arn:aws:s3:::secret/*"}"#,
+                "Allow any action on any resource except arn:aws:s3:::secret/*.",
+            ),
+            (
+                r#"{"Effect": "Allow", "Principal": {"Service": "ecs.amazonaws.com"}, "Action": "sts:AssumeRole", "Resource": "*"}"#,
+                "Allow sts:AssumeRole on any resource for ecs.amazonaws.com.",
+            ),
+            (
+                r#"{"Effect": "Deny", "Principal": "*", "Action": "s3:*", "Resource": "*", "Condition": {
+                    "StringNotEquals": {"aws:SourceVpc": ["vpc-1", "vpc-2"]},
+                    "Null": {"aws:SourceVpce": "false"},
+                    "ForAllValues:StringLike": {"aws:TagKeys": "team-*"}
+                }}"#,
+                "Deny s3:* on any resource for anyone when aws:SourceVpc is not vpc-1 nor vpc-2 and aws:SourceVpce is present and every value of aws:TagKeys matches team-*.",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(parse(source).summary(), expected);
+        }
+    }
+
+    #[test]
+    fn account_principal() {
+        let account = parse(r#"{"Effect": "Allow", "Principal": {"AWS": "123456789012"}, "Action": "s3:GetObject", "Resource": "*"}"#);
+        let root = parse(r#"{"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:root"}, "Action": "s3:GetObject", "Resource": "*"}"#);
+        assert_eq!(account, root);
+
+        let action = "s3:GetObject".parse().unwrap();
+        let resource = "arn:aws:s3:::bucket/key".parse().unwrap();
+        let context = Context::new();
+        let principals = ["123456789012", "arn:aws:iam::123456789012:root", "arn:aws:iam::123456789012:user/Alice", "210987654321"];
+        for principal in principals {
+            let principal = principal.parse::<Principal>().unwrap();
+            assert_eq!(account.check(&principal, &action, &resource, &context).unwrap(), root.check(&principal, &action, &resource, &context).unwrap(), "{}", principal);
+        }
+        let principal = "123456789012".parse::<Principal>().unwrap();
+        assert_eq!(account.check(&principal, &action, &resource, &context).unwrap(), CheckResult::Allow);
+    }
+
+    #[test]
+    fn group_principal() {
+        for source in [
+            r#"{"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:group/Admins"}, "Action": "s3:GetObject", "Resource": "*"}"#,
+            r#"{"Effect": "Deny", "NotPrincipal": {"AWS": ["arn:aws:iam::123456789012:root", "arn:aws:iam::123456789012:group/path/Admins"]}, "Action": "s3:GetObject", "Resource": "*"}"#,
+        ] {
+            let err = Statement::try_from(&json::parse(source).unwrap()).unwrap_err();
+            assert!(matches!(err.downcast_ref::<PrincipalError>(), Some(PrincipalError::GroupPrincipal(_))), "{}", source);
+        }
+        parse(r#"{"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:user/group"}, "Action": "s3:GetObject", "Resource": "*"}"#);
+    }
+}
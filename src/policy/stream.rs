@@ -0,0 +1,170 @@
+use super::Policy;
+
+use std::io::{BufReader, Bytes, Read};
+
+use anyhow::anyhow;
+
+/*
+Reads a JSON array of policies one element at a time, so only the policy being
+parsed is held in memory. Elements are delimited by tracking the nesting of
+braces and brackets outside of strings, then parsed as usual.
+
+A policy that fails to parse is reported and skipped, but malformed JSON
+between policies ends the iteration, since there is no reliable way to find
+where the next policy starts.
+ */
+
+pub struct PolicyStream<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    started: bool,
+    done: bool,
+}
+
+impl<R: Read> PolicyStream<R> {
+    pub fn new(reader: R) -> Self {
+        PolicyStream{ bytes: BufReader::new(reader).bytes(), started: false, done: false }
+    }
+
+    fn next_byte(&mut self) -> anyhow::Result<Option<u8>> {
+        self.bytes.next().transpose().map_err(|err| anyhow!("unable to read policies: {}", err))
+    }
+
+    fn next_non_whitespace(&mut self) -> anyhow::Result<Option<u8>> {
+        while let Some(byte) = self.next_byte()? {
+            if !byte.is_ascii_whitespace() {
+                return Ok(Some(byte));
+            }
+        }
+        Ok(None)
+    }
+
+    // Reads the rest of an object whose opening brace was already read.
+    fn read_object(&mut self) -> anyhow::Result<String> {
+        let mut buffer = vec![b'{'];
+        let (mut depth, mut in_string, mut escaped) = (1, false, false);
+        while depth > 0 {
+            let byte = self.next_byte()?.ok_or_else(|| anyhow!("unexpected end of input in policy"))?;
+            buffer.push(byte);
+            match (in_string, byte) {
+                (true, _) if escaped => escaped = false,
+                (true, b'\\') => escaped = true,
+                (true, b'"') | (false, b'"') => in_string = !in_string,
+                (false, b'{') | (false, b'[') => depth += 1,
+                (false, b'}') | (false, b']') => depth -= 1,
+                _ => {}
+            }
+        }
+        String::from_utf8(buffer).map_err(|_| anyhow!("expected policy to be valid UTF-8"))
+    }
+
+    // Returns the next policy's source, or None at the end of the array.
+    fn next_source(&mut self) -> anyhow::Result<Option<String>> {
+        let mut byte = self.next_non_whitespace()?;
+        if !self.started {
+            self.started = true;
+            // Skip a UTF-8 byte order mark
+            if byte == Some(0xef) {
+                for expected in [0xbb, 0xbf] {
+                    if self.next_byte()? != Some(expected) {
+                        return Err(anyhow!("expected an array of policies"));
+                    }
+                }
+                byte = self.next_non_whitespace()?;
+            }
+            if byte != Some(b'[') {
+                return Err(anyhow!("expected an array of policies"));
+            }
+            return match self.next_non_whitespace()? {
+                Some(b']') => Ok(None),
+                Some(b'{') => self.read_object().map(Some),
+                _ => Err(anyhow!("expected each policy to be an object")),
+            };
+        }
+        match byte {
+            Some(b']') => Ok(None),
+            Some(b',') => match self.next_non_whitespace()? {
+                Some(b'{') => self.read_object().map(Some),
+                _ => Err(anyhow!("expected each policy to be an object")),
+            },
+            Some(_) => Err(anyhow!("expected ',' or ']' after policy")),
+            None => Err(anyhow!("unexpected end of input in array of policies")),
+        }
+    }
+}
+
+impl<R: Read> Iterator for PolicyStream<R> {
+    type Item = anyhow::Result<Policy>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_source() {
+            Ok(Some(source)) => Some(source.parse()),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::policy::Policy;
+
+    fn write_temp(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("awspolicy-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).expect("failed to write temporary file");
+        path
+    }
+
+    #[test]
+    fn parse_file() {
+        let path = write_temp("policies.json", r#"[
+            {"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/{braces}/*"}},
+            {"Version": "2012-10-17", "Statement": {"Sid": "Quote\"}]", "Effect": "Deny", "Action": "s3:*", "Resource": "*"}},
+            {"Version": "1999-01-01", "Statement": []},
+            {"Version": "2012-10-17", "Statement": [{"Effect": "Allow", "Action": ["ec2:*"], "Resource": "*"}]}
+        ]"#);
+        let results: Vec<anyhow::Result<Policy>> = Policy::parse_many(std::fs::File::open(&path).unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(results.len(), 4);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.statements[0].sid, None);
+        let second = results[1].as_ref().unwrap();
+        assert_eq!(second.statements[0].sid.as_deref(), Some("Quote\"}]"));
+        // An invalid policy is reported without ending the stream
+        assert!(results[2].is_err());
+        assert_eq!(results[3].as_ref().unwrap().statements.len(), 1);
+    }
+
+    #[test]
+    fn empty_and_malformed() {
+        let parse = |source: &str| Policy::parse_many(source.as_bytes()).collect::<Vec<_>>();
+        assert!(parse(" [ ] ").is_empty());
+        assert!(parse("\u{feff}[]").is_empty());
+
+        let results = parse("{}");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+
+        // Malformed JSON between policies ends the stream
+        let results = parse(r#"[{"Statement": []} {"Statement": []}]"#);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+
+        let results = parse(r#"[{"Statement": []}, {"Statement": ["#);
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+
+        let results = parse(r#"[{"Statement": []}, "policy"]"#);
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::aws::GlobPart;
+use super::context::ResourceContext;
+
+/*
+Policy variables are placeholders, e.g. ${aws:username}, that are replaced
+with values from the request context when a policy is evaluated. They can be
+used in the Resource and NotResource elements and in condition values, but not
+in Principal, and only in policies with Version 2012-10-17.
+
+A variable can give a default for when the key is missing, e.g.
+${aws:username, 'anonymous'}. Without a default, a missing key means the
+statement does not match. The special variables ${*}, ${?}, and ${$} stand for
+those characters, matched literally. Substituted values are also matched
+literally, so a '*' in a username is not a wildcard.
+See https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_variables.html
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VariableError {
+    // The key is missing from the context and the variable has no default.
+    Missing(String),
+    // The key has more than one value, so there is no single value to
+    // substitute.
+    MultiValued(String),
+    // A "${" without a closing "}".
+    Unterminated(String),
+}
+
+impl std::fmt::Display for VariableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(key) => write!(f, "no value for policy variable {}", key),
+            Self::MultiValued(key) => write!(f, "policy variable {} has more than one value", key),
+            Self::Unterminated(template) => write!(f, "unterminated policy variable in {}", template),
+        }
+    }
+}
+
+impl std::error::Error for VariableError {}
+
+pub fn has_variables(template: &str) -> bool {
+    template.contains("${")
+}
+
+// Replaces each variable in the template with its value. The text around
+// the variables keeps its wildcards, while the values are literal.
+pub fn substitute(template: &str, values: &ResourceContext) -> Result<Vec<GlobPart>, VariableError> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..].find('}')
+            .ok_or_else(|| VariableError::Unterminated(template.to_string()))? + start;
+        if start > 0 {
+            parts.push(GlobPart::Glob(rest[..start].to_string()));
+        }
+        parts.push(GlobPart::Literal(resolve(&rest[start + 2 .. end], values)?));
+        rest = &rest[end + 1 ..];
+    }
+    if !rest.is_empty() {
+        parts.push(GlobPart::Glob(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+fn resolve(variable: &str, values: &ResourceContext) -> Result<String, VariableError> {
+    let variable = variable.trim();
+    if matches!(variable, "*" | "?" | "$") {
+        return Ok(variable.to_string());
+    }
+    let (key, default) = match variable.split_once(',') {
+        Some((key, default)) => {
+            let default = default.trim();
+            let unquoted = default.strip_prefix('\'').and_then(|default| default.strip_suffix('\''));
+            (key.trim(), Some(unquoted.unwrap_or(default)))
+        },
+        None => (variable, None),
+    };
+    match values.get(key).map(Vec::as_slice) {
+        Some([value]) => Ok(value.clone()),
+        Some([_, _, ..]) => Err(VariableError::MultiValued(key.to_string())),
+        _ => default.map(String::from).ok_or_else(|| VariableError::Missing(key.to_string())),
+    }
+}
+
+// The text of the parts, without distinguishing literal ones.
+pub fn to_text(parts: &[GlobPart]) -> String {
+    parts.iter().map(|part| match part {
+        GlobPart::Glob(text) | GlobPart::Literal(text) => text.as_str(),
+    }).collect()
+}
+
+// Splits the parts at the separator, which is only recognized outside of
+// literal parts, into at most limit segments.
+pub fn split_parts(parts: Vec<GlobPart>, separator: char, limit: usize) -> Vec<Vec<GlobPart>> {
+    let mut segments = vec![Vec::new()];
+    for part in parts {
+        let glob = match part {
+            GlobPart::Glob(glob) => glob,
+            literal => {
+                segments.last_mut().unwrap().push(literal);
+                continue;
+            },
+        };
+        let mut pieces = glob.splitn(limit + 1 - segments.len(), separator);
+        if let Some(first) = pieces.next().filter(|first| !first.is_empty()) {
+            segments.last_mut().unwrap().push(GlobPart::Glob(first.to_string()));
+        }
+        for piece in pieces {
+            let mut segment = Vec::new();
+            if !piece.is_empty() {
+                segment.push(GlobPart::Glob(piece.to_string()));
+            }
+            segments.push(segment);
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_parts, substitute, to_text, VariableError};
+    use crate::aws::GlobPart;
+    use crate::policy::context::ResourceContext;
+
+    fn values() -> ResourceContext {
+        ResourceContext::from([
+            ("aws:username".to_string(), vec!["alice".to_string()]),
+            ("aws:TagKeys".to_string(), vec!["Env".to_string(), "Owner".to_string()]),
+            ("aws:PrincipalTag/team".to_string(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn substitute_variables() {
+        let values = values();
+        let glob = |text: &str| GlobPart::Glob(text.to_string());
+        let literal = |text: &str| GlobPart::Literal(text.to_string());
+        assert_eq!(substitute("home/${aws:username}/*", &values), Ok(vec![glob("home/"), literal("alice"), glob("/*")]));
+        assert_eq!(substitute("no variables", &values), Ok(vec![glob("no variables")]));
+        assert_eq!(to_text(&substitute("${ aws:username }${aws:username}", &values).unwrap()), "alicealice");
+        assert_eq!(substitute("${*}${?}${$}", &values), Ok(vec![literal("*"), literal("?"), literal("$")]));
+
+        // Defaults apply when the key is missing or has no values
+        assert_eq!(to_text(&substitute("${aws:userid, 'anonymous'}", &values).unwrap()), "anonymous");
+        assert_eq!(to_text(&substitute("${aws:PrincipalTag/team,'none'}", &values).unwrap()), "none");
+        assert_eq!(to_text(&substitute("${aws:username, 'anonymous'}", &values).unwrap()), "alice");
+
+        assert_eq!(substitute("${aws:userid}", &values), Err(VariableError::Missing("aws:userid".to_string())));
+        assert_eq!(substitute("${aws:TagKeys}", &values), Err(VariableError::MultiValued("aws:TagKeys".to_string())));
+        assert!(matches!(substitute("home/${aws:username", &values), Err(VariableError::Unterminated(_))));
+    }
+
+    #[test]
+    fn split_outside_literals() {
+        let values = ResourceContext::from([("aws:userid".to_string(), vec!["AROAEXAMPLE:alice".to_string()])]);
+        let parts = substitute("arn:aws:iam::${aws:userid}:role/a:b", &values).unwrap();
+        let segments: Vec<String> = split_parts(parts, ':', 6).iter().map(|segment| to_text(segment)).collect();
+        assert_eq!(segments, vec!["arn", "aws", "iam", "", "AROAEXAMPLE:alice", "role/a:b"]);
+    }
+}
@@ -0,0 +1,216 @@
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_awspolicy"))
+        .args(args)
+        .output()
+        .expect("failed to run awspolicy")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+const MINIMAL_POLICY: &str = r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}}"#;
+
+#[test]
+fn inline_policy_json() {
+    let output = run(&["--policy-json", MINIMAL_POLICY]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Policy successfully parsed"));
+
+    let output = run(&["--policy-json", MINIMAL_POLICY, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Allow"));
+}
+
+#[test]
+fn policy_source_required() {
+    let output = run(&[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn policy_sources_exclusive() {
+    let output = run(&["--policy", "testdata/test_global_unconditional.json", "--policy-json", MINIMAL_POLICY]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn invalid_policy_fails() {
+    let output = run(&["--policy-json", "{"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Policy parse error"), "{}", stderr(&output));
+    assert!(stdout(&output).is_empty(), "{}", stdout(&output));
+}
+
+const CONDITIONAL_POLICY: &str = r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*", "Condition": {"Bool": {"aws:SecureTransport": "true"}}}}"#;
+
+#[test]
+fn conditions_without_context_warns() {
+    let output = run(&["--policy-json", CONDITIONAL_POLICY, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("no context was provided"));
+
+    let output = run(&["--policy-json", MINIMAL_POLICY, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(!stdout(&output).contains("no context was provided"));
+}
+
+#[test]
+fn resource_policy_without_principal_warns() {
+    let policy = r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "*"}}"#;
+    let output = run(&["--policy-json", policy, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(stdout(&output).contains("Principal clauses but no principal was provided"));
+
+    let output = run(&["--policy-json", policy, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key", "--principal-service", "ecs.amazonaws.com"]);
+    assert!(!stdout(&output).contains("Principal clauses but no principal was provided"));
+}
+
+fn write_temp(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("awspolicy-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).expect("failed to write temporary file");
+    path
+}
+
+#[test]
+fn merged_contexts() {
+    let policy = r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*", "Condition": {"StringEquals": {"aws:username": "alice"}, "Bool": {"aws:SecureTransport": "true"}}}}"#;
+    let base = write_temp("base.json", r#"{"global": {"aws:username": "bob", "aws:SecureTransport": "true"}}"#);
+    let request = write_temp("request.json", r#"{"global": {"aws:username": "alice"}}"#);
+    let (base, request) = (base.to_str().unwrap(), request.to_str().unwrap());
+    let check = ["--policy-json", policy, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"];
+
+    let output = run(&[&check[..], &["--context", base, "--context", request]].concat());
+    assert!(stdout(&output).contains(": Allow"), "{}", stdout(&output));
+
+    let output = run(&[&check[..], &["--context", request, "--context", base]].concat());
+    assert!(stdout(&output).contains(": Unspecified"), "{}", stdout(&output));
+}
+
+#[test]
+fn format_pretty() {
+    let output = run(&["--policy-json", MINIMAL_POLICY, "--format", "pretty"]);
+    assert!(output.status.success());
+    let pretty = stdout(&output);
+    assert!(pretty.contains("\n    \"Statement\": [\n"));
+    let output = run(&["--policy-json", pretty.trim()]);
+    assert!(stdout(&output).contains("Policy successfully parsed"));
+}
+
+#[test]
+fn only_sid() {
+    let policy = r#"{"Version": "2012-10-17", "Statement": [
+        {"Sid": "AllowRead", "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+        {"Sid": "DenyAll", "Effect": "Deny", "Action": "s3:*", "Resource": "arn:aws:s3:::bucket/*"}
+    ]}"#;
+    let args = ["--policy-json", policy, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"];
+    let output = run(&args);
+    assert!(stdout(&output).contains("Deny"));
+
+    let output = run(&[&args[..], &["--only-sid", "AllowRead"]].concat());
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Allow"));
+
+    let output = run(&[&args[..], &["--only-sid", "Missing"]].concat());
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no statement with Sid Missing"));
+}
+
+#[test]
+fn explicit_and_implicit_deny() {
+    let policy = r#"{"Version": "2012-10-17", "Statement": [
+        {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"},
+        {"Effect": "Deny", "Action": "s3:*", "Resource": "arn:aws:s3:::secret/*"}
+    ]}"#;
+    let output = run(&["--policy-json", policy, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::secret/key"]);
+    assert!(stdout(&output).contains("explicitly denied by a statement"));
+
+    let output = run(&["--policy-json", policy, "--action", "s3:PutObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(stdout(&output).contains("implicitly denied because no statement allows it"));
+}
+
+#[test]
+fn action_case_insensitive() {
+    let output = run(&["--policy-json", MINIMAL_POLICY, "--action", "s3:getobject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains(": Allow"), "{}", stdout(&output));
+
+    let output = run(&["--policy-json", MINIMAL_POLICY, "--action", "S3:GETOBJECTACL", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(stdout(&output).contains(": Unspecified"), "{}", stdout(&output));
+}
+
+#[test]
+fn principal_bare_account() {
+    let policy = r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Principal": {"AWS": "123456789012"}, "Action": "s3:GetObject", "Resource": "*"}}"#;
+    for principal in ["123456789012", "arn:aws:iam::123456789012:root"] {
+        let output = run(&["--policy-json", policy, "--principal-aws", principal, "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"]);
+        assert!(stdout(&output).contains(": Allow"), "{}: {}", principal, stdout(&output));
+    }
+
+    let output = run(&["--policy-json", policy, "--principal-aws", "ec2.amazonaws.com", "--action", "s3:GetObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Argument error: InvalidPrincipal"), "{}", stderr(&output));
+}
+
+#[test]
+fn principal_action_matrix() {
+    let policy = r#"{"Version": "2012-10-17", "Statement": [
+        {"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:root"}, "Action": "s3:*", "Resource": "*"},
+        {"Effect": "Deny", "Principal": {"AWS": "arn:aws:iam::123456789012:user/Bob"}, "Action": "s3:PutObject", "Resource": "*"}
+    ]}"#;
+    let args = [
+        "--policy-json", policy, "--resource", "arn:aws:s3:::bucket/key",
+        "--principal-aws", "arn:aws:iam::123456789012:user/Alice",
+        "--principal-aws", "arn:aws:iam::123456789012:user/Bob",
+        "--action", "s3:GetObject",
+        "--action", "s3:PutObject",
+    ];
+    let output = run(&[&args[..], &["--format", "csv"]].concat());
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "\
+Principal,s3:GetObject,s3:PutObject
+AWS: arn:aws:iam::123456789012:user/Alice,Allow,Allow
+AWS: arn:aws:iam::123456789012:user/Bob,Allow,Deny
+");
+
+    let output = run(&args);
+    let text = stdout(&output);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", text);
+    assert!(lines[0].starts_with("Principal ") && lines[0].ends_with("s3:GetObject  s3:PutObject"), "{}", text);
+    assert!(lines[2].starts_with("AWS: arn:aws:iam::123456789012:user/Bob  ") && lines[2].ends_with("Allow         Deny"), "{}", text);
+
+    // Other principal kinds cannot be combined with AWS principals
+    let output = run(&[&args[..], &["--principal-anonymous"]].concat());
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Argument error: MultiplePrincipalsSpecified"));
+}
+
+#[test]
+fn bare_action() {
+    let output = run(&["--policy-json", MINIMAL_POLICY, "--action", "GetObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    assert!(!output.status.success());
+    let text = stderr(&output);
+    assert!(text.contains("Argument error: InvalidAction"), "{}", text);
+    assert!(text.contains("Actions must be written as service:Action, e.g. s3:GetObject"), "{}", text);
+    assert!(text.contains("Did you mean s3:GetObject?"), "{}", text);
+
+    // No suggestion when the policy has no action by that name
+    let output = run(&["--policy-json", MINIMAL_POLICY, "--action", "PutObject", "--resource", "arn:aws:s3:::bucket/key"]);
+    let text = stderr(&output);
+    assert!(text.contains("Actions must be written as service:Action"), "{}", text);
+    assert!(!text.contains("Did you mean"), "{}", text);
+}
+
+#[test]
+fn malformed_resource() {
+    for resource in ["arn:::::", "arn:aws:s3:::", "bucket/key"] {
+        let output = run(&["--policy-json", MINIMAL_POLICY, "--action", "s3:GetObject", "--resource", resource]);
+        assert!(!output.status.success());
+        assert!(stderr(&output).contains("Argument error: InvalidResource"), "{}: {}", resource, stderr(&output));
+    }
+}